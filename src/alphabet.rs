@@ -0,0 +1,178 @@
+use crate::bnf::{Grammer, IntoKind, Symbol};
+use std::fmt::Debug;
+
+/// 文法に現れる終端記号と非終端記号の並び.
+///
+/// 並びは正準オートマトンの状態番号や LaTeX の表の列の順番になる.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Alphabet<NT, T> {
+    terms: Vec<T>,
+    nonterms: Vec<NT>,
+}
+
+/// 指定した並びが文法の記号と合わない.
+#[derive(Debug, PartialEq, Eq)]
+pub struct AlphabetMismatch<NT, T>
+where
+    NT: Ord + Eq + Clone,
+    T: Ord + Eq + Clone,
+{
+    /// 文法に現れるが並びにない記号
+    pub missing: Vec<Symbol<NT, T>>,
+    /// 並びにあるが文法に現れない記号, または2回以上現れる記号
+    pub unknown: Vec<Symbol<NT, T>>,
+}
+
+impl<NT, T> Alphabet<NT, T>
+where
+    NT: Ord + Eq + Clone + Debug,
+    T: Ord + Eq + Clone + Debug,
+{
+    /// 文法の規則に現れる順に記号を並べる.
+    pub fn from_grammer<NTV, TV>(grammer: &Grammer<NT, T, NTV, TV>) -> Self
+    where
+        NTV: IntoKind<NT>,
+        TV: IntoKind<T>,
+    {
+        let mut alphabet = Alphabet {
+            terms: vec![],
+            nonterms: vec![],
+        };
+        for rule in &grammer.rules {
+            alphabet.push(&Symbol::NonTerm(rule.left.clone()));
+            for symbol in &rule.right {
+                alphabet.push(symbol);
+            }
+        }
+        alphabet
+    }
+
+    /// 記号の並びを指定する. 文法の記号と過不足があればエラー.
+    ///
+    /// 並びにない終端記号 extra (入力の終わりを表す記号など) は文法になくてもよい.
+    pub fn ordered<NTV, TV>(
+        grammer: &Grammer<NT, T, NTV, TV>,
+        terms: &[T],
+        nonterms: &[NT],
+        extra: &[T],
+    ) -> Result<Self, AlphabetMismatch<NT, T>>
+    where
+        NTV: IntoKind<NT>,
+        TV: IntoKind<T>,
+    {
+        let derived = Self::from_grammer(grammer);
+        let ordered = Alphabet {
+            terms: terms.to_vec(),
+            nonterms: nonterms.to_vec(),
+        };
+        let missing: Vec<_> = derived
+            .symbols()
+            .into_iter()
+            .filter(|symbol| !ordered.contains(symbol))
+            .collect();
+        let mut unknown = vec![];
+        for (i, symbol) in ordered.symbols().into_iter().enumerate() {
+            let known = match &symbol {
+                Symbol::Term(t) => extra.contains(t),
+                Symbol::NonTerm(_) => false,
+            } || derived.contains(&symbol);
+            let duplicated = ordered.symbols()[..i].contains(&symbol);
+            if !known || duplicated {
+                unknown.push(symbol);
+            }
+        }
+        if missing.is_empty() && unknown.is_empty() {
+            Ok(ordered)
+        } else {
+            Err(AlphabetMismatch { missing, unknown })
+        }
+    }
+
+    fn push(&mut self, symbol: &Symbol<NT, T>) {
+        if !self.contains(symbol) {
+            match symbol {
+                Symbol::Term(t) => self.terms.push(t.clone()),
+                Symbol::NonTerm(nt) => self.nonterms.push(nt.clone()),
+            }
+        }
+    }
+
+    pub fn contains(&self, symbol: &Symbol<NT, T>) -> bool {
+        match symbol {
+            Symbol::Term(t) => self.terms.contains(t),
+            Symbol::NonTerm(nt) => self.nonterms.contains(nt),
+        }
+    }
+
+    pub fn terms(&self) -> &[T] {
+        &self.terms
+    }
+
+    pub fn nonterms(&self) -> &[NT] {
+        &self.nonterms
+    }
+
+    /// 非終端記号, 終端記号の順に並べたすべての記号
+    pub fn symbols(&self) -> Vec<Symbol<NT, T>> {
+        self.nonterms
+            .iter()
+            .cloned()
+            .map(Symbol::NonTerm)
+            .chain(self.terms.iter().cloned().map(Symbol::Term))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Alphabet, AlphabetMismatch};
+    use crate::bnf::{Expr, Grammer, Symbol};
+
+    #[derive(Debug, PartialOrd, Ord, Clone, PartialEq, Eq)]
+    enum NT {
+        E,
+        P,
+        Q,
+    }
+
+    #[test]
+    fn test_alphabet() {
+        // E -> E + P | P , P -> 1
+        let grammer: Grammer<NT, char, NT, char> = Grammer {
+            rules: vec![
+                Expr {
+                    left: NT::E,
+                    right: vec![
+                        Symbol::NonTerm(NT::E),
+                        Symbol::Term('+'),
+                        Symbol::NonTerm(NT::P),
+                    ],
+                    reduce_action: None,
+                },
+                Expr {
+                    left: NT::E,
+                    right: vec![Symbol::NonTerm(NT::P)],
+                    reduce_action: None,
+                },
+                Expr {
+                    left: NT::P,
+                    right: vec![Symbol::Term('1')],
+                    reduce_action: None,
+                },
+            ],
+        };
+        let alphabet = Alphabet::from_grammer(&grammer);
+        assert_eq!(alphabet.terms(), &['+', '1']);
+        assert_eq!(alphabet.nonterms(), &[NT::E, NT::P]);
+
+        let ordered = Alphabet::ordered(&grammer, &['1', '+', '$'], &[NT::P, NT::E], &['$']);
+        assert_eq!(ordered.unwrap().terms(), &['1', '+', '$']);
+        assert_eq!(
+            Alphabet::ordered(&grammer, &['1', '*', '1'], &[NT::E, NT::Q], &['$']),
+            Err(AlphabetMismatch {
+                missing: vec![Symbol::NonTerm(NT::P), Symbol::Term('+')],
+                unknown: vec![Symbol::NonTerm(NT::Q), Symbol::Term('*'), Symbol::Term('1')],
+            })
+        );
+    }
+}
@@ -1,8 +1,33 @@
 use std::fmt::Debug;
 
 use crate::parsing_table::ValueStackSymbol;
-pub type ReduceArgs<NTV, TV> = Vec<ValueStackSymbol<NTV, TV>>;
-pub type ReduceAction<NTV, TV> = Box<dyn Fn(ReduceArgs<NTV, TV>) -> NTV>;
+pub type ReduceArgs<NT, NTV, TV> = Vec<ValueStackSymbol<NT, NTV, TV>>;
+pub type ReduceAction<NT, NTV, TV> = Box<dyn ReduceFn<NT, NTV, TV>>;
+
+/// 還元動作. 右辺の記号の値から左辺の値を作る.
+///
+/// `Fn(ReduceArgs) -> NTV` のクロージャはそのまま還元動作になる.
+pub trait ReduceFn<NT, NTV, TV>: Send + Sync {
+    /// 引数を受け取れなければ None.
+    fn call(&self, args: ReduceArgs<NT, NTV, TV>) -> Option<NTV>;
+    /// 具象構文木として受け取る右辺の位置. 文法から表を作るときに調べる.
+    fn tree_arguments(&self) -> Vec<usize> {
+        vec![]
+    }
+    /// 還元動作の値として受け取る右辺の位置. 文法から表を作るときに調べる.
+    fn value_arguments(&self) -> Vec<usize> {
+        vec![]
+    }
+}
+
+impl<F, NT, NTV, TV> ReduceFn<NT, NTV, TV> for F
+where
+    F: Fn(ReduceArgs<NT, NTV, TV>) -> NTV + Send + Sync,
+{
+    fn call(&self, args: ReduceArgs<NT, NTV, TV>) -> Option<NTV> {
+        Some(self(args))
+    }
+}
 
 pub struct Grammer<NT, T, NTV, TV>
 where
@@ -22,7 +47,7 @@ where
 {
     pub left: NT,
     pub right: Vec<Symbol<NT, T>>,
-    pub reduce_action: Option<ReduceAction<NTV, TV>>,
+    pub reduce_action: Option<ReduceAction<NT, NTV, TV>>,
 }
 
 #[derive(Clone, PartialEq, PartialOrd, Ord, Eq)]
@@ -47,10 +72,30 @@ where
     }
 }
 
+/// 文法から作った非終端記号. 元の記号か,元の記号から新しく作った記号.
+///
+/// 拡大文法の開始記号 S' や,文法の変換で作る記号に使う.
+#[derive(Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Derived<NT> {
+    Original(NT),
+    /// 元の記号 NT から作った n 番目の記号. `E'` , `E''` のように表示する.
+    Fresh(NT, usize),
+}
+
+impl<NT: Debug> Debug for Derived<NT> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Derived::Original(nt) => write!(f, "{:?}", nt),
+            Derived::Fresh(nt, n) => write!(f, "{:?}{}", nt, "'".repeat(*n)),
+        }
+    }
+}
+
 pub trait EOFSupply<T> {
     fn eof() -> T;
 }
 
+#[allow(clippy::wrong_self_convention)]
 pub trait IntoKind<T> {
     fn into_kind(&self) -> T;
 }
@@ -63,3 +108,36 @@ where
         self.clone()
     }
 }
+
+#[cfg(test)]
+pub(crate) mod test {
+    use super::EOFSupply;
+    use std::fmt::{Debug, Display};
+
+    /// テストで字句に使う文字. '$' を終端記号とする.
+    #[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+    pub(crate) struct Char(pub char);
+
+    impl Debug for Char {
+        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            write!(f, "{:?}", self.0)
+        }
+    }
+
+    impl Display for Char {
+        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            write!(f, "{}", self.0)
+        }
+    }
+
+    impl EOFSupply<Char> for Char {
+        fn eof() -> Char {
+            Char('$')
+        }
+    }
+
+    /// 文字列を字句の列にする.
+    pub(crate) fn chars(s: &str) -> impl Iterator<Item = Char> + '_ {
+        s.chars().map(Char)
+    }
+}
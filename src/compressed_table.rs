@@ -0,0 +1,281 @@
+use crate::{
+    bnf::IntoKind,
+    parsing_table::{ActionKind, ParseTable},
+};
+use std::{collections::BTreeMap, fmt::Debug};
+
+/// 行を1本の配列に詰めたもの (comb vector).
+///
+/// 状態 s の列 c の値は entries[base[s] + c] にあり,その持ち主が s のときだけ有効.
+struct CombVector<V> {
+    base: Vec<usize>,
+    entries: Vec<Option<(usize, V)>>,
+}
+
+impl<V: Copy> CombVector<V> {
+    /// 値の多い行から順に,ほかの行と重ならない一番左の位置に置く.
+    fn pack(rows: Vec<Vec<(usize, V)>>) -> Self {
+        let mut order: Vec<usize> = (0..rows.len()).collect();
+        order.sort_by_key(|state| std::cmp::Reverse(rows[*state].len()));
+        let mut base = vec![0; rows.len()];
+        let mut entries: Vec<Option<(usize, V)>> = vec![];
+        for state in order {
+            let row = &rows[state];
+            let fits = |offset: usize| {
+                row.iter().all(|(column, _)| {
+                    entries
+                        .get(offset + column)
+                        .is_none_or(|entry| entry.is_none())
+                })
+            };
+            let offset = (0..).find(|offset| fits(*offset)).unwrap();
+            for (column, value) in row {
+                if entries.len() <= offset + column {
+                    entries.resize(offset + column + 1, None);
+                }
+                entries[offset + column] = Some((state, *value));
+            }
+            base[state] = offset;
+        }
+        Self { base, entries }
+    }
+
+    fn get(&self, state: usize, column: usize) -> Option<&V> {
+        let base = self.base.get(state)?;
+        match self.entries.get(base + column) {
+            Some(Some((owner, value))) if *owner == state => Some(value),
+            _ => None,
+        }
+    }
+}
+
+/// 圧縮した構文解析表.
+///
+/// 次の3つで `ParseTable` の動作表と遷移表を小さくする.
+/// * 列がまったく同じ終端記号は同じ番号 (同値類) にまとめる.
+/// * 空欄のない行では一番多い還元を既定の還元にして,表からは除く.
+/// * 残りの行は comb vector に詰める.
+///
+/// `start_state`,`action`,`goto` は `ParseTable` と同じ値を返す.
+pub struct CompressedTable<NT, T>
+where
+    NT: Debug + Clone + Eq + Ord,
+    T: Debug + Clone + Eq + Ord,
+{
+    term_classes: BTreeMap<T, usize>,
+    nonterm_index: BTreeMap<NT, usize>,
+    default_reductions: Vec<Option<ActionKind>>,
+    actions: CombVector<ActionKind>,
+    gotos: CombVector<usize>,
+    start_state: usize,
+}
+
+impl<NT, T> CompressedTable<NT, T>
+where
+    NT: Debug + Clone + Eq + Ord,
+    T: Debug + Clone + Eq + Ord,
+{
+    pub fn new<NTV, TV>(table: &ParseTable<NT, T, NTV, TV>) -> Self
+    where
+        NTV: IntoKind<NT>,
+        TV: IntoKind<T>,
+    {
+        let states = table
+            .action_entries()
+            .flat_map(|((state, _), action)| match action {
+                ActionKind::Shift(to) => vec![*state, *to],
+                _ => vec![*state],
+            })
+            .chain(
+                table
+                    .goto_entries()
+                    .flat_map(|((state, _), to)| vec![*state, *to]),
+            )
+            .chain(std::iter::once(table.start_state()))
+            .max()
+            .unwrap()
+            + 1;
+
+        //終端記号の列. 状態ごとの動作を並べたもの.
+        let mut columns: BTreeMap<T, Vec<Option<ActionKind>>> = BTreeMap::new();
+        for ((state, t), action) in table.action_entries() {
+            columns
+                .entry(t.clone())
+                .or_insert_with(|| vec![None; states])[*state] = Some(*action);
+        }
+        //同じ列には同じ番号をつける.
+        let mut classes: Vec<&Vec<Option<ActionKind>>> = vec![];
+        let mut term_classes = BTreeMap::new();
+        for (t, column) in &columns {
+            let class = match classes.iter().position(|other| *other == column) {
+                Some(class) => class,
+                None => {
+                    classes.push(column);
+                    classes.len() - 1
+                }
+            };
+            term_classes.insert(t.clone(), class);
+        }
+
+        let mut default_reductions = vec![None; states];
+        let mut action_rows = vec![];
+        for (state, default_reduction) in default_reductions.iter_mut().enumerate() {
+            let row: Vec<(usize, ActionKind)> = classes
+                .iter()
+                .enumerate()
+                .filter_map(|(class, column)| column[state].map(|action| (class, action)))
+                .collect();
+            //空欄があると既定の還元で表が変わってしまう.
+            if row.len() == classes.len() {
+                let mut counts: BTreeMap<usize, usize> = BTreeMap::new();
+                for (_, action) in &row {
+                    if let ActionKind::Reduce(rule) = action {
+                        *counts.entry(*rule).or_default() += 1;
+                    }
+                }
+                *default_reduction = counts
+                    .into_iter()
+                    .max_by_key(|(_, count)| *count)
+                    .map(|(rule, _)| ActionKind::Reduce(rule));
+            }
+            action_rows.push(
+                row.into_iter()
+                    .filter(|(_, action)| Some(*action) != *default_reduction)
+                    .collect(),
+            );
+        }
+
+        let nonterm_index: BTreeMap<NT, usize> = table
+            .goto_entries()
+            .map(|((_, nt), _)| nt.clone())
+            .collect::<std::collections::BTreeSet<_>>()
+            .into_iter()
+            .enumerate()
+            .map(|(index, nt)| (nt, index))
+            .collect();
+        let mut goto_rows = vec![vec![]; states];
+        for ((state, nt), to) in table.goto_entries() {
+            goto_rows[*state].push((nonterm_index[nt], *to));
+        }
+
+        Self {
+            term_classes,
+            nonterm_index,
+            default_reductions,
+            actions: CombVector::pack(action_rows),
+            gotos: CombVector::pack(goto_rows),
+            start_state: table.start_state(),
+        }
+    }
+
+    /// 開始状態
+    pub fn start_state(&self) -> usize {
+        self.start_state
+    }
+
+    /// 状態 state で先読み t に対する動作
+    pub fn action(&self, state: usize, t: &T) -> Option<&ActionKind> {
+        let class = *self.term_classes.get(t)?;
+        self.actions
+            .get(state, class)
+            .or_else(|| self.default_reductions.get(state)?.as_ref())
+    }
+
+    /// 状態 state から非終端記号 nt で遷移する先の状態
+    pub fn goto(&self, state: usize, nt: &NT) -> Option<usize> {
+        let index = *self.nonterm_index.get(nt)?;
+        self.gotos.get(state, index).copied()
+    }
+
+    /// 終端記号の同値類の数
+    pub fn term_classes(&self) -> usize {
+        self.term_classes
+            .values()
+            .max()
+            .map_or(0, |class| class + 1)
+    }
+
+    /// 動作と遷移の配列の長さの合計. 圧縮した表の大きさの目安.
+    pub fn len(&self) -> usize {
+        self.actions.entries.len() + self.gotos.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::CompressedTable;
+    use crate::bnf::test::Char;
+    use crate::bnf::{Expr, Grammer, Symbol};
+    use crate::item_set::generate_canonical_automaton;
+    use crate::parsing_table::canonical_automaton_to_lr0_parser_with_resolution;
+    use crate::resolution::Resolution;
+
+    #[derive(Ord, PartialOrd, PartialEq, Eq, Clone, Debug)]
+    enum NT {
+        E,
+        T,
+        F,
+    }
+
+    #[test]
+    fn test_compressed_table() {
+        let rule = |left: NT, right: Vec<Symbol<NT, Char>>| Expr {
+            left,
+            right,
+            reduce_action: None,
+        };
+        use Symbol::{NonTerm as N, Term as Tm};
+        // E -> E + T | T , T -> T * F | F , F -> ( E ) | 1 | x
+        let grammer: Grammer<NT, Char, NT, Char> = Grammer {
+            rules: vec![
+                rule(NT::E, vec![N(NT::E), Tm(Char('+')), N(NT::T)]),
+                rule(NT::E, vec![N(NT::T)]),
+                rule(NT::T, vec![N(NT::T), Tm(Char('*')), N(NT::F)]),
+                rule(NT::T, vec![N(NT::F)]),
+                rule(NT::F, vec![Tm(Char('(')), N(NT::E), Tm(Char(')'))]),
+                rule(NT::F, vec![Tm(Char('1'))]),
+                rule(NT::F, vec![Tm(Char('x'))]),
+            ],
+        };
+        let (automaton, reduce_action) =
+            generate_canonical_automaton(grammer, NT::E, None).unwrap();
+        //競合しているセルも圧縮する.
+        let table = canonical_automaton_to_lr0_parser_with_resolution(
+            &automaton,
+            reduce_action,
+            &Resolution::PreferShift,
+            &mut (),
+        )
+        .unwrap();
+        let compressed = CompressedTable::new(&table);
+
+        assert_eq!(compressed.start_state(), table.start_state());
+        //表にない記号 z も含めてすべてのセルが同じ.
+        let terms = [
+            Char('+'),
+            Char('*'),
+            Char('('),
+            Char(')'),
+            Char('1'),
+            Char('x'),
+            Char('$'),
+            Char('z'),
+        ];
+        for state in 0..automaton.len() + 1 {
+            for t in &terms {
+                assert_eq!(compressed.action(state, t), table.action(state, t));
+            }
+            for nt in [NT::E, NT::T, NT::F] {
+                assert_eq!(compressed.goto(state, &nt), table.goto(state, &nt));
+            }
+        }
+        // 1 と x は同じ所で使われるが,シフトした先の状態が違うので列は別になる.
+        assert_eq!(compressed.term_classes(), 7);
+        let cells = table.action_entries().count() + table.goto_entries().count();
+        assert!(compressed.len() < cells);
+    }
+}
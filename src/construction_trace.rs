@@ -0,0 +1,319 @@
+use crate::bnf::Symbol;
+use crate::item_set::{LR0Item, StateId};
+use crate::observer::{BuildEvent, BuildObserver};
+use crate::parse_tree::escape_latex;
+use std::fmt::{Debug, Write};
+
+/// Goto(I_from, symbol) = I_to を1回計算したこと.
+struct GotoStep<NT, T>
+where
+    NT: Ord + Eq + Clone + Debug,
+    T: Ord + Eq + Clone + Debug,
+{
+    from: StateId,
+    symbol: Symbol<NT, T>,
+    to: StateId,
+    new_state: bool,
+}
+
+/// 状態の核と,クロージャで加えた項
+struct StateTrace<NT, T>
+where
+    NT: Ord + Eq + Clone + Debug,
+    T: Ord + Eq + Clone + Debug,
+{
+    kernel: Vec<LR0Item<NT, T>>,
+    added: Vec<LR0Item<NT, T>>,
+}
+
+/// 表の1行
+struct Row<'a, NT, T>
+where
+    NT: Ord + Eq + Clone + Debug,
+    T: Ord + Eq + Clone + Debug,
+{
+    state: StateId,
+    /// Goto(I_from, symbol) の from と symbol. 開始状態の行は None.
+    goto: Option<(StateId, String)>,
+    kernel: &'a [LR0Item<NT, T>],
+    added: &'a [LR0Item<NT, T>],
+    new_state: bool,
+}
+
+/// 正準オートマトンを作る手順の記録.
+///
+/// `generate_canonical_automaton_with` に渡して記録し, LaTeX か Markdown の表に書き出す.
+/// 1行目は開始状態 I_0 で,その後に Goto を計算した順に並ぶ.
+pub struct ConstructionTrace<NT, T>
+where
+    NT: Ord + Eq + Clone + Debug,
+    T: Ord + Eq + Clone + Debug,
+{
+    states: Vec<StateTrace<NT, T>>,
+    steps: Vec<GotoStep<NT, T>>,
+}
+
+impl<NT, T> Default for ConstructionTrace<NT, T>
+where
+    NT: Ord + Eq + Clone + Debug,
+    T: Ord + Eq + Clone + Debug,
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<NT, T> BuildObserver<NT, T> for ConstructionTrace<NT, T>
+where
+    NT: Ord + Eq + Clone + Debug,
+    T: Ord + Eq + Clone + Debug,
+{
+    fn notify(&mut self, event: BuildEvent<'_, NT, T>) {
+        match event {
+            BuildEvent::Expand { state, items } => {
+                //状態を調べるときにクロージャが分かる. 項は核が先に並ぶ.
+                let trace = self.state_mut(state);
+                if trace.kernel.is_empty() {
+                    //開始状態の核は S' -> ・S $ だけ.
+                    trace.kernel = items[..1].to_vec();
+                }
+                trace.added = items[trace.kernel.len()..].to_vec();
+            }
+            BuildEvent::Goto {
+                from,
+                symbol,
+                to,
+                kernel,
+                new_state,
+            } => {
+                if new_state {
+                    self.state_mut(to).kernel = kernel.to_vec();
+                }
+                self.steps.push(GotoStep {
+                    from,
+                    symbol: symbol.clone(),
+                    to,
+                    new_state,
+                });
+            }
+            _ => {}
+        }
+    }
+}
+
+impl<NT, T> ConstructionTrace<NT, T>
+where
+    NT: Ord + Eq + Clone + Debug,
+    T: Ord + Eq + Clone + Debug,
+{
+    pub fn new() -> Self {
+        Self {
+            states: vec![],
+            steps: vec![],
+        }
+    }
+
+    fn state_mut(&mut self, state: StateId) -> &mut StateTrace<NT, T> {
+        while self.states.len() <= state {
+            self.states.push(StateTrace {
+                kernel: vec![],
+                added: vec![],
+            });
+        }
+        &mut self.states[state]
+    }
+
+    /// Goto を計算した回数
+    pub fn len(&self) -> usize {
+        self.steps.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.steps.is_empty()
+    }
+
+    /// 表の行
+    fn rows(&self) -> Vec<Row<'_, NT, T>> {
+        let mut rows = vec![];
+        if let Some(start) = self.states.first() {
+            rows.push(Row {
+                state: 0,
+                goto: None,
+                kernel: start.kernel.as_slice(),
+                added: start.added.as_slice(),
+                new_state: true,
+            });
+        }
+        for step in &self.steps {
+            let symbol = match &step.symbol {
+                Symbol::Term(t) => format!("{:?}", t),
+                Symbol::NonTerm(nt) => format!("{:?}", nt),
+            };
+            let state = &self.states[step.to];
+            rows.push(Row {
+                state: step.to,
+                goto: Some((step.from, symbol)),
+                kernel: state.kernel.as_slice(),
+                //前に作った状態ならクロージャは書かない.
+                added: if step.new_state {
+                    state.added.as_slice()
+                } else {
+                    &[]
+                },
+                new_state: step.new_state,
+            });
+        }
+        rows
+    }
+
+    /// LaTeX の tabular として書き出す. 項は1つずつ改行して並べる.
+    pub fn export_as_latex_src(&self) -> String {
+        let items = |items: &[LR0Item<NT, T>]| {
+            items
+                .iter()
+                .map(|item| escape_latex(&item.to_string()))
+                .collect::<Vec<_>>()
+                .join(r" \newline ")
+        };
+        let mut buffer = String::new();
+        writeln!(&mut buffer, "\\begin{{tabular}}{{llp{{5cm}}p{{5cm}}l}}").unwrap();
+        writeln!(&mut buffer, r" & Goto & kernel & closure & new \\ \hline").unwrap();
+        for (step, row) in self.rows().into_iter().enumerate() {
+            let goto = match &row.goto {
+                Some((from, symbol)) => format!(
+                    "$I_{{{}}}$ = Goto($I_{{{}}}$,{})",
+                    row.state,
+                    from,
+                    escape_latex(symbol)
+                ),
+                None => format!("$I_{{{}}}$", row.state),
+            };
+            writeln!(
+                &mut buffer,
+                "{} & {} & {} & {} & {} \\\\ \\hline",
+                step,
+                goto,
+                items(row.kernel),
+                items(row.added),
+                if row.new_state { "new" } else { "" }
+            )
+            .unwrap();
+        }
+        writeln!(&mut buffer, "\\end{{tabular}}").unwrap();
+        buffer
+    }
+
+    /// Markdown の表として書き出す.
+    pub fn export_as_markdown(&self) -> String {
+        let mut buffer = String::new();
+        writeln!(&mut buffer, "| step | Goto | kernel | closure | new |").unwrap();
+        writeln!(&mut buffer, "|---|---|---|---|---|").unwrap();
+        for (step, row) in self.rows().into_iter().enumerate() {
+            let goto = match &row.goto {
+                Some((from, symbol)) => format!("I_{} = Goto(I_{},{})", row.state, from, symbol),
+                None => format!("I_{}", row.state),
+            };
+            writeln!(
+                &mut buffer,
+                "| {} | {} | {} | {} | {} |",
+                step,
+                goto.replace('|', "\\|"),
+                Self::join(row.kernel, "<br>").replace('|', "\\|"),
+                Self::join(row.added, "<br>").replace('|', "\\|"),
+                if row.new_state { "new" } else { "" }
+            )
+            .unwrap();
+        }
+        buffer
+    }
+
+    fn join(items: &[LR0Item<NT, T>], separator: &str) -> String {
+        items
+            .iter()
+            .map(|item| item.to_string())
+            .collect::<Vec<_>>()
+            .join(separator)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::ConstructionTrace;
+    use crate::bnf::test::Char;
+    use crate::bnf::{Expr, Grammer, Symbol};
+    use crate::item_set::generate_canonical_automaton_with;
+
+    #[derive(Ord, PartialOrd, PartialEq, Eq, Clone, Debug)]
+    enum NT {
+        S,
+    }
+
+    #[test]
+    fn test_construction_trace() {
+        // S -> { S } | x
+        let grammer: Grammer<NT, Char, NT, Char> = Grammer {
+            rules: vec![
+                Expr {
+                    left: NT::S,
+                    right: vec![
+                        Symbol::Term(Char('{')),
+                        Symbol::NonTerm(NT::S),
+                        Symbol::Term(Char('}')),
+                    ],
+                    reduce_action: None,
+                },
+                Expr {
+                    left: NT::S,
+                    right: vec![Symbol::Term(Char('x'))],
+                    reduce_action: None,
+                },
+            ],
+        };
+        let mut trace = ConstructionTrace::new();
+        let (automaton, _) =
+            generate_canonical_automaton_with(grammer, NT::S, None, &mut trace).unwrap();
+        let markdown = trace.export_as_markdown();
+        let lines: Vec<_> = markdown.lines().collect();
+        // 見出し2行と I_0 と Goto の数だけ行がある.
+        assert_eq!(lines.len(), 3 + trace.len());
+        assert_eq!(
+            lines[2],
+            "| 0 | I_0 | S' -> ・S'$' | S -> ・'{'S'}'<br>S -> ・'x' | new |"
+        );
+        assert_eq!(
+            lines[3],
+            "| 1 | I_1 = Goto(I_0,S) | S' -> S・'$' |  | new |"
+        );
+        assert_eq!(
+            lines[4],
+            "| 2 | I_2 = Goto(I_0,'{') | S -> '{'・S'}' | S -> ・'{'S'}'<br>S -> ・'x' | new |"
+        );
+        // { の後で { を読むと I_2 に戻る.
+        assert!(lines.contains(&"| 6 | I_2 = Goto(I_2,'{') | S -> '{'・S'}' |  |  |"));
+        let new_states = lines[2..]
+            .iter()
+            .filter(|line| line.ends_with("| new |"))
+            .count();
+        assert_eq!(new_states, automaton.len());
+
+        // 記号は文字として書き,項は同じセルの中で改行する.
+        let latex = trace.export_as_latex_src();
+        let lines: Vec<_> = latex.lines().collect();
+        assert_eq!(lines.len(), 4 + trace.len());
+        assert_eq!(lines[0], r"\begin{tabular}{llp{5cm}p{5cm}l}");
+        assert_eq!(
+            lines[2],
+            r"0 & $I_{0}$ & S' -> ・S'\$' & S -> ・'\{'S'\}' \newline S -> ・'x' & new \\ \hline"
+        );
+        assert_eq!(
+            lines[3],
+            r"1 & $I_{1}$ = Goto($I_{0}$,S) & S' -> S・'\$' &  & new \\ \hline"
+        );
+        assert_eq!(lines.last(), Some(&r"\end{tabular}"));
+        assert!(latex.contains(r"Goto($I_{1}$,'\$')"));
+        // 表の行を終える \\ は各行の最後の1つだけ.
+        assert!(lines[2..lines.len() - 1]
+            .iter()
+            .all(|line| line.matches(r"\\").count() == 1));
+    }
+}
@@ -0,0 +1,142 @@
+use crate::parsing_table::ValueStackSymbol;
+use std::fmt::{Debug, Display, Write};
+
+/// 具象構文木の節.
+///
+/// 還元動作を与えていない規則で還元したときに,構文解析器が代わりに作る.
+/// 子は値スタックに積まれていたものがそのまま入るので,
+/// 字句・還元動作の結果・別の具象構文木が混ざっていてもよい.
+#[derive(Debug)]
+pub struct ConcreteSyntaxTree<NT, NTV, TV> {
+    /// 還元に使った規則の番号 r_{rule}
+    pub rule: usize,
+    pub left: NT,
+    pub children: Vec<ValueStackSymbol<NT, NTV, TV>>,
+}
+
+impl<NT, NTV, TV> ConcreteSyntaxTree<NT, NTV, TV>
+where
+    NT: Debug,
+    NTV: Debug,
+    TV: Debug,
+{
+    /// 1行の S式 として書き出す.
+    ///
+    /// `(E (P 1) + (P 1))` のように,節は左辺の非終端記号から始まる.
+    pub fn export_as_sexpr(&self) -> String {
+        let mut buffer = String::new();
+        self.write_sexpr(&mut buffer);
+        buffer
+    }
+
+    fn write_sexpr(&self, buffer: &mut String) {
+        write!(buffer, "({:?}", self.left).unwrap();
+        for child in &self.children {
+            buffer.push(' ');
+            match child {
+                ValueStackSymbol::Tree(tree) => tree.write_sexpr(buffer),
+                ValueStackSymbol::Term(tv) => write!(buffer, "{:?}", tv).unwrap(),
+                ValueStackSymbol::NonTerm(ntv) => write!(buffer, "{:?}", ntv).unwrap(),
+            }
+        }
+        buffer.push(')');
+    }
+
+    fn write_indented(&self, f: &mut std::fmt::Formatter<'_>, depth: usize) -> std::fmt::Result {
+        writeln!(f, "{}{:?} (r_{})", "  ".repeat(depth), self.left, self.rule)?;
+        for child in &self.children {
+            match child {
+                ValueStackSymbol::Tree(tree) => tree.write_indented(f, depth + 1)?,
+                ValueStackSymbol::Term(tv) => writeln!(f, "{}{:?}", "  ".repeat(depth + 1), tv)?,
+                ValueStackSymbol::NonTerm(ntv) => {
+                    writeln!(f, "{}{:?}", "  ".repeat(depth + 1), ntv)?
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// 1行に1つの節を字下げして書き出す.
+impl<NT, NTV, TV> Display for ConcreteSyntaxTree<NT, NTV, TV>
+where
+    NT: Debug,
+    NTV: Debug,
+    TV: Debug,
+{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.write_indented(f, 0)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::bnf::{EOFSupply, Expr, Grammer, Symbol};
+    use crate::item_set::generate_canonical_automaton;
+    use crate::parse_session::ParseSession;
+    use crate::parsing_table::{canonical_automaton_to_lr0_parser, ValueStackSymbol};
+    use std::sync::Arc;
+
+    #[derive(Ord, PartialOrd, PartialEq, Eq, Clone, Debug)]
+    enum NT {
+        E,
+        P,
+    }
+
+    #[derive(Ord, PartialOrd, PartialEq, Eq, Clone, Debug)]
+    enum T {
+        One,
+        Plus,
+        Eof,
+    }
+
+    impl EOFSupply<T> for T {
+        fn eof() -> T {
+            T::Eof
+        }
+    }
+
+    #[test]
+    fn test_default_concrete_syntax_tree() {
+        // E -> E + P | P , P -> 1
+        let grammer: Grammer<NT, T, NT, T> = Grammer {
+            rules: vec![
+                Expr {
+                    left: NT::E,
+                    right: vec![
+                        Symbol::NonTerm(NT::E),
+                        Symbol::Term(T::Plus),
+                        Symbol::NonTerm(NT::P),
+                    ],
+                    reduce_action: None,
+                },
+                Expr {
+                    left: NT::E,
+                    right: vec![Symbol::NonTerm(NT::P)],
+                    reduce_action: None,
+                },
+                Expr {
+                    left: NT::P,
+                    right: vec![Symbol::Term(T::One)],
+                    reduce_action: None,
+                },
+            ],
+        };
+        let (automaton, reduce_action) =
+            generate_canonical_automaton(grammer, NT::E, None).unwrap();
+        let table = canonical_automaton_to_lr0_parser(&automaton, reduce_action).unwrap();
+        let mut session = ParseSession::new(Arc::new(table));
+        let tree = match session.parse_iter(vec![T::One, T::Plus, T::One]) {
+            Ok(ValueStackSymbol::Tree(tree)) => tree,
+            other => panic!("expected concrete syntax tree but got {:?}", other),
+        };
+        assert_eq!(tree.left, NT::E);
+        assert_eq!(tree.children.len(), 3);
+        assert_eq!(tree.export_as_sexpr(), "(E (E (P One)) Plus (P One))");
+        // 子は2つずつ字下げして1行に1つ書く.
+        assert_eq!(
+            tree.to_string(),
+            "E (r_0)\n  E (r_1)\n    P (r_2)\n      One\n  Plus\n  P (r_2)\n    One\n"
+        );
+    }
+}
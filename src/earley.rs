@@ -0,0 +1,484 @@
+use crate::{
+    bnf::{Grammer, IntoKind, Symbol},
+    cst::ConcreteSyntaxTree,
+    nullable_set::generate_null_set,
+    parsing_table::ValueStackSymbol,
+};
+use std::collections::{BTreeMap, BTreeSet};
+
+/// Earley 項目. 規則 r_{rule} の右辺の dot 番目まで読み,origin 番目の字句から始まった.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+struct EarleyItem {
+    rule: usize,
+    dot: usize,
+    origin: usize,
+}
+
+/// 位置ごとの項目集合. items は追加した順.
+#[derive(Default)]
+struct EarleySet {
+    items: Vec<EarleyItem>,
+    seen: BTreeSet<EarleyItem>,
+}
+
+impl EarleySet {
+    fn insert(&mut self, item: EarleyItem) {
+        if self.seen.insert(item) {
+            self.items.push(item);
+        }
+    }
+
+    fn contains(&self, item: &EarleyItem) -> bool {
+        self.seen.contains(item)
+    }
+}
+
+enum EarleyTree {
+    Token(usize),
+    Node {
+        rule: usize,
+        children: Vec<EarleyTree>,
+    },
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum EarleyError<T> {
+    /// position 番目の字句 token を読めない.
+    UnexpectedToken { position: usize, token: T },
+    /// 開始記号を導出する前に入力が尽きた.
+    UnexpectedEndOfInput,
+    /// 規則 rule の還元動作が値を受け取れなかった.
+    ActionMismatch { rule: usize },
+}
+
+/// Earley 法の構文解析器.
+///
+/// 左再帰・曖昧・空規則を含む任意の文脈自由文法をそのまま扱える.
+/// 空になりうる非終端記号は Aycock–Horspool の方法で予測と同時に読み飛ばす.
+/// 規則の番号は `Grammer::rules` の位置.
+pub struct EarleyParser<NT, T, NTV, TV>
+where
+    T: Ord + Eq + Clone,
+    NT: Ord + Eq + Clone,
+    TV: IntoKind<T>,
+    NTV: IntoKind<NT>,
+{
+    grammer: Grammer<NT, T, NTV, TV>,
+    start: NT,
+    nullable: BTreeSet<NT>,
+    rules_by_left: BTreeMap<NT, Vec<usize>>,
+}
+
+impl<NT, T, NTV, TV> EarleyParser<NT, T, NTV, TV>
+where
+    T: Ord + Eq + Clone,
+    NT: Ord + Eq + Clone,
+    TV: IntoKind<T>,
+    NTV: IntoKind<NT>,
+{
+    pub fn new(grammer: Grammer<NT, T, NTV, TV>, start: NT) -> Self {
+        let nullable = generate_null_set(&grammer);
+        let mut rules_by_left: BTreeMap<NT, Vec<usize>> = BTreeMap::new();
+        for (number, rule) in grammer.rules.iter().enumerate() {
+            rules_by_left
+                .entry(rule.left.clone())
+                .or_default()
+                .push(number);
+        }
+        Self {
+            grammer,
+            start,
+            nullable,
+            rules_by_left,
+        }
+    }
+
+    /// tokens が開始記号から導出できるか.
+    pub fn recognize(&self, tokens: &[TV]) -> bool {
+        self.chart(tokens).is_ok()
+    }
+
+    /// tokens を構文解析して,規則の還元動作で評価する.
+    ///
+    /// 曖昧なときは導出の1つを選ぶ. 還元動作のない規則は具象構文木になる.
+    pub fn parse(&self, tokens: Vec<TV>) -> Result<ValueStackSymbol<NT, NTV, TV>, EarleyError<T>> {
+        let chart = self.chart(&tokens)?;
+        let tree = self
+            .build(
+                &chart,
+                &tokens,
+                &self.start,
+                0,
+                tokens.len(),
+                &mut BTreeSet::new(),
+            )
+            .expect("recognized input must have a derivation");
+        let mut tokens: Vec<Option<TV>> = tokens.into_iter().map(Some).collect();
+        self.evaluate(tree, &mut tokens)
+    }
+
+    fn chart(&self, tokens: &[TV]) -> Result<Vec<EarleySet>, EarleyError<T>> {
+        let mut chart: Vec<EarleySet> = (0..=tokens.len()).map(|_| EarleySet::default()).collect();
+        for rule in self.rules_of(&self.start) {
+            chart[0].insert(EarleyItem {
+                rule: *rule,
+                dot: 0,
+                origin: 0,
+            });
+        }
+        for position in 0..=tokens.len() {
+            let kind = tokens.get(position).map(|token| token.into_kind());
+            let mut index = 0;
+            while index < chart[position].items.len() {
+                let item = chart[position].items[index];
+                index += 1;
+                let right = &self.grammer.rules[item.rule].right;
+                match right.get(item.dot) {
+                    //予測
+                    Some(Symbol::NonTerm(nt)) => {
+                        for rule in self.rules_of(nt) {
+                            chart[position].insert(EarleyItem {
+                                rule: *rule,
+                                dot: 0,
+                                origin: position,
+                            });
+                        }
+                        if self.nullable.contains(nt) {
+                            chart[position].insert(EarleyItem {
+                                dot: item.dot + 1,
+                                ..item
+                            });
+                        }
+                    }
+                    //走査
+                    Some(Symbol::Term(t)) => {
+                        if kind.as_ref() == Some(t) {
+                            chart[position + 1].insert(EarleyItem {
+                                dot: item.dot + 1,
+                                ..item
+                            });
+                        }
+                    }
+                    //完了
+                    None => {
+                        let left = &self.grammer.rules[item.rule].left;
+                        let waiting: Vec<EarleyItem> = chart[item.origin]
+                            .items
+                            .iter()
+                            .filter(|waiting| {
+                                self.grammer.rules[waiting.rule].right.get(waiting.dot)
+                                    == Some(&Symbol::NonTerm(left.clone()))
+                            })
+                            .copied()
+                            .collect();
+                        for waiting in waiting {
+                            chart[position].insert(EarleyItem {
+                                dot: waiting.dot + 1,
+                                ..waiting
+                            });
+                        }
+                    }
+                }
+            }
+            if position < tokens.len() && chart[position + 1].items.is_empty() {
+                return Err(EarleyError::UnexpectedToken {
+                    position,
+                    token: kind.unwrap(),
+                });
+            }
+        }
+        if self
+            .completed_rules(&chart, &self.start, 0, tokens.len())
+            .is_empty()
+        {
+            return Err(EarleyError::UnexpectedEndOfInput);
+        }
+        Ok(chart)
+    }
+
+    fn rules_of(&self, nt: &NT) -> &[usize] {
+        self.rules_by_left.get(nt).map_or(&[], |rules| rules)
+    }
+
+    /// 字句 start..end を nt として導出し終えた規則
+    fn completed_rules(
+        &self,
+        chart: &[EarleySet],
+        nt: &NT,
+        start: usize,
+        end: usize,
+    ) -> Vec<usize> {
+        self.rules_of(nt)
+            .iter()
+            .filter(|rule| {
+                chart[end].contains(&EarleyItem {
+                    rule: **rule,
+                    dot: self.grammer.rules[**rule].right.len(),
+                    origin: start,
+                })
+            })
+            .copied()
+            .collect()
+    }
+
+    /// 字句 start..end を nt として導出する木を1つ作る.
+    ///
+    /// visiting は作りかけの (nt,start,end) で, A =>+ A のような循環をたどらないために使う.
+    fn build(
+        &self,
+        chart: &[EarleySet],
+        tokens: &[TV],
+        nt: &NT,
+        start: usize,
+        end: usize,
+        visiting: &mut BTreeSet<(NT, usize, usize)>,
+    ) -> Option<EarleyTree> {
+        if !visiting.insert((nt.clone(), start, end)) {
+            return None;
+        }
+        let tree = self
+            .completed_rules(chart, nt, start, end)
+            .into_iter()
+            .find_map(|rule| {
+                let len = self.grammer.rules[rule].right.len();
+                self.split(chart, tokens, rule, len, start, end, visiting)
+                    .map(|children| EarleyTree::Node { rule, children })
+            });
+        visiting.remove(&(nt.clone(), start, end));
+        tree
+    }
+
+    /// 規則 r_{rule} の右辺の dot 番目までを字句 start..end に割り当てる.
+    #[allow(clippy::too_many_arguments)]
+    fn split(
+        &self,
+        chart: &[EarleySet],
+        tokens: &[TV],
+        rule: usize,
+        dot: usize,
+        start: usize,
+        end: usize,
+        visiting: &mut BTreeSet<(NT, usize, usize)>,
+    ) -> Option<Vec<EarleyTree>> {
+        if dot == 0 {
+            return (start == end).then(Vec::new);
+        }
+        let prefix = EarleyItem {
+            rule,
+            dot: dot - 1,
+            origin: start,
+        };
+        match &self.grammer.rules[rule].right[dot - 1] {
+            Symbol::Term(t) => {
+                if end == start
+                    || tokens[end - 1].into_kind() != *t
+                    || !chart[end - 1].contains(&prefix)
+                {
+                    return None;
+                }
+                let mut children =
+                    self.split(chart, tokens, rule, dot - 1, start, end - 1, visiting)?;
+                children.push(EarleyTree::Token(end - 1));
+                Some(children)
+            }
+            Symbol::NonTerm(nt) => (start..=end).rev().find_map(|middle| {
+                if !chart[middle].contains(&prefix) {
+                    return None;
+                }
+                let last = self.build(chart, tokens, nt, middle, end, visiting)?;
+                let mut children =
+                    self.split(chart, tokens, rule, dot - 1, start, middle, visiting)?;
+                children.push(last);
+                Some(children)
+            }),
+        }
+    }
+
+    fn evaluate(
+        &self,
+        tree: EarleyTree,
+        tokens: &mut [Option<TV>],
+    ) -> Result<ValueStackSymbol<NT, NTV, TV>, EarleyError<T>> {
+        match tree {
+            EarleyTree::Token(position) => Ok(ValueStackSymbol::Term(
+                tokens[position].take().expect("token used twice"),
+            )),
+            EarleyTree::Node { rule, children } => {
+                let args = children
+                    .into_iter()
+                    .map(|child| self.evaluate(child, tokens))
+                    .collect::<Result<_, _>>()?;
+                let expr = &self.grammer.rules[rule];
+                match &expr.reduce_action {
+                    Some(function) => function
+                        .call(args)
+                        .map(ValueStackSymbol::NonTerm)
+                        .ok_or(EarleyError::ActionMismatch { rule }),
+                    None => Ok(ValueStackSymbol::Tree(ConcreteSyntaxTree {
+                        rule,
+                        left: expr.left.clone(),
+                        children: args,
+                    })),
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{EarleyError, EarleyParser};
+    use crate::bnf::test::{chars, Char};
+    use crate::bnf::{Expr, Grammer, IntoKind, ReduceArgs, Symbol};
+    use crate::item_set::generate_canonical_automaton;
+    use crate::parse_session::ParseSession;
+    use crate::parsing_table::{canonical_automaton_to_lr0_parser, ValueStackSymbol};
+    use std::sync::Arc;
+
+    #[derive(Ord, PartialOrd, PartialEq, Eq, Clone, Debug)]
+    enum NT {
+        S,
+        A,
+    }
+
+    /// 読んだ 'a' の数
+    #[derive(Debug, PartialEq)]
+    struct Count(usize);
+
+    impl IntoKind<NT> for Count {
+        fn into_kind(&self) -> NT {
+            NT::S
+        }
+    }
+
+    fn count(args: ReduceArgs<NT, Count, Char>) -> Count {
+        Count(
+            args.iter()
+                .map(|arg| match arg {
+                    ValueStackSymbol::NonTerm(Count(n)) => *n,
+                    ValueStackSymbol::Term(Char('a')) => 1,
+                    _ => 0,
+                })
+                .sum(),
+        )
+    }
+
+    #[test]
+    fn test_earley_nullable() {
+        // S -> A A x | S S , A -> ε | a
+        let grammer: Grammer<NT, Char, Count, Char> = Grammer {
+            rules: vec![
+                Expr {
+                    left: NT::S,
+                    right: vec![
+                        Symbol::NonTerm(NT::A),
+                        Symbol::NonTerm(NT::A),
+                        Symbol::Term(Char('x')),
+                    ],
+                    reduce_action: Some(Box::new(count)),
+                },
+                Expr {
+                    left: NT::S,
+                    right: vec![Symbol::NonTerm(NT::S), Symbol::NonTerm(NT::S)],
+                    reduce_action: Some(Box::new(count)),
+                },
+                Expr {
+                    left: NT::A,
+                    right: vec![],
+                    reduce_action: Some(Box::new(count)),
+                },
+                Expr {
+                    left: NT::A,
+                    right: vec![Symbol::Term(Char('a'))],
+                    reduce_action: Some(Box::new(count)),
+                },
+            ],
+        };
+        let parser = EarleyParser::new(grammer, NT::S);
+        for input in ["x", "ax", "aax", "xx", "axaax"] {
+            assert!(
+                parser.recognize(&chars(input).collect::<Vec<_>>()),
+                "{}",
+                input
+            );
+        }
+        for input in ["", "a", "aaax", "xa"] {
+            assert!(
+                !parser.recognize(&chars(input).collect::<Vec<_>>()),
+                "{}",
+                input
+            );
+        }
+        assert!(matches!(
+            parser.parse(chars("axaax").collect()),
+            Ok(ValueStackSymbol::NonTerm(Count(3)))
+        ));
+        assert!(matches!(
+            parser.parse(chars("aaax").collect()),
+            Err(EarleyError::UnexpectedToken {
+                position: 2,
+                token: Char('a')
+            })
+        ));
+        assert!(matches!(
+            parser.parse(chars("a").collect()),
+            Err(EarleyError::UnexpectedEndOfInput)
+        ));
+    }
+
+    /// LR(0) 構文解析器と同じ文を受理することを,短い文をすべて並べて確かめる.
+    #[test]
+    fn test_earley_agrees_with_lr0() {
+        // S -> ( S ) | x
+        let grammer = || -> Grammer<NT, Char, NT, Char> {
+            Grammer {
+                rules: vec![
+                    Expr {
+                        left: NT::S,
+                        right: vec![
+                            Symbol::Term(Char('(')),
+                            Symbol::NonTerm(NT::S),
+                            Symbol::Term(Char(')')),
+                        ],
+                        reduce_action: None,
+                    },
+                    Expr {
+                        left: NT::S,
+                        right: vec![Symbol::Term(Char('x'))],
+                        reduce_action: None,
+                    },
+                ],
+            }
+        };
+        let (automaton, reduce_action) =
+            generate_canonical_automaton(grammer(), NT::S, None).unwrap();
+        let table = Arc::new(canonical_automaton_to_lr0_parser(&automaton, reduce_action).unwrap());
+        let earley = EarleyParser::new(grammer(), NT::S);
+
+        let mut inputs: Vec<Vec<Char>> = vec![vec![]];
+        for _ in 0..6 {
+            inputs = inputs
+                .iter()
+                .flat_map(|input| {
+                    [Char('('), Char(')'), Char('x')].iter().map(move |c| {
+                        let mut input = input.clone();
+                        input.push(*c);
+                        input
+                    })
+                })
+                .collect();
+            for input in &inputs {
+                let mut session = ParseSession::new(table.clone());
+                let lr0 = session.parse_iter(input.clone()).is_ok();
+                assert_eq!(earley.recognize(input), lr0, "{:?}", input);
+            }
+        }
+
+        match earley.parse(chars("((x))").collect()) {
+            Ok(ValueStackSymbol::Tree(tree)) => {
+                assert_eq!(tree.export_as_sexpr(), "(S '(' (S '(' (S 'x') ')') ')')")
+            }
+            other => panic!("expected concrete syntax tree but got {:?}", other),
+        }
+    }
+}
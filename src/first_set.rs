@@ -1,11 +1,25 @@
 use crate::bnf::{Grammer, IntoKind, Symbol};
+use crate::observer::{BuildEvent, BuildObserver};
 use std::collections::{BTreeMap, BTreeSet};
 use std::fmt::Debug;
 
-#[allow(dead_code)]
 pub fn generate_first_set<NT, T, NTV, TV>(
     grammer: &Grammer<NT, T, NTV, TV>,
 ) -> BTreeMap<Symbol<NT, T>, BTreeSet<T>>
+where
+    T: Ord + Eq + Clone + Debug,
+    NT: Ord + Eq + Clone + Debug,
+    NTV: IntoKind<NT>,
+    TV: IntoKind<T>,
+{
+    generate_first_set_with(grammer, &mut ())
+}
+
+/// `generate_first_set` と同じだが,包含関係とそれを解く様子を observer に知らせる.
+pub fn generate_first_set_with<NT, T, NTV, TV>(
+    grammer: &Grammer<NT, T, NTV, TV>,
+    observer: &mut impl BuildObserver<NT, T>,
+) -> BTreeMap<Symbol<NT, T>, BTreeSet<T>>
 where
     T: Ord + Eq + Clone + Debug,
     NT: Ord + Eq + Clone + Debug,
@@ -32,53 +46,76 @@ where
     }
     //ヌル集合
     let nullable_set = crate::nullable_set::generate_null_set(grammer);
+    //包含関係を示す. 右辺の先頭から,ヌルにならない最初の記号までの First 集合が左辺に含まれる.
+    let mut constraints = vec![];
+    for rule in &grammer.rules {
+        let sup = &rule.left;
+        for sub in &rule.right {
+            if Symbol::NonTerm(sup.clone()) != *sub {
+                observer.notify(BuildEvent::FirstConstraint { sup, sub });
+                constraints.push((sup, sub));
+            }
+            let nullable = match sub {
+                //終端記号なのでヌルになることはない
+                Symbol::Term(_) => false,
+                Symbol::NonTerm(nt) => nullable_set.contains(nt),
+            };
+            if !nullable {
+                break;
+            }
+        }
+    }
     let mut changed = true;
     while changed {
-        //包含関係を示す.
-        let mut constraints = vec![];
-
-        for rule in &grammer.rules {
-            let sup = &rule.left;
-            let first_non_null = rule.right.iter().find(|symbol| {
-                match symbol {
-                    //終端記号なのでヌルになることはない
-                    Symbol::Term(_) => false,
-                    //ヌル集合にないならばこれ目当てのものになる.
-                    Symbol::NonTerm(nt) => nullable_set.contains(nt),
-                }
-            });
+        //解決.
+        observer.notify(BuildEvent::FirstIteration);
+        let snap_shot = first_sets.clone();
+        for constraint in &constraints {
+            let sub = first_sets.get(constraint.1).cloned();
 
-            if let Some(sub) = first_non_null {
-                if Symbol::NonTerm(sup.clone()) != sub.clone() {
-                    println!("{:?} <-{:?}", sup, sub);
-                    constraints.push((sup, sub));
-                }
+            if let Some(sub) = sub {
+                let super_ = first_sets
+                    .get_mut(&Symbol::NonTerm(constraint.0.clone()))
+                    .unwrap();
+                sub.iter().for_each(|symbol| {
+                    super_.insert(symbol.clone());
+                });
             }
         }
+        changed = snap_shot != first_sets;
+    }
 
-        if constraints.is_empty() {
-            changed = false;
-        } else {
-            //解決.
-            println!("solving");
-            let snap_shot = first_sets.clone();
-            for constraint in constraints {
-                let sub = first_sets.get(constraint.1).cloned();
+    first_sets
+}
 
-                if let Some(sub) = sub {
-                    let super_ = first_sets
-                        .get_mut(&Symbol::NonTerm(constraint.0.clone()))
-                        .unwrap();
-                    sub.iter().for_each(|symbol| {
-                        super_.insert(symbol.clone());
-                    });
+/// 記号列 symbols の First 集合と,symbols 全体がヌルになりうるか.
+pub fn first_of_symbols<NT, T>(
+    first_sets: &BTreeMap<Symbol<NT, T>, BTreeSet<T>>,
+    nullable_set: &BTreeSet<NT>,
+    symbols: &[Symbol<NT, T>],
+) -> (BTreeSet<T>, bool)
+where
+    T: Ord + Eq + Clone,
+    NT: Ord + Eq + Clone,
+{
+    let mut first = BTreeSet::new();
+    for symbol in symbols {
+        match symbol {
+            Symbol::Term(t) => {
+                first.insert(t.clone());
+                return (first, false);
+            }
+            Symbol::NonTerm(nt) => {
+                if let Some(set) = first_sets.get(symbol) {
+                    first.extend(set.iter().cloned());
+                }
+                if !nullable_set.contains(nt) {
+                    return (first, false);
                 }
             }
-            changed = snap_shot != first_sets;
         }
     }
-
-    first_sets
+    (first, true)
 }
 
 #[cfg(test)]
@@ -126,7 +163,48 @@ mod test {
             ],
         };
         let first_set = generate_first_set(&grammer);
-        println!("{:#?}", first_set)
+        let first = |symbol| first_set[&symbol].iter().copied().collect::<String>();
+        assert_eq!(first(NT(T)), "+n");
+        assert_eq!(first(NT(E)), "(+n");
+        assert_eq!(first(NT(S)), "(+n");
+    }
+
+    #[test]
+    fn test_generate_first_set_nullable() {
+        // S -> A B c , A -> a | ε , B -> b | ε
+        let grammer: Grammer<NonTerm, char, NonTerm, char> = Grammer {
+            rules: vec![
+                Expr {
+                    left: S,
+                    right: vec![NT(E), NT(T), Term('c')],
+                    reduce_action: None,
+                },
+                Expr {
+                    left: E,
+                    right: vec![Term('a')],
+                    reduce_action: None,
+                },
+                Expr {
+                    left: E,
+                    right: vec![],
+                    reduce_action: None,
+                },
+                Expr {
+                    left: T,
+                    right: vec![Term('b')],
+                    reduce_action: None,
+                },
+                Expr {
+                    left: T,
+                    right: vec![],
+                    reduce_action: None,
+                },
+            ],
+        };
+        let first_set = generate_first_set(&grammer);
+        let first = |symbol| first_set[&symbol].iter().copied().collect::<String>();
+        assert_eq!(first(NT(S)), "abc");
+        assert_eq!(first(NT(E)), "a");
     }
 
     #[derive(Clone, Ord, PartialOrd, PartialEq, Eq, Debug)]
@@ -0,0 +1,110 @@
+use crate::bnf::{Grammer, IntoKind, Symbol};
+use crate::first_set::{first_of_symbols, generate_first_set};
+use crate::nullable_set::generate_null_set;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt::Debug;
+
+/// Follow 集合. 開始記号 start の後ろには終端記号 eof が続くものとする.
+pub fn generate_follow_set<NT, T, NTV, TV>(
+    grammer: &Grammer<NT, T, NTV, TV>,
+    start: NT,
+    eof: T,
+) -> BTreeMap<NT, BTreeSet<T>>
+where
+    T: Ord + Eq + Clone + Debug,
+    NT: Ord + Eq + Clone + Debug,
+    NTV: IntoKind<NT>,
+    TV: IntoKind<T>,
+{
+    let first_sets = generate_first_set(grammer);
+    let nullable_set = generate_null_set(grammer);
+    let mut follow_sets: BTreeMap<NT, BTreeSet<T>> = BTreeMap::new();
+    for rule in &grammer.rules {
+        follow_sets.entry(rule.left.clone()).or_default();
+    }
+    follow_sets.entry(start).or_default().insert(eof);
+
+    let mut changed = true;
+    while changed {
+        changed = false;
+        for rule in &grammer.rules {
+            for (i, symbol) in rule.right.iter().enumerate() {
+                let nt = match symbol {
+                    Symbol::NonTerm(nt) => nt,
+                    Symbol::Term(_) => continue,
+                };
+                //後ろの記号列の First 集合. 後ろがヌルになりうるなら左辺の Follow 集合も.
+                let (mut follow, nullable) =
+                    first_of_symbols(&first_sets, &nullable_set, &rule.right[i + 1..]);
+                if nullable {
+                    follow.extend(follow_sets[&rule.left].iter().cloned());
+                }
+                let set = follow_sets.entry(nt.clone()).or_default();
+                let before = set.len();
+                set.extend(follow);
+                changed |= set.len() != before;
+            }
+        }
+    }
+    follow_sets
+}
+
+#[cfg(test)]
+mod test {
+    use super::generate_follow_set;
+    use crate::bnf::{Expr, Grammer, Symbol};
+
+    #[derive(Clone, Ord, PartialOrd, PartialEq, Eq, Debug)]
+    enum NonTerm {
+        E,
+        Edash,
+        T,
+    }
+
+    #[test]
+    fn test_generate_follow_set() {
+        // E -> T E' , E' -> + T E' | ε , T -> ( E ) | n
+        let grammer: Grammer<NonTerm, char, NonTerm, char> = Grammer {
+            rules: vec![
+                Expr {
+                    left: NonTerm::E,
+                    right: vec![Symbol::NonTerm(NonTerm::T), Symbol::NonTerm(NonTerm::Edash)],
+                    reduce_action: None,
+                },
+                Expr {
+                    left: NonTerm::Edash,
+                    right: vec![
+                        Symbol::Term('+'),
+                        Symbol::NonTerm(NonTerm::T),
+                        Symbol::NonTerm(NonTerm::Edash),
+                    ],
+                    reduce_action: None,
+                },
+                Expr {
+                    left: NonTerm::Edash,
+                    right: vec![],
+                    reduce_action: None,
+                },
+                Expr {
+                    left: NonTerm::T,
+                    right: vec![
+                        Symbol::Term('('),
+                        Symbol::NonTerm(NonTerm::E),
+                        Symbol::Term(')'),
+                    ],
+                    reduce_action: None,
+                },
+                Expr {
+                    left: NonTerm::T,
+                    right: vec![Symbol::Term('n')],
+                    reduce_action: None,
+                },
+            ],
+        };
+        let follow_set = generate_follow_set(&grammer, NonTerm::E, '$');
+        let follow = |nt| follow_set[&nt].iter().copied().collect::<String>();
+        assert_eq!(follow(NonTerm::E), "$)");
+        assert_eq!(follow(NonTerm::Edash), "$)");
+        assert_eq!(follow(NonTerm::T), "$)+");
+    }
+}
@@ -0,0 +1,452 @@
+use crate::sppf::{Derivation, ForestNode, ForestNodeId, PackedNode, ParseForest};
+use std::collections::BTreeMap;
+
+/// 子の記号節がどこに置かれるか. 規則 r_{rule} (右辺の長さ arity) の position 番目.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct ChildContext {
+    pub rule: usize,
+    pub position: usize,
+    pub arity: usize,
+}
+
+/// 曖昧さを解くための絞り込み.
+///
+/// 記号節の導出の候補を受け取って,残すものを返す. context は親の中での位置で,根なら None.
+pub trait ForestFilter<NT, TV> {
+    fn filter<'a>(
+        &self,
+        forest: &ParseForest<NT, TV>,
+        context: Option<&ChildContext>,
+        candidates: Vec<&'a PackedNode>,
+    ) -> Vec<&'a PackedNode>;
+}
+
+/// 優先順位. levels の前にある規則ほど強く結合する.
+///
+/// 強い規則の子として,それより弱い規則の導出を直接置かない.
+pub struct Priority {
+    levels: Vec<Vec<usize>>,
+}
+
+impl Priority {
+    pub fn new(levels: Vec<Vec<usize>>) -> Self {
+        Self { levels }
+    }
+
+    fn level(&self, rule: usize) -> Option<usize> {
+        self.levels.iter().position(|level| level.contains(&rule))
+    }
+}
+
+impl<NT, TV> ForestFilter<NT, TV> for Priority {
+    fn filter<'a>(
+        &self,
+        _forest: &ParseForest<NT, TV>,
+        context: Option<&ChildContext>,
+        candidates: Vec<&'a PackedNode>,
+    ) -> Vec<&'a PackedNode> {
+        let parent = match context.and_then(|context| self.level(context.rule)) {
+            Some(parent) => parent,
+            None => return candidates,
+        };
+        candidates
+            .into_iter()
+            .filter(|packed| !matches!(self.level(packed.rule), Some(child) if parent < child))
+            .collect()
+    }
+}
+
+/// 結合性. 同じ組の規則が続くとき,どちら側に木を伸ばすか.
+pub enum Associativity {
+    /// 右端の子に同じ組の規則を置かない. 1+1+1 は (1+1)+1.
+    Left(Vec<usize>),
+    /// 左端の子に同じ組の規則を置かない. 1+1+1 は 1+(1+1).
+    Right(Vec<usize>),
+    /// どちらの端にも置かない. 1+1+1 は誤り.
+    NonAssoc(Vec<usize>),
+}
+
+impl<NT, TV> ForestFilter<NT, TV> for Associativity {
+    fn filter<'a>(
+        &self,
+        _forest: &ParseForest<NT, TV>,
+        context: Option<&ChildContext>,
+        candidates: Vec<&'a PackedNode>,
+    ) -> Vec<&'a PackedNode> {
+        let (rules, left, right) = match self {
+            Associativity::Left(rules) => (rules, false, true),
+            Associativity::Right(rules) => (rules, true, false),
+            Associativity::NonAssoc(rules) => (rules, true, true),
+        };
+        let context = match context {
+            Some(context) if rules.contains(&context.rule) => context,
+            _ => return candidates,
+        };
+        let forbidden =
+            (left && context.position == 0) || (right && context.position + 1 == context.arity);
+        candidates
+            .into_iter()
+            .filter(|packed| !(forbidden && rules.contains(&packed.rule)))
+            .collect()
+    }
+}
+
+/// シフト・還元競合ではシフトを選ぶ.
+///
+/// シフトもできるセルで還元した導出が部分木に最も少ない候補を残す.
+pub struct PreferShift;
+
+impl PreferShift {
+    fn reduced_over_shift<NT, TV>(
+        forest: &ParseForest<NT, TV>,
+        id: ForestNodeId,
+        memo: &mut BTreeMap<ForestNodeId, usize>,
+    ) -> usize
+    where
+        NT: Ord + Clone,
+    {
+        if let Some(count) = memo.get(&id) {
+            return *count;
+        }
+        memo.insert(id, 0);
+        let count = forest
+            .packed(id)
+            .iter()
+            .map(|packed| Self::count_packed(forest, packed, memo))
+            .min()
+            .unwrap_or(0);
+        memo.insert(id, count);
+        count
+    }
+
+    fn count_packed<NT, TV>(
+        forest: &ParseForest<NT, TV>,
+        packed: &PackedNode,
+        memo: &mut BTreeMap<ForestNodeId, usize>,
+    ) -> usize
+    where
+        NT: Ord + Clone,
+    {
+        packed
+            .children
+            .iter()
+            .map(|child| Self::reduced_over_shift(forest, *child, memo))
+            .sum::<usize>()
+            + packed.reduced_over_shift as usize
+    }
+}
+
+impl<NT, TV> ForestFilter<NT, TV> for PreferShift
+where
+    NT: Ord + Clone,
+{
+    fn filter<'a>(
+        &self,
+        forest: &ParseForest<NT, TV>,
+        _context: Option<&ChildContext>,
+        candidates: Vec<&'a PackedNode>,
+    ) -> Vec<&'a PackedNode> {
+        let mut memo = BTreeMap::new();
+        let counts: Vec<usize> = candidates
+            .iter()
+            .map(|packed| Self::count_packed(forest, packed, &mut memo))
+            .collect();
+        let min = counts.iter().copied().min().unwrap_or(0);
+        candidates
+            .into_iter()
+            .zip(counts)
+            .filter(|(_, count)| *count == min)
+            .map(|(packed, _)| packed)
+            .collect()
+    }
+}
+
+/// 利用者の述語. true を返した候補を残す.
+pub struct Predicate<F>(pub F);
+
+impl<NT, TV, F> ForestFilter<NT, TV> for Predicate<F>
+where
+    F: Fn(&ParseForest<NT, TV>, Option<&ChildContext>, &PackedNode) -> bool,
+{
+    fn filter<'a>(
+        &self,
+        forest: &ParseForest<NT, TV>,
+        context: Option<&ChildContext>,
+        candidates: Vec<&'a PackedNode>,
+    ) -> Vec<&'a PackedNode> {
+        candidates
+            .into_iter()
+            .filter(|packed| (self.0)(forest, context, packed))
+            .collect()
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum DisambiguationError {
+    /// 森に根がない.
+    NoRoot,
+    /// 絞り込んだ後も node に candidates 個の導出が残った.
+    Ambiguous {
+        node: ForestNodeId,
+        candidates: usize,
+    },
+    /// node の導出がすべて取り除かれた.
+    Rejected { node: ForestNodeId },
+    /// 選んだ導出を評価したが,規則 r_{rule} の還元動作が値を受け取れなかった.
+    ActionMismatch { rule: usize },
+}
+
+/// 絞り込みを順に当てはめて,森から構文木を1つ選ぶ.
+///
+/// 絞り込んだ後の導出のうち,子もすべて選べるものがちょうど1つでなければ誤り.
+pub fn select<NT, TV>(
+    forest: &ParseForest<NT, TV>,
+    filters: &[&dyn ForestFilter<NT, TV>],
+) -> Result<Derivation, DisambiguationError>
+where
+    NT: Ord + Clone,
+{
+    let root = forest.root().ok_or(DisambiguationError::NoRoot)?;
+    choose(forest, filters, root, None, &mut BTreeMap::new())
+}
+
+type ChoiceMemo =
+    BTreeMap<(ForestNodeId, Option<ChildContext>), Result<Derivation, DisambiguationError>>;
+
+fn choose<NT, TV>(
+    forest: &ParseForest<NT, TV>,
+    filters: &[&dyn ForestFilter<NT, TV>],
+    id: ForestNodeId,
+    context: Option<ChildContext>,
+    memo: &mut ChoiceMemo,
+) -> Result<Derivation, DisambiguationError>
+where
+    NT: Ord + Clone,
+{
+    if let ForestNode::Token { position } = forest.node(id) {
+        return Ok(Derivation::Token(*position));
+    }
+    if let Some(result) = memo.get(&(id, context)) {
+        return result.clone();
+    }
+    //自分自身を経由する導出は選ばない.
+    memo.insert(
+        (id, context),
+        Err(DisambiguationError::Rejected { node: id }),
+    );
+
+    let mut candidates: Vec<&PackedNode> = forest.packed(id).iter().collect();
+    for filter in filters {
+        candidates = filter.filter(forest, context.as_ref(), candidates);
+    }
+    let mut viable = vec![];
+    let mut result = Ok(());
+    'candidate: for packed in candidates {
+        let mut children = vec![];
+        for (position, child) in packed.children.iter().enumerate() {
+            let context = ChildContext {
+                rule: packed.rule,
+                position,
+                arity: packed.children.len(),
+            };
+            match choose(forest, filters, *child, Some(context), memo) {
+                Ok(derivation) => children.push(derivation),
+                Err(DisambiguationError::Rejected { .. }) => continue 'candidate,
+                Err(error) => {
+                    result = Err(error);
+                    continue 'candidate;
+                }
+            }
+        }
+        viable.push(Derivation::Node {
+            node: id,
+            rule: packed.rule,
+            children,
+        });
+    }
+    let result = match (viable.len(), result) {
+        (1, _) => Ok(viable.pop().unwrap()),
+        (0, Err(error)) => Err(error),
+        (0, Ok(())) => Err(DisambiguationError::Rejected { node: id }),
+        (candidates, _) => Err(DisambiguationError::Ambiguous {
+            node: id,
+            candidates,
+        }),
+    };
+    memo.insert((id, context), result.clone());
+    result
+}
+
+#[cfg(test)]
+mod test {
+    use super::{
+        Associativity, ChildContext, DisambiguationError, Predicate, PreferShift, Priority,
+    };
+    use crate::bnf::test::{chars, Char};
+    use crate::bnf::{Expr, Grammer, IntoKind, ReduceArgs, Symbol};
+    use crate::glr::GlrParser;
+    use crate::item_set::generate_canonical_automaton;
+    use crate::parsing_table::{
+        canonical_automaton_to_lr0_parser_with_resolution, ValueStackSymbol,
+    };
+    use crate::resolution::Resolution;
+    use crate::sppf::{PackedNode, ParseForest};
+    use std::sync::Arc;
+
+    #[derive(Ord, PartialOrd, PartialEq, Eq, Clone, Debug)]
+    enum NT {
+        E,
+    }
+
+    /// 括弧を補った式
+    #[derive(Debug, PartialEq)]
+    struct Text(String);
+
+    impl IntoKind<NT> for Text {
+        fn into_kind(&self) -> NT {
+            NT::E
+        }
+    }
+
+    fn binary(mut args: ReduceArgs<NT, Text, Char>) -> Text {
+        match (args.remove(0), args.remove(0), args.remove(0)) {
+            (
+                ValueStackSymbol::NonTerm(Text(l)),
+                ValueStackSymbol::Term(op),
+                ValueStackSymbol::NonTerm(Text(r)),
+            ) => Text(format!("({}{}{})", l, op, r)),
+            _ => panic!("unexpected arguments"),
+        }
+    }
+
+    fn parser() -> GlrParser<NT, Char, Text, Char> {
+        // E -> E + E | E * E | 1
+        let grammer: Grammer<NT, Char, Text, Char> = Grammer {
+            rules: vec![
+                Expr {
+                    left: NT::E,
+                    right: vec![
+                        Symbol::NonTerm(NT::E),
+                        Symbol::Term(Char('+')),
+                        Symbol::NonTerm(NT::E),
+                    ],
+                    reduce_action: Some(Box::new(binary)),
+                },
+                Expr {
+                    left: NT::E,
+                    right: vec![
+                        Symbol::NonTerm(NT::E),
+                        Symbol::Term(Char('*')),
+                        Symbol::NonTerm(NT::E),
+                    ],
+                    reduce_action: Some(Box::new(binary)),
+                },
+                Expr {
+                    left: NT::E,
+                    right: vec![Symbol::Term(Char('1'))],
+                    reduce_action: Some(Box::new(|_| Text("1".to_string()))),
+                },
+            ],
+        };
+        let (automaton, reduce_action) =
+            generate_canonical_automaton(grammer, NT::E, None).unwrap();
+        // GLR は競合しているセルのすべての動作を使うので,どの選び方でもよい.
+        let table = canonical_automaton_to_lr0_parser_with_resolution(
+            &automaton,
+            reduce_action,
+            &Resolution::PreferShift,
+            &mut (),
+        )
+        .unwrap();
+        GlrParser::new(Arc::new(table))
+    }
+
+    /// 2番目の記号が op の規則の番号
+    fn rule_number(parser: &GlrParser<NT, Char, Text, Char>, op: Char) -> usize {
+        parser
+            .table()
+            .rules()
+            .iter()
+            .position(|rule| rule.right.get(1) == Some(&Symbol::Term(op)))
+            .unwrap()
+    }
+
+    fn text(result: ValueStackSymbol<NT, Text, Char>) -> String {
+        match result {
+            ValueStackSymbol::NonTerm(Text(text)) => text,
+            _ => panic!("expected reduced value"),
+        }
+    }
+
+    #[test]
+    fn test_count_derivations() {
+        let parser = parser();
+        let forest = parser.parse(chars("1+1*1+1")).unwrap();
+        assert_eq!(forest.count_derivations(), 5);
+        assert_eq!(forest.derivations(usize::MAX).len(), 5);
+        assert_eq!(forest.derivations(3).len(), 3);
+        assert!(!forest.ambiguities().is_empty());
+
+        let forest = parser.parse(chars("1+1")).unwrap();
+        assert_eq!(forest.count_derivations(), 1);
+        assert_eq!(
+            forest.export_derivation_as_sexpr(&forest.derivations(1)[0]),
+            "(E (E '1') '+' (E '1'))"
+        );
+    }
+
+    #[test]
+    fn test_filters() {
+        let parser = parser();
+        let add = rule_number(&parser, Char('+'));
+        let mul = rule_number(&parser, Char('*'));
+
+        let forest = parser.parse(chars("1+1")).unwrap();
+        assert_eq!(text(parser.evaluate(forest, &[]).unwrap()), "(1+1)");
+
+        let forest = parser.parse(chars("1+1+1")).unwrap();
+        assert!(matches!(
+            parser.evaluate(forest, &[]),
+            Err(DisambiguationError::Ambiguous { candidates: 2, .. })
+        ));
+
+        let left = Associativity::Left(vec![add]);
+        let forest = parser.parse(chars("1+1+1")).unwrap();
+        assert_eq!(
+            text(parser.evaluate(forest, &[&left]).unwrap()),
+            "((1+1)+1)"
+        );
+
+        let forest = parser.parse(chars("1+1+1")).unwrap();
+        assert_eq!(
+            text(parser.evaluate(forest, &[&PreferShift]).unwrap()),
+            "(1+(1+1))"
+        );
+
+        let priority = Priority::new(vec![vec![mul], vec![add]]);
+        let forest = parser.parse(chars("1+1*1+1")).unwrap();
+        assert_eq!(
+            text(parser.evaluate(forest, &[&priority, &left]).unwrap()),
+            "((1+(1*1))+1)"
+        );
+
+        let non_assoc = Associativity::NonAssoc(vec![add]);
+        let forest = parser.parse(chars("1+1+1")).unwrap();
+        assert!(matches!(
+            parser.evaluate(forest, &[&non_assoc]),
+            Err(DisambiguationError::Rejected { .. })
+        ));
+
+        //掛け算を使わない導出だけ残す.
+        let no_mul = Predicate(
+            |_: &ParseForest<NT, Char>, _: Option<&ChildContext>, packed: &PackedNode| {
+                packed.rule != mul
+            },
+        );
+        let forest = parser.parse(chars("1*1")).unwrap();
+        assert!(matches!(
+            parser.evaluate(forest, &[&no_mul]),
+            Err(DisambiguationError::Rejected { .. })
+        ));
+    }
+}
@@ -0,0 +1,448 @@
+use crate::{
+    bnf::{EOFSupply, IntoKind},
+    cst::ConcreteSyntaxTree,
+    forest_filter::{select, DisambiguationError, ForestFilter},
+    parse_session::ParseError,
+    parsing_table::{ActionKind, ParseTable, ValueStackSymbol},
+    sppf::{Derivation, ForestNodeId, PackedNode, ParseForest},
+};
+use std::{collections::BTreeMap, fmt::Debug, sync::Arc};
+
+type GssNodeId = usize;
+
+/// graph structured stack の節
+struct GssNode {
+    state: usize,
+    /// この節が作られた入力の位置
+    level: usize,
+    /// (下の節,その間にある記号の森の節)
+    edges: Vec<(GssNodeId, ForestNodeId)>,
+}
+
+/// 富田法 (GLR) の構文解析器.
+///
+/// 表の競合しているセルではすべての動作を試し,スタックをグラフにして枝分かれを共有する.
+/// 結果はすべての構文木をまとめた共有圧縮構文森になる.
+pub struct GlrParser<NT, T, NTV, TV>
+where
+    NT: Debug + Clone + Eq + Ord,
+    T: Debug + Clone + Eq + Ord,
+    NTV: IntoKind<NT>,
+    TV: IntoKind<T>,
+{
+    table: Arc<ParseTable<NT, T, NTV, TV>>,
+}
+
+impl<NT, T, NTV, TV> GlrParser<NT, T, NTV, TV>
+where
+    NT: Debug + Clone + Eq + Ord,
+    T: Debug + Clone + Eq + Ord,
+    NTV: IntoKind<NT>,
+    TV: IntoKind<T> + EOFSupply<TV>,
+{
+    pub fn new(table: Arc<ParseTable<NT, T, NTV, TV>>) -> Self {
+        Self { table }
+    }
+
+    pub fn table(&self) -> &ParseTable<NT, T, NTV, TV> {
+        &self.table
+    }
+
+    /// 絞り込みで森から構文木を1つ選び,還元動作で評価する.
+    ///
+    /// 還元動作のない規則は `ParseSession` と同じく具象構文木になる.
+    pub fn evaluate(
+        &self,
+        forest: ParseForest<NT, TV>,
+        filters: &[&dyn ForestFilter<NT, TV>],
+    ) -> Result<ValueStackSymbol<NT, NTV, TV>, DisambiguationError> {
+        let derivation = select(&forest, filters)?;
+        let mut tokens: Vec<Option<TV>> = forest.into_tokens().into_iter().map(Some).collect();
+        self.evaluate_derivation(derivation, &mut tokens)
+    }
+
+    fn evaluate_derivation(
+        &self,
+        derivation: Derivation,
+        tokens: &mut [Option<TV>],
+    ) -> Result<ValueStackSymbol<NT, NTV, TV>, DisambiguationError> {
+        match derivation {
+            Derivation::Token(position) => Ok(ValueStackSymbol::Term(
+                tokens[position].take().expect("token used twice"),
+            )),
+            Derivation::Node { rule, children, .. } => {
+                let args = children
+                    .into_iter()
+                    .map(|child| self.evaluate_derivation(child, tokens))
+                    .collect::<Result<_, _>>()?;
+                let lr0item = self.table.rule(rule).unwrap();
+                match self.table.reduce_action(rule) {
+                    Some(function) => function
+                        .call(args)
+                        .map(ValueStackSymbol::NonTerm)
+                        .ok_or(DisambiguationError::ActionMismatch { rule }),
+                    None => Ok(ValueStackSymbol::Tree(ConcreteSyntaxTree {
+                        rule,
+                        left: lr0item.left.clone(),
+                        children: args,
+                    })),
+                }
+            }
+        }
+    }
+
+    /// tokens を構文解析する. 入力の末尾には `EOFSupply` で得た終端記号を補う.
+    pub fn parse<I>(&self, tokens: I) -> Result<ParseForest<NT, TV>, ParseError<T>>
+    where
+        I: IntoIterator<Item = TV>,
+    {
+        let table = &self.table;
+        let mut forest = ParseForest::new();
+        let mut gss = vec![GssNode {
+            state: table.start_state(),
+            level: 0,
+            edges: vec![],
+        }];
+        //今の位置の節. 状態ごとに1つ.
+        let mut frontier: BTreeMap<usize, GssNodeId> =
+            std::iter::once((table.start_state(), 0)).collect();
+        let mut tokens = tokens.into_iter();
+        let mut level = 0;
+        loop {
+            let token = tokens.next();
+            let kind = match &token {
+                Some(token) => token.into_kind(),
+                None => TV::eof().into_kind(),
+            };
+            Self::reduce_all(table, &mut gss, &mut frontier, &mut forest, level, &kind);
+
+            if token.is_none() {
+                //受理できる節から開始記号の森の節を探す.
+                let root = frontier
+                    .values()
+                    .filter(|v| {
+                        table
+                            .actions(gss[**v].state, &kind)
+                            .contains(&ActionKind::Accept)
+                    })
+                    .flat_map(|v| gss[*v].edges.iter())
+                    .find(|(u, _)| gss[*u].level == 0)
+                    .map(|(_, label)| *label);
+                return match root {
+                    Some(root) => {
+                        forest.set_root(root);
+                        Ok(forest)
+                    }
+                    None => Err(ParseError::UnexpectedToken {
+                        state: gss[*frontier.values().next().unwrap()].state,
+                        token: kind,
+                    }),
+                };
+            }
+
+            //シフト
+            let label = forest.push_token(token.unwrap());
+            let mut next: BTreeMap<usize, GssNodeId> = BTreeMap::new();
+            for v in frontier.values() {
+                for action in table.actions(gss[*v].state, &kind) {
+                    if let ActionKind::Shift(s) = action {
+                        let w = *next.entry(*s).or_insert_with(|| {
+                            gss.push(GssNode {
+                                state: *s,
+                                level: level + 1,
+                                edges: vec![],
+                            });
+                            gss.len() - 1
+                        });
+                        gss[w].edges.push((*v, label));
+                    }
+                }
+            }
+            if next.is_empty() {
+                return Err(ParseError::UnexpectedToken {
+                    state: gss[*frontier.values().next().unwrap()].state,
+                    token: kind,
+                });
+            }
+            frontier = next;
+            level += 1;
+        }
+    }
+
+    /// 今の位置でできる還元を,新しい節も辺もできなくなるまで繰り返す.
+    fn reduce_all(
+        table: &ParseTable<NT, T, NTV, TV>,
+        gss: &mut Vec<GssNode>,
+        frontier: &mut BTreeMap<usize, GssNodeId>,
+        forest: &mut ParseForest<NT, TV>,
+        level: usize,
+        kind: &T,
+    ) {
+        let mut changed = true;
+        while changed {
+            changed = false;
+            let nodes: Vec<GssNodeId> = frontier.values().copied().collect();
+            for v in nodes {
+                for action in table.actions(gss[v].state, kind) {
+                    let rule_number = match action {
+                        ActionKind::Reduce(rule_number) => *rule_number,
+                        _ => continue,
+                    };
+                    let rule = table.rule(rule_number).unwrap();
+                    let reduced_over_shift = table
+                        .actions(gss[v].state, kind)
+                        .iter()
+                        .any(|action| matches!(action, ActionKind::Shift(_)));
+                    for (u, children) in Self::paths(gss, v, rule.right.len()) {
+                        let symbol = forest.symbol_node(rule.left.clone(), gss[u].level, level);
+                        forest.add_packed(
+                            symbol,
+                            PackedNode {
+                                rule: rule_number,
+                                children,
+                                reduced_over_shift,
+                            },
+                        );
+                        let goto = match table.goto(gss[u].state, &rule.left) {
+                            Some(goto) => goto,
+                            None => continue,
+                        };
+                        let w = *frontier.entry(goto).or_insert_with(|| {
+                            changed = true;
+                            gss.push(GssNode {
+                                state: goto,
+                                level,
+                                edges: vec![],
+                            });
+                            gss.len() - 1
+                        });
+                        if !gss[w].edges.contains(&(u, symbol)) {
+                            gss[w].edges.push((u, symbol));
+                            changed = true;
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    /// v から長さ len の道をたどった先の節と,道の上の森の節 (左から順)
+    fn paths(gss: &[GssNode], v: GssNodeId, len: usize) -> Vec<(GssNodeId, Vec<ForestNodeId>)> {
+        if len == 0 {
+            return vec![(v, vec![])];
+        }
+        let mut result = vec![];
+        for (u, label) in &gss[v].edges {
+            for (end, mut labels) in Self::paths(gss, *u, len - 1) {
+                labels.push(*label);
+                result.push((end, labels));
+            }
+        }
+        result
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::GlrParser;
+    use crate::bnf::test::{chars, Char};
+    use crate::bnf::{Expr, Grammer, Symbol};
+    use crate::earley::EarleyParser;
+    use crate::item_set::generate_canonical_automaton;
+    use crate::parsing_table::canonical_automaton_to_lr0_parser_with_resolution;
+    use crate::resolution::Resolution;
+    use crate::sppf::ForestNode;
+    use std::sync::Arc;
+
+    #[derive(Ord, PartialOrd, PartialEq, Eq, Clone, Debug)]
+    enum NT {
+        E,
+    }
+
+    fn ambiguous_parser() -> GlrParser<NT, Char, NT, Char> {
+        // E -> E + E | 1
+        let grammer: Grammer<NT, Char, NT, Char> = Grammer {
+            rules: vec![
+                Expr {
+                    left: NT::E,
+                    right: vec![
+                        Symbol::NonTerm(NT::E),
+                        Symbol::Term(Char('+')),
+                        Symbol::NonTerm(NT::E),
+                    ],
+                    reduce_action: None,
+                },
+                Expr {
+                    left: NT::E,
+                    right: vec![Symbol::Term(Char('1'))],
+                    reduce_action: None,
+                },
+            ],
+        };
+        let (automaton, reduce_action) =
+            generate_canonical_automaton(grammer, NT::E, None).unwrap();
+        // GLR は競合しているセルのすべての動作を使うので,どの選び方でもよい.
+        let table = canonical_automaton_to_lr0_parser_with_resolution(
+            &automaton,
+            reduce_action,
+            &Resolution::PreferShift,
+            &mut (),
+        )
+        .unwrap();
+        GlrParser::new(Arc::new(table))
+    }
+
+    #[test]
+    fn test_glr_ambiguous() {
+        let parser = ambiguous_parser();
+
+        let forest = parser.parse(chars("1")).unwrap();
+        assert!(!forest.is_ambiguous());
+
+        let forest = parser.parse(chars("1+1")).unwrap();
+        assert!(!forest.is_ambiguous());
+
+        // (1+1)+1 と 1+(1+1)
+        let forest = parser.parse(chars("1+1+1")).unwrap();
+        assert!(forest.is_ambiguous());
+        match forest.node(forest.root().unwrap()) {
+            ForestNode::Symbol {
+                left,
+                start,
+                end,
+                packed,
+            } => {
+                assert_eq!((left, *start, *end), (&NT::E, 0, 5));
+                assert_eq!(packed.len(), 2);
+            }
+            ForestNode::Token { .. } => panic!("root must be a symbol node"),
+        }
+
+        assert!(parser.parse(chars("1+")).is_err());
+        assert!(parser.parse(chars("1+1 1")).is_err());
+    }
+
+    /// A =>+ A の文法では森が循環するが,導出を並べても止まる.
+    #[test]
+    fn test_glr_cyclic() {
+        // E -> E | 1
+        let grammer: Grammer<NT, Char, NT, Char> = Grammer {
+            rules: vec![
+                Expr {
+                    left: NT::E,
+                    right: vec![Symbol::NonTerm(NT::E)],
+                    reduce_action: None,
+                },
+                Expr {
+                    left: NT::E,
+                    right: vec![Symbol::Term(Char('1'))],
+                    reduce_action: None,
+                },
+            ],
+        };
+        let (automaton, reduce_action) =
+            generate_canonical_automaton(grammer, NT::E, None).unwrap();
+        let parser = GlrParser::new(Arc::new(
+            canonical_automaton_to_lr0_parser_with_resolution(
+                &automaton,
+                reduce_action,
+                &Resolution::PreferShift,
+                &mut (),
+            )
+            .unwrap(),
+        ));
+        let forest = parser.parse(chars("1")).unwrap();
+        let root = forest.root().unwrap();
+        // E(0,1) は E -> 1 と E -> E(0,1) の2つの導出を持つ.
+        match forest.node(root) {
+            ForestNode::Symbol { packed, .. } => {
+                assert!(packed.iter().any(|packed| packed.children == vec![root]))
+            }
+            ForestNode::Token { .. } => panic!("root must be a symbol node"),
+        }
+        let derivations = forest.derivations(10);
+        assert_eq!(derivations.len(), 1);
+        assert_eq!(
+            forest.export_derivation_as_sexpr(&derivations[0]),
+            "(E '1')"
+        );
+    }
+
+    /// 空規則で競合する文法でも Earley 法と同じ文を受理する.
+    #[test]
+    fn test_glr_nullable() {
+        #[derive(Ord, PartialOrd, PartialEq, Eq, Clone, Debug)]
+        enum NT {
+            S,
+            A,
+        }
+        // S -> A A x | S S , A -> ε | a
+        let grammer = || -> Grammer<NT, Char, NT, Char> {
+            Grammer {
+                rules: vec![
+                    Expr {
+                        left: NT::S,
+                        right: vec![
+                            Symbol::NonTerm(NT::A),
+                            Symbol::NonTerm(NT::A),
+                            Symbol::Term(Char('x')),
+                        ],
+                        reduce_action: None,
+                    },
+                    Expr {
+                        left: NT::S,
+                        right: vec![Symbol::NonTerm(NT::S), Symbol::NonTerm(NT::S)],
+                        reduce_action: None,
+                    },
+                    Expr {
+                        left: NT::A,
+                        right: vec![],
+                        reduce_action: None,
+                    },
+                    Expr {
+                        left: NT::A,
+                        right: vec![Symbol::Term(Char('a'))],
+                        reduce_action: None,
+                    },
+                ],
+            }
+        };
+        let (automaton, reduce_action) =
+            generate_canonical_automaton(grammer(), NT::S, None).unwrap();
+        let parser = GlrParser::new(Arc::new(
+            canonical_automaton_to_lr0_parser_with_resolution(
+                &automaton,
+                reduce_action,
+                &Resolution::PreferShift,
+                &mut (),
+            )
+            .unwrap(),
+        ));
+        let earley = EarleyParser::new(grammer(), NT::S);
+
+        let mut inputs: Vec<Vec<Char>> = vec![vec![]];
+        for _ in 0..5 {
+            inputs = inputs
+                .iter()
+                .flat_map(|input| {
+                    [Char('a'), Char('x')].iter().map(move |c| {
+                        let mut input = input.clone();
+                        input.push(*c);
+                        input
+                    })
+                })
+                .collect();
+            for input in &inputs {
+                assert_eq!(
+                    parser.parse(input.iter().copied()).is_ok(),
+                    earley.recognize(input),
+                    "{:?}",
+                    input
+                );
+            }
+        }
+        // x x は S S で,それぞれの x の前の A A は空になる.
+        let forest = parser.parse(chars("xx")).unwrap();
+        assert!(!forest.is_ambiguous());
+    }
+}
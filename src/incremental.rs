@@ -0,0 +1,436 @@
+use crate::{
+    bnf::{EOFSupply, IntoKind},
+    parse_session::ParseError,
+    parsing_table::{ActionKind, ParseTable},
+};
+use std::{collections::VecDeque, fmt::Debug, ops::Range, rc::Rc, sync::Arc};
+
+/// 増分構文解析で作る構文木の節.
+///
+/// 節を作ったときの LR 状態と先読みを覚えておき,編集後に同じ状態・同じ先読みで
+/// この節に出会ったら,中身を解析し直さずにそのままシフトする (Wagner–Graham の状態照合).
+#[derive(Debug)]
+pub struct ParseNode<NT, T, TV> {
+    /// この節をスタックに積む直前の状態
+    pub state: usize,
+    /// 還元したときの先読み. 字句の場合は None.
+    pub lookahead: Option<T>,
+    /// 覆っている字句の数
+    pub token_len: usize,
+    pub kind: ParseNodeKind<NT, T, TV>,
+}
+
+#[derive(Debug)]
+pub enum ParseNodeKind<NT, T, TV> {
+    Token(TV),
+    Node {
+        rule: usize,
+        left: NT,
+        children: Vec<Rc<ParseNode<NT, T, TV>>>,
+    },
+}
+
+impl<NT, T, TV> ParseNode<NT, T, TV>
+where
+    NT: Debug,
+    TV: IntoKind<T>,
+    T: Debug,
+{
+    /// 一番左の字句
+    fn first_token(&self) -> Option<&TV> {
+        match &self.kind {
+            ParseNodeKind::Token(tv) => Some(tv),
+            ParseNodeKind::Node { children, .. } => {
+                children.iter().find_map(|child| child.first_token())
+            }
+        }
+    }
+
+    /// 1行の S式 として書き出す.
+    pub fn export_as_sexpr(&self) -> String {
+        match &self.kind {
+            ParseNodeKind::Token(tv) => format!("{:?}", tv.into_kind()),
+            ParseNodeKind::Node { left, children, .. } => {
+                let mut buffer = format!("({:?}", left);
+                for child in children {
+                    buffer.push(' ');
+                    buffer.push_str(&child.export_as_sexpr());
+                }
+                buffer.push(')');
+                buffer
+            }
+        }
+    }
+}
+
+/// 直前の解析でどれだけ再利用できたか
+#[derive(Debug, Default, PartialEq, Eq, Clone, Copy)]
+pub struct ReparseStats {
+    /// そのままシフトした部分木の数
+    pub reused_nodes: usize,
+    /// 表を引いてシフトし直した字句の数
+    pub shifted_tokens: usize,
+}
+
+/// 解析を待っている入力の要素
+enum Pending<NT, T, TV> {
+    /// 編集の影響を受けていない古い部分木
+    Subtree(Rc<ParseNode<NT, T, TV>>),
+    /// 新しく入力された字句
+    Token(TV),
+}
+
+type StackEntry<NT, T, TV> = (usize, Option<Rc<ParseNode<NT, T, TV>>>);
+
+/// 構文木を保持して,字句の列の編集ごとに変わった部分だけを解析し直す.
+pub struct IncrementalParser<NT, T, NTV, TV>
+where
+    NT: Debug + Clone + Eq + Ord,
+    T: Debug + Clone + Eq + Ord,
+    NTV: IntoKind<NT>,
+    TV: IntoKind<T>,
+{
+    table: Arc<ParseTable<NT, T, NTV, TV>>,
+    tree: Option<Rc<ParseNode<NT, T, TV>>>,
+    stats: ReparseStats,
+}
+
+impl<NT, T, NTV, TV> IncrementalParser<NT, T, NTV, TV>
+where
+    NT: Debug + Clone + Eq + Ord,
+    T: Debug + Clone + Eq + Ord,
+    NTV: IntoKind<NT>,
+    TV: IntoKind<T> + EOFSupply<TV> + Clone,
+{
+    pub fn new(table: Arc<ParseTable<NT, T, NTV, TV>>) -> Self {
+        Self {
+            table,
+            tree: None,
+            stats: ReparseStats::default(),
+        }
+    }
+
+    /// 現在の構文木
+    pub fn tree(&self) -> Option<&Rc<ParseNode<NT, T, TV>>> {
+        self.tree.as_ref()
+    }
+
+    pub fn stats(&self) -> ReparseStats {
+        self.stats
+    }
+
+    /// 全体を解析する.
+    pub fn parse(&mut self, tokens: Vec<TV>) -> Result<&Rc<ParseNode<NT, T, TV>>, ParseError<T>> {
+        self.tree = None;
+        self.edit(0..0, tokens)
+    }
+
+    /// 字句の列の range (字句の番号) を new_tokens に置き換えて解析し直す.
+    ///
+    /// 文字列の編集は,呼び出し側で影響を受けた字句を字句解析し直して字句の範囲に直しておく.
+    /// range が逆向きか今の字句の数を超えていれば `ParseError::InvalidRange` を返す.
+    /// 失敗した場合は元の構文木を残す.
+    pub fn edit(
+        &mut self,
+        range: Range<usize>,
+        new_tokens: Vec<TV>,
+    ) -> Result<&Rc<ParseNode<NT, T, TV>>, ParseError<T>> {
+        let len = self.tree.as_ref().map_or(0, |root| root.token_len);
+        if range.start > range.end || range.end > len {
+            return Err(ParseError::InvalidRange {
+                start: range.start,
+                end: range.end,
+                len,
+            });
+        }
+        //編集範囲に重ならない最大の部分木を左から並べて,間に新しい字句を入れる.
+        let mut input = VecDeque::new();
+        let mut right = VecDeque::new();
+        if let Some(root) = &self.tree {
+            collect_undamaged(root, 0, &range, &mut input, &mut right);
+        }
+        input.extend(new_tokens.into_iter().map(Pending::Token));
+        input.extend(right);
+
+        self.stats = ReparseStats::default();
+        let tree = self.run(input)?;
+        self.tree = Some(tree);
+        Ok(self.tree.as_ref().unwrap())
+    }
+
+    /// input の先頭から offset 番目以降で最初に現れる字句の種類
+    fn next_kind(input: &VecDeque<Pending<NT, T, TV>>, offset: usize) -> T {
+        input
+            .iter()
+            .skip(offset)
+            .find_map(|pending| match pending {
+                Pending::Subtree(node) => node.first_token().map(|tv| tv.into_kind()),
+                Pending::Token(tv) => Some(tv.into_kind()),
+            })
+            .unwrap_or_else(|| TV::eof().into_kind())
+    }
+
+    fn run(
+        &mut self,
+        mut input: VecDeque<Pending<NT, T, TV>>,
+    ) -> Result<Rc<ParseNode<NT, T, TV>>, ParseError<T>> {
+        let table = self.table.clone();
+        //(状態,その状態に入るときに積んだ節)
+        let mut stack: Vec<StackEntry<NT, T, TV>> = vec![(table.start_state(), None)];
+        loop {
+            let top = stack.last().unwrap().0;
+            let kind = match input.pop_front() {
+                Some(Pending::Subtree(node)) => match &node.kind {
+                    ParseNodeKind::Node { left, children, .. } => {
+                        let goto = table.goto(top, left).filter(|_| {
+                            node.state == top && node.lookahead == Some(Self::next_kind(&input, 0))
+                        });
+                        if let Some(goto) = goto {
+                            //同じ状態で作られた部分木なので中身を解析し直さずにシフトする.
+                            self.stats.reused_nodes += 1;
+                            stack.push((goto, Some(node)));
+                        } else {
+                            //状態が違うので子に分解する.
+                            for child in children.iter().rev() {
+                                input.push_front(Pending::Subtree(child.clone()));
+                            }
+                        }
+                        continue;
+                    }
+                    ParseNodeKind::Token(tv) => {
+                        input.push_front(Pending::Token(tv.clone()));
+                        continue;
+                    }
+                },
+                Some(Pending::Token(tv)) => {
+                    let kind = tv.into_kind();
+                    input.push_front(Pending::Token(tv));
+                    kind
+                }
+                None => TV::eof().into_kind(),
+            };
+            match table.action(top, &kind) {
+                Some(ActionKind::Accept) => {
+                    return match (input.is_empty(), stack.pop()) {
+                        (true, Some((_, Some(node)))) => Ok(node),
+                        (false, _) => Err(ParseError::TrailingInput(Self::next_kind(&input, 0))),
+                        _ => Err(ParseError::UnexpectedEndOfInput),
+                    };
+                }
+                Some(ActionKind::Reduce(rule_number)) => {
+                    let rule = table.rule(*rule_number).unwrap();
+                    let children: Vec<_> = stack
+                        .split_off(stack.len() - rule.right.len())
+                        .into_iter()
+                        .map(|(_, node)| node.unwrap())
+                        .collect();
+                    let state = stack.last().unwrap().0;
+                    let goto = table
+                        .goto(state, &rule.left)
+                        .unwrap_or_else(|| panic!("({},{:?}) -> ?", state, rule.left));
+                    let node = ParseNode {
+                        state,
+                        lookahead: Some(kind),
+                        token_len: children.iter().map(|child| child.token_len).sum(),
+                        kind: ParseNodeKind::Node {
+                            rule: *rule_number,
+                            left: rule.left.clone(),
+                            children,
+                        },
+                    };
+                    stack.push((goto, Some(Rc::new(node))));
+                }
+                Some(ActionKind::Shift(next_state)) => {
+                    let tv = match input.pop_front() {
+                        Some(Pending::Token(tv)) => tv,
+                        _ => return Err(ParseError::UnexpectedEndOfInput),
+                    };
+                    self.stats.shifted_tokens += 1;
+                    let node = ParseNode {
+                        state: top,
+                        lookahead: None,
+                        token_len: 1,
+                        kind: ParseNodeKind::Token(tv),
+                    };
+                    stack.push((*next_state, Some(Rc::new(node))));
+                }
+                Some(ActionKind::Error) | None => {
+                    return Err(ParseError::UnexpectedToken {
+                        state: top,
+                        token: kind,
+                    })
+                }
+            }
+        }
+    }
+}
+
+/// start から始まる node のうち range に重ならない最大の部分木を集める.
+///
+/// range より左のものは left に,右のものは right に入れる. range 内の字句は捨てる.
+fn collect_undamaged<NT, T, TV>(
+    node: &Rc<ParseNode<NT, T, TV>>,
+    start: usize,
+    range: &Range<usize>,
+    left: &mut VecDeque<Pending<NT, T, TV>>,
+    right: &mut VecDeque<Pending<NT, T, TV>>,
+) {
+    let end = start + node.token_len;
+    if end <= range.start {
+        left.push_back(Pending::Subtree(node.clone()));
+    } else if start >= range.end {
+        right.push_back(Pending::Subtree(node.clone()));
+    } else if let ParseNodeKind::Node { children, .. } = &node.kind {
+        let mut offset = start;
+        for child in children {
+            collect_undamaged(child, offset, range, left, right);
+            offset += child.token_len;
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{IncrementalParser, ReparseStats};
+    use crate::bnf::test::{chars, Char};
+    use crate::bnf::{Expr, Grammer, Symbol};
+    use crate::item_set::generate_canonical_automaton;
+    use crate::parse_session::ParseError;
+    use crate::parsing_table::{canonical_automaton_to_lr0_parser, ParseTable};
+    use std::sync::Arc;
+
+    #[derive(Ord, PartialOrd, PartialEq, Eq, Clone, Debug)]
+    enum NT {
+        E,
+        P,
+    }
+
+    fn table() -> Arc<ParseTable<NT, Char, NT, Char>> {
+        // E -> E + P | P , P -> ( E ) | 1
+        let grammer: Grammer<NT, Char, NT, Char> = Grammer {
+            rules: vec![
+                Expr {
+                    left: NT::E,
+                    right: vec![
+                        Symbol::NonTerm(NT::E),
+                        Symbol::Term(Char('+')),
+                        Symbol::NonTerm(NT::P),
+                    ],
+                    reduce_action: None,
+                },
+                Expr {
+                    left: NT::E,
+                    right: vec![Symbol::NonTerm(NT::P)],
+                    reduce_action: None,
+                },
+                Expr {
+                    left: NT::P,
+                    right: vec![
+                        Symbol::Term(Char('(')),
+                        Symbol::NonTerm(NT::E),
+                        Symbol::Term(Char(')')),
+                    ],
+                    reduce_action: None,
+                },
+                Expr {
+                    left: NT::P,
+                    right: vec![Symbol::Term(Char('1'))],
+                    reduce_action: None,
+                },
+            ],
+        };
+        let (automaton, reduce_action) =
+            generate_canonical_automaton(grammer, NT::E, None).unwrap();
+        Arc::new(canonical_automaton_to_lr0_parser(&automaton, reduce_action).unwrap())
+    }
+
+    fn full_parse(src: &str) -> String {
+        let mut parser = IncrementalParser::new(table());
+        parser
+            .parse(chars(src).collect())
+            .unwrap()
+            .export_as_sexpr()
+    }
+
+    #[test]
+    fn test_incremental_reparse() {
+        let mut parser = IncrementalParser::new(table());
+        let src = "1+(1+1)+1+1";
+        parser.parse(chars(src).collect()).unwrap();
+        assert_eq!(parser.stats().shifted_tokens, src.len());
+        assert_eq!(parser.tree().unwrap().token_len, src.len());
+
+        // 1+(1+1)+1+1 -> 1+(1+(1))+1+1
+        // 1 の部分木 4 つをそのまま使い,残りの字句 9 個をシフトし直す.
+        let tree = parser.edit(5..6, chars("(1)").collect()).unwrap();
+        assert_eq!(tree.export_as_sexpr(), full_parse("1+(1+(1))+1+1"));
+        assert_eq!(
+            parser.stats(),
+            ReparseStats {
+                reused_nodes: 4,
+                shifted_tokens: 9
+            }
+        );
+
+        // 末尾に追加. 根は先読みが変わるので分解し,最後の +1 より左の部分木を使う.
+        let tree = parser.edit(13..13, chars("+1").collect()).unwrap();
+        assert_eq!(tree.export_as_sexpr(), full_parse("1+(1+(1))+1+1+1"));
+        assert_eq!(
+            parser.stats(),
+            ReparseStats {
+                reused_nodes: 1,
+                shifted_tokens: 4
+            }
+        );
+
+        // 削除
+        let tree = parser.edit(1..9, vec![]).unwrap();
+        assert_eq!(tree.export_as_sexpr(), full_parse("1+1+1+1"));
+        assert_eq!(
+            parser.stats(),
+            ReparseStats {
+                reused_nodes: 4,
+                shifted_tokens: 3
+            }
+        );
+
+        // 何も変えなければ根をそのまま使う
+        parser.edit(0..0, vec![]).unwrap();
+        assert_eq!(
+            parser.stats(),
+            ReparseStats {
+                reused_nodes: 1,
+                shifted_tokens: 0
+            }
+        );
+    }
+
+    #[test]
+    fn test_incremental_error_keeps_tree() {
+        let mut parser = IncrementalParser::new(table());
+        parser.parse(chars("1+1").collect()).unwrap();
+        assert!(parser.edit(1..2, vec![Char('(')]).is_err());
+        assert_eq!(parser.tree().unwrap().export_as_sexpr(), full_parse("1+1"));
+
+        // 範囲の外や逆向きの範囲は編集しない.
+        assert_eq!(
+            parser.edit(4..4, chars("+1").collect()).err(),
+            Some(ParseError::InvalidRange {
+                start: 4,
+                end: 4,
+                len: 3
+            })
+        );
+        #[allow(clippy::reversed_empty_ranges)]
+        let reversed = 2..1;
+        assert_eq!(
+            parser.edit(reversed, vec![]).err(),
+            Some(ParseError::InvalidRange {
+                start: 2,
+                end: 1,
+                len: 3
+            })
+        );
+        assert_eq!(parser.tree().unwrap().export_as_sexpr(), full_parse("1+1"));
+    }
+}
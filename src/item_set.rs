@@ -1,9 +1,12 @@
 use std::{
-    collections::{BTreeMap, BTreeSet},
+    collections::{BTreeMap, BTreeSet, HashMap},
     fmt::Debug,
 };
 
-use crate::bnf::{Grammer, IntoKind, ReduceAction, Symbol};
+use crate::alphabet::{Alphabet, AlphabetMismatch};
+use crate::bnf::{Derived, EOFSupply, Grammer, IntoKind, ReduceAction, Symbol};
+use crate::observer::{BuildEvent, BuildObserver, ConflictKind};
+use crate::typed_action::{check_tree_arguments, TypeMismatch};
 
 #[derive(Debug)]
 pub struct ItemClosure0<NT, T>(pub BTreeSet<LR0Item<NT, T>>)
@@ -11,6 +14,45 @@ where
     NT: Ord + Eq + Clone + Debug,
     T: Ord + Eq + Clone + Debug;
 
+/// 文法から正準オートマトンを作れない.
+#[derive(Debug, PartialEq, Eq)]
+pub enum GrammerError<NT, T>
+where
+    NT: Ord + Eq + Clone,
+    T: Ord + Eq + Clone,
+{
+    /// 記号の並びが文法と合わない.
+    Alphabet(AlphabetMismatch<NT, T>),
+    /// 還元動作の型が文法と合わない.
+    Type(TypeMismatch<NT, T>),
+}
+
+impl<NT, T> From<AlphabetMismatch<NT, T>> for GrammerError<NT, T>
+where
+    NT: Ord + Eq + Clone,
+    T: Ord + Eq + Clone,
+{
+    fn from(error: AlphabetMismatch<NT, T>) -> Self {
+        GrammerError::Alphabet(error)
+    }
+}
+
+impl<NT, T> From<TypeMismatch<NT, T>> for GrammerError<NT, T>
+where
+    NT: Ord + Eq + Clone,
+    T: Ord + Eq + Clone,
+{
+    fn from(error: TypeMismatch<NT, T>) -> Self {
+        GrammerError::Type(error)
+    }
+}
+
+/// 規則番号ごとの還元動作
+pub type ReduceActionTable<NT, NTV, TV> = BTreeMap<RuleId, ReduceAction<NT, NTV, TV>>;
+/// 正準オートマトンと規則番号ごとの還元動作
+pub type GeneratedAutomaton<NT, T, NTV, TV> =
+    (CanonicalAutomaton<NT, T>, ReduceActionTable<NT, NTV, TV>);
+
 #[derive(Ord, PartialOrd, PartialEq, Eq, Clone)]
 pub struct LR0Item<NT, T>
 where
@@ -119,6 +161,19 @@ pub fn generate_lr0_item_closure<NT, T>(
     lr0_items: &[LR0Item<NT, T>],
     i: &[LR0Item<NT, T>],
 ) -> Vec<LR0Item<NT, T>>
+where
+    NT: Ord + Eq + Clone + Debug,
+    T: Ord + Eq + Clone + Debug,
+{
+    generate_lr0_item_closure_with(lr0_items, i, &mut ())
+}
+
+/// `generate_lr0_item_closure` と同じだが,導入項を要求・追加したことを observer に知らせる.
+pub fn generate_lr0_item_closure_with<NT, T>(
+    lr0_items: &[LR0Item<NT, T>],
+    i: &[LR0Item<NT, T>],
+    observer: &mut impl BuildObserver<NT, T>,
+) -> Vec<LR0Item<NT, T>>
 where
     NT: Ord + Eq + Clone + Debug,
     T: Ord + Eq + Clone + Debug,
@@ -153,7 +208,7 @@ where
         }
         //追加していく
         for require in requires {
-            println!("required {:?}", require);
+            observer.notify(BuildEvent::ClosureRequired(&require));
             let induction_terms = induction_terms
                 .iter()
                 .filter(|lr0_item| lr0_item.left == require)
@@ -162,7 +217,7 @@ where
         }
         changed = ss != i_dash;
         if changed {
-            println!("added items");
+            observer.notify(BuildEvent::ClosureAdded);
         }
     }
     i_dash
@@ -189,6 +244,22 @@ pub fn generate_goto_set<NT, T, NTV, TV>(
     lr0_set: &[LR0Item<NT, T>],
     symbol: &Symbol<NT, T>,
 ) -> Vec<LR0Item<NT, T>>
+where
+    NT: Ord + Eq + Clone + Debug,
+    T: Ord + Eq + Clone + Debug,
+    NTV: IntoKind<NT>,
+    TV: IntoKind<T>,
+{
+    generate_goto_set_with(grammer, lr0_set, symbol, &mut ())
+}
+
+/// `generate_goto_set` と同じだが,クロージャをとる項を observer に知らせる.
+pub fn generate_goto_set_with<NT, T, NTV, TV>(
+    grammer: &Grammer<NT, T, NTV, TV>,
+    lr0_set: &[LR0Item<NT, T>],
+    symbol: &Symbol<NT, T>,
+    observer: &mut impl BuildObserver<NT, T>,
+) -> Vec<LR0Item<NT, T>>
 where
     NT: Ord + Eq + Clone + Debug,
     T: Ord + Eq + Clone + Debug,
@@ -197,14 +268,9 @@ where
 {
     let full_set = generate_lr0_item_set(grammer);
     //ドットの直後に symbolがあるものを集めて.
-    let target_items = lr0_set.iter().filter(|item| {
-        if test_symbol_after_dot(item, symbol) {
-            println!("closure <= {}", item);
-            true
-        } else {
-            false
-        }
-    });
+    let target_items = lr0_set
+        .iter()
+        .filter(|item| test_symbol_after_dot(item, symbol));
     //ドットを一つすすめる.
     let i: Vec<LR0Item<NT, T>> = target_items
         .map(|item| LR0Item {
@@ -214,193 +280,567 @@ where
         })
         .collect();
     if !i.is_empty() {
-        println!("generate for ");
-        for item in i.iter() {
-            println!("{}", item);
+        observer.notify(BuildEvent::GotoKernel(&i));
+    }
+    generate_lr0_item_closure_with(&full_set, &i, observer)
+}
+
+/// 記号を拡大文法の記号にする.
+pub fn augment_symbol<NT, T>(symbol: &Symbol<NT, T>) -> Symbol<Derived<NT>, T>
+where
+    NT: Ord + Eq + Clone,
+    T: Ord + Eq + Clone,
+{
+    match symbol {
+        Symbol::Term(t) => Symbol::Term(t.clone()),
+        Symbol::NonTerm(nt) => Symbol::NonTerm(Derived::Original(nt.clone())),
+    }
+}
+
+/// 拡大文法の項を元の文法の項に戻す. 追加した開始記号 S' の項なら None.
+pub fn original_item<NT, T>(item: &LR0Item<Derived<NT>, T>) -> Option<LR0Item<NT, T>>
+where
+    NT: Ord + Eq + Clone + Debug,
+    T: Ord + Eq + Clone + Debug,
+{
+    let left = match &item.left {
+        Derived::Original(nt) => nt.clone(),
+        Derived::Fresh(..) => return None,
+    };
+    let right = item
+        .right
+        .iter()
+        .map(|symbol| match symbol {
+            Symbol::Term(t) => Some(Symbol::Term(t.clone())),
+            Symbol::NonTerm(Derived::Original(nt)) => Some(Symbol::NonTerm(nt.clone())),
+            Symbol::NonTerm(Derived::Fresh(..)) => None,
+        })
+        .collect::<Option<Vec<_>>>()?;
+    Some(LR0Item {
+        left,
+        right,
+        dot_pos: item.dot_pos,
+    })
+}
+
+/// 正準オートマトンの状態番号. 見つけた順につける.
+pub type StateId = usize;
+/// 規則番号. `Grammer::rules` での位置で, 追加した S' -> S $ は最後の番号になる.
+pub type RuleId = usize;
+/// 記号の番号
+type SymbolId = usize;
+
+/// LR(0)項を規則番号とドットの位置で表したもの.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct ItemId {
+    pub rule: RuleId,
+    pub dot: usize,
+}
+
+/// 拡大文法の規則. 記号は番号で持つ.
+struct IndexedRule {
+    left: SymbolId,
+    right: Vec<SymbolId>,
+}
+
+/// 正準オートマトン.
+///
+/// 項は `ItemId`, 状態は `StateId` で表し,規則の右辺を写さずに作る.
+/// 状態は整列した核で区別する. クロージャは遷移を調べるときに計算して,すべての状態の分を保存しておく.
+/// 状態番号は開始状態から幅優先で見つけた順で, dot・LaTeX・構文解析表で同じ番号を使う.
+pub struct CanonicalAutomaton<NT, T>
+where
+    NT: Ord + Eq + Clone + Debug,
+    T: Ord + Eq + Clone + Debug,
+{
+    /// 記号. 遷移を調べる順 (非終端記号, 終端記号, $, S') に並ぶ.
+    symbols: Vec<Symbol<Derived<NT>, T>>,
+    rules: Vec<IndexedRule>,
+    /// 非終端記号ごとの規則
+    rules_by_left: Vec<Vec<RuleId>>,
+    /// 状態ごとの核. 整列してある.
+    kernels: Vec<Vec<ItemId>>,
+    /// 状態ごとのクロージャ. 核が先に並ぶ. 作っている途中でまだ調べていない状態は空.
+    closures: Vec<Vec<ItemId>>,
+    /// 状態ごとの遷移 (記号, 遷移先). 記号の番号順に並ぶ.
+    transitions: Vec<Vec<(SymbolId, StateId)>>,
+}
+
+impl<NT, T> CanonicalAutomaton<NT, T>
+where
+    NT: Ord + Eq + Clone + Debug,
+    T: Ord + Eq + Clone + Debug,
+{
+    /// 状態の数
+    pub fn len(&self) -> usize {
+        self.kernels.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.kernels.is_empty()
+    }
+
+    /// 開始状態. S' -> ・S $ を含む.
+    pub fn start_state(&self) -> StateId {
+        0
+    }
+
+    /// 遷移を調べる順に並んだ記号
+    pub fn symbols(&self) -> &[Symbol<Derived<NT>, T>] {
+        &self.symbols
+    }
+
+    /// 状態 state の核
+    pub fn kernel(&self, state: StateId) -> &[ItemId] {
+        &self.kernels[state]
+    }
+
+    /// 状態 state の項. 核の後に,クロージャで加えた項が続く.
+    pub fn items(&self, state: StateId) -> &[ItemId] {
+        &self.closures[state]
+    }
+
+    /// 追加した規則 S' -> S $ の番号. 文法の規則の数と同じ.
+    pub fn augmented_rule(&self) -> RuleId {
+        self.rules.len() - 1
+    }
+
+    /// 規則 r_{rule} を完全項で表したもの
+    pub fn rule(&self, rule: RuleId) -> LR0Item<Derived<NT>, T> {
+        self.item(ItemId {
+            rule,
+            dot: self.rules[rule].right.len(),
+        })
+    }
+
+    /// 項を LR0Item に直す.
+    pub fn item(&self, item: ItemId) -> LR0Item<Derived<NT>, T> {
+        let rule = &self.rules[item.rule];
+        LR0Item {
+            left: match &self.symbols[rule.left] {
+                Symbol::NonTerm(nt) => nt.clone(),
+                Symbol::Term(_) => unreachable!("left hand side must be a nonterminal"),
+            },
+            right: rule
+                .right
+                .iter()
+                .map(|symbol| self.symbols[*symbol].clone())
+                .collect(),
+            dot_pos: item.dot,
+        }
+    }
+
+    /// 完全項か
+    pub fn is_complete(&self, item: ItemId) -> bool {
+        item.dot == self.rules[item.rule].right.len()
+    }
+
+    /// ドットの直後の記号
+    pub fn symbol_after_dot(&self, item: ItemId) -> Option<&Symbol<Derived<NT>, T>> {
+        self.rules[item.rule]
+            .right
+            .get(item.dot)
+            .map(|symbol| &self.symbols[*symbol])
+    }
+
+    /// 状態 state で還元する規則. 完全項の規則番号で, S' -> S $・ は受理なので除く.
+    pub fn reduce_rules(&self, state: StateId) -> Vec<RuleId> {
+        self.items(state)
+            .iter()
+            .filter(|item| self.is_complete(**item) && item.rule != self.augmented_rule())
+            .map(|item| item.rule)
+            .collect()
+    }
+
+    /// 状態 state の競合
+    pub fn conflict(&self, state: StateId) -> Option<ConflictKind> {
+        let reduce_rules = self.reduce_rules(state);
+        if reduce_rules.len() > 1 {
+            Some(ConflictKind::ReduceReduce)
+        //シフトできる項があれば競合. 空規則の完全項 A -> ・ は非終端記号の項とは並んでよい.
+        } else if reduce_rules.len() == 1
+            && self
+                .items(state)
+                .iter()
+                .any(|item| matches!(self.symbol_after_dot(*item), Some(Symbol::Term(_))))
+        {
+            Some(ConflictKind::ShiftReduce)
+        } else {
+            None
         }
     }
-    generate_lr0_item_closure(&full_set, &i)
+
+    /// 状態 state からの遷移 (記号, 遷移先)
+    pub fn transitions(
+        &self,
+        state: StateId,
+    ) -> impl Iterator<Item = (&Symbol<Derived<NT>, T>, StateId)> {
+        self.transitions[state]
+            .iter()
+            .map(|(symbol, to)| (&self.symbols[*symbol], *to))
+    }
+
+    /// 核 kernel の状態を加える.
+    fn add_state(&mut self, kernel: Vec<ItemId>) -> StateId {
+        self.kernels.push(kernel);
+        self.closures.push(vec![]);
+        self.transitions.push(vec![]);
+        self.kernels.len() - 1
+    }
+
+    /// 核 kernel のクロージャをとる. 導入項を要求・追加したことを observer に知らせる.
+    fn closure(
+        &self,
+        kernel: Vec<ItemId>,
+        observer: &mut impl BuildObserver<Derived<NT>, T>,
+    ) -> Vec<ItemId> {
+        let mut items = kernel;
+        let mut required = vec![false; self.symbols.len()];
+        let mut i = 0;
+        while i < items.len() {
+            let item = items[i];
+            if let Some(&symbol) = self.rules[item.rule].right.get(item.dot) {
+                if !required[symbol] {
+                    required[symbol] = true;
+                    if let Symbol::NonTerm(nt) = &self.symbols[symbol] {
+                        observer.notify(BuildEvent::ClosureRequired(nt));
+                    }
+                    let rules = &self.rules_by_left[symbol];
+                    items.extend(rules.iter().map(|rule| ItemId {
+                        rule: *rule,
+                        dot: 0,
+                    }));
+                    if !rules.is_empty() {
+                        observer.notify(BuildEvent::ClosureAdded);
+                    }
+                }
+            }
+            i += 1;
+        }
+        items
+    }
 }
 
 /// 正準オートマトンを作成する.
 /// * grammer 文法,
-/// * start_symbol 開始記号(左辺のみにあり,OR規則でないこと)
-///   ``` S -> S' のような形になっている Sのこと. ```
+/// * start_symbol 開始記号
+/// * order 遷移を調べる記号の順番. None なら文法の規則に現れる順.
+///
+/// 文法に新しい開始記号 S' と規則 ``` S' -> S $ ``` を追加した拡大文法から作る.
+/// $ は `EOFSupply` で得た終端記号.
+/// order が文法の記号と合わないか,具象構文木を受け取る型付きの還元動作が
+/// 還元動作のある規則の記号を受け取っていればエラー.
 pub fn generate_canonical_automaton<NT, T, NTV, TV>(
     grammer: Grammer<NT, T, NTV, TV>,
     start_symbol: NT,
-    symbols: &[Symbol<NT, T>],
-) -> (
-    Vec<Vec<LR0Item<NT, T>>>,
-    BTreeMap<(Vec<LR0Item<NT, T>>, Symbol<NT, T>), Vec<LR0Item<NT, T>>>,
-    BTreeMap<LR0Item<NT, T>, ReduceAction<NTV, TV>>,
-)
+    order: Option<&Alphabet<NT, T>>,
+) -> Result<GeneratedAutomaton<NT, T, NTV, TV>, GrammerError<NT, T>>
 where
     NT: Ord + Clone + Eq + Debug,
-    T: Ord + Clone + Eq + Debug,
+    T: Ord + Clone + Eq + Debug + EOFSupply<T>,
     NTV: IntoKind<NT>,
     TV: IntoKind<T>,
 {
-    let items = generate_lr0_item_set(&grammer);
-    let start_rule = items.iter().find(|item| item.left.clone() == start_symbol);
-    if let Some(start_rule) = start_rule {
-        let ie = generate_lr0_item_closure(&items, &[start_rule.clone()]);
-        let mut x = vec![ie];
-        let mut y = vec![];
-        //状態遷移表
-        let mut delta = BTreeMap::new();
-        let mut cnt = 0;
-        while !x.is_empty() {
-            let i = x.remove(0);
-            y.push(i.clone());
-            println!("trying to generate next states for I{}={:?}", cnt, i);
-            symbols.iter().for_each(|symbol| {
-                println!("/////////////////////////////////////");
-                if i.iter().any(|item| test_symbol_after_dot(item, symbol)) {
-                    println!(
-                        "I' = Goto(I{},{})",
-                        cnt,
-                        match symbol {
-                            Symbol::Term(t) => {
-                                format!("{:?}", t)
-                            }
-                            Symbol::NonTerm(nt) => {
-                                format!("{:?}", nt)
-                            }
-                        }
-                    );
-                    let i_dash = generate_goto_set(&grammer, &i, symbol);
-                    println!("I' = {:?}", i_dash);
-                    if !i_dash.is_empty() {
-                        if !y.contains(&i_dash) & !x.contains(&i_dash) {
-                            x.push(i_dash.clone());
-                            cnt += 1;
-                        } else {
-                            println!("same goto set geenrated before.");
-                        }
-                        //状態遷移関数に追加
-                        delta.insert((i.clone(), symbol.clone()), i_dash);
-                    }
-                } else {
-                    println!(
-                        "we can't find {} after dot so we skip generating I'",
-                        match symbol {
-                            Symbol::Term(t) => {
-                                format!("{:?}", t)
-                            }
-                            Symbol::NonTerm(nt) => {
-                                format!("{:?}", nt)
-                            }
-                        }
-                    );
-                }
+    generate_canonical_automaton_with(grammer, start_symbol, order, &mut ())
+}
+
+/// `generate_canonical_automaton` と同じだが,状態を調べたことと遷移を observer に知らせる.
+pub fn generate_canonical_automaton_with<NT, T, NTV, TV>(
+    grammer: Grammer<NT, T, NTV, TV>,
+    start_symbol: NT,
+    order: Option<&Alphabet<NT, T>>,
+    observer: &mut impl BuildObserver<Derived<NT>, T>,
+) -> Result<GeneratedAutomaton<NT, T, NTV, TV>, GrammerError<NT, T>>
+where
+    NT: Ord + Clone + Eq + Debug,
+    T: Ord + Clone + Eq + Debug + EOFSupply<T>,
+    NTV: IntoKind<NT>,
+    TV: IntoKind<T>,
+{
+    check_tree_arguments(&grammer)?;
+    let alphabet = match order {
+        Some(order) => Alphabet::ordered(&grammer, order.terms(), order.nonterms(), &[T::eof()])?,
+        None => Alphabet::from_grammer(&grammer),
+    };
+    //記号に番号をつける.
+    let mut symbols: Vec<_> = alphabet.symbols().iter().map(augment_symbol).collect();
+    if !symbols.contains(&Symbol::Term(T::eof())) {
+        symbols.push(Symbol::Term(T::eof()));
+    }
+    let extended_start = Symbol::NonTerm(Derived::Fresh(start_symbol.clone(), 1));
+    symbols.push(extended_start.clone());
+    let symbol_ids: BTreeMap<_, _> = symbols
+        .iter()
+        .enumerate()
+        .map(|(id, symbol)| (symbol.clone(), id))
+        .collect();
+    let id_of = |symbol: &Symbol<Derived<NT>, T>| symbol_ids[symbol];
+
+    //規則に番号をつける. 文法の規則は同じ順で,その後に S' -> S $.
+    let mut rules: Vec<IndexedRule> = grammer
+        .rules
+        .iter()
+        .map(|rule| IndexedRule {
+            left: id_of(&Symbol::NonTerm(Derived::Original(rule.left.clone()))),
+            right: rule
+                .right
+                .iter()
+                .map(|symbol| id_of(&augment_symbol(symbol)))
+                .collect(),
+        })
+        .collect();
+    rules.push(IndexedRule {
+        left: id_of(&extended_start),
+        right: vec![
+            id_of(&Symbol::NonTerm(Derived::Original(start_symbol))),
+            id_of(&Symbol::Term(T::eof())),
+        ],
+    });
+    let mut rules_by_left = vec![vec![]; symbols.len()];
+    for (id, rule) in rules.iter().enumerate() {
+        rules_by_left[rule.left].push(id);
+    }
+
+    let mut automaton = CanonicalAutomaton {
+        symbols,
+        rules,
+        rules_by_left,
+        kernels: vec![],
+        closures: vec![],
+        transitions: vec![],
+    };
+    //核から状態を引く.
+    let mut kernels: HashMap<Vec<ItemId>, StateId> = HashMap::new();
+    let start_kernel = vec![ItemId {
+        rule: automaton.augmented_rule(),
+        dot: 0,
+    }];
+    kernels.insert(start_kernel.clone(), automaton.add_state(start_kernel));
+
+    let mut current = 0;
+    while current < automaton.len() {
+        if current != automaton.start_state() && observer.enabled() {
+            let kernel: Vec<_> = automaton
+                .kernel(current)
+                .iter()
+                .map(|item| automaton.item(*item))
+                .collect();
+            observer.notify(BuildEvent::GotoKernel(&kernel));
+        }
+        let items = automaton.closure(automaton.kernels[current].clone(), observer);
+        if observer.enabled() {
+            let items: Vec<_> = items.iter().map(|item| automaton.item(*item)).collect();
+            observer.notify(BuildEvent::Expand {
+                state: current,
+                items: &items,
             });
         }
-        let mut reduce_action = BTreeMap::new();
-        let mut rules = grammer.rules;
-        for rule in rules.drain(..) {
-            let lr0_item = LR0Item {
-                left: rule.left,
-                dot_pos: rule.right.len(),
-                right: rule.right,
+        //ドットの直後の記号ごとに,ドットを一つすすめた項を集める.
+        let mut gotos: BTreeMap<SymbolId, Vec<ItemId>> = BTreeMap::new();
+        for item in &items {
+            if let Some(&symbol) = automaton.rules[item.rule].right.get(item.dot) {
+                gotos.entry(symbol).or_default().push(ItemId {
+                    rule: item.rule,
+                    dot: item.dot + 1,
+                });
+            }
+        }
+        for (symbol, mut kernel) in gotos {
+            //項の順番によらず同じ核なら同じ状態.
+            kernel.sort();
+            let (next, new_state) = match kernels.get(&kernel) {
+                Some(next) => (*next, false),
+                None => {
+                    let next = automaton.add_state(kernel.clone());
+                    kernels.insert(kernel, next);
+                    (next, true)
+                }
             };
-            if let Some(action) = rule.reduce_action {
-                reduce_action.insert(lr0_item, action);
+            if observer.enabled() {
+                let kernel: Vec<_> = automaton
+                    .kernel(next)
+                    .iter()
+                    .map(|item| automaton.item(*item))
+                    .collect();
+                observer.notify(BuildEvent::Goto {
+                    from: current,
+                    symbol: &automaton.symbols[symbol],
+                    to: next,
+                    kernel: &kernel,
+                    new_state,
+                });
             }
+            //状態遷移関数に追加
+            automaton.transitions[current].push((symbol, next));
         }
-        (y, delta, reduce_action)
-    } else {
-        (vec![], BTreeMap::new(), BTreeMap::new())
+        //クロージャを `items` のために保存する.
+        automaton.closures[current] = items;
+        current += 1;
     }
+
+    let reduce_action = grammer
+        .rules
+        .into_iter()
+        .enumerate()
+        .filter_map(|(rule, expr)| Some((rule, expr.reduce_action?)))
+        .collect();
+    Ok((automaton, reduce_action))
+}
+
+/// `compile_canonical_automaton_to_dot_with_options` の見た目の選択.
+///
+/// `Default` は `compile_canonical_automaton_to_dot` と同じ見た目.
+#[derive(Debug, Clone, Default)]
+pub struct DotOptions {
+    /// 核とクロージャで加えた項の間に線を引く.
+    pub kernel_separator: bool,
+    /// 競合している状態に色をつける.
+    pub highlight_conflicts: bool,
+    /// 還元する状態から Reduce 節へ,規則を書いた辺を引く.
+    pub reduce_edges: bool,
+    /// HTML 風のラベルにする. 項は左揃えの表になる.
+    pub html_labels: bool,
+    /// 左から右に並べる.
+    pub left_to_right: bool,
+}
+
+/// 競合している状態の色
+const CONFLICT_COLOR: &str = "lightcoral";
+
+/// dot の "..." の中に書けるようにする.
+pub fn escape_dot(s: &str) -> String {
+    s.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+/// HTML 風のラベルの中に書けるようにする.
+fn escape_html(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
 }
 
 pub fn compile_canonical_automaton_to_dot<NT, T>(
-    automaton: (
-        &[Vec<LR0Item<NT, T>>],
-        &BTreeMap<(Vec<LR0Item<NT, T>>, Symbol<NT, T>), Vec<LR0Item<NT, T>>>,
-    ),
+    automaton: &CanonicalAutomaton<NT, T>,
     automaton_name: &str,
 ) -> String
 where
     NT: Ord + Eq + Clone + Debug,
     T: Ord + Eq + Clone + Debug,
 {
-    let node_with_id: BTreeMap<Vec<LR0Item<NT, T>>, (usize, bool)> = automaton
-        .0
-        .iter()
-        .enumerate()
-        .map(|(id, set)| {
-            let is_accept = set.iter().any(|item| item.dot_pos == item.right.len());
-            (set.clone(), (id, is_accept))
-        })
-        .collect();
-    println!("nodes = {}", node_with_id.len());
-    format!(
-        "digraph {} {{
-            /*Nodes*/
-            {}
-            /*Relations*/
-            {}
-        }}",
+    compile_canonical_automaton_to_dot_with_options(
+        automaton,
         automaton_name,
-        {
-            use std::fmt::Write;
-            let mut buffer = String::new();
-            for node in node_with_id.iter() {
-                let b = node.0;
-                let node_id = node.1 .0;
-                let is_accept_node = node.1 .1;
+        &DotOptions::default(),
+    )
+}
+
+/// 正準オートマトンを graphviz の dot で書き出す. 見た目は options で選ぶ.
+pub fn compile_canonical_automaton_to_dot_with_options<NT, T>(
+    automaton: &CanonicalAutomaton<NT, T>,
+    automaton_name: &str,
+    options: &DotOptions,
+) -> String
+where
+    NT: Ord + Eq + Clone + Debug,
+    T: Ord + Eq + Clone + Debug,
+{
+    use std::fmt::Write;
+    let mut buffer = String::new();
+    writeln!(&mut buffer, "digraph {} {{", automaton_name).unwrap();
+    if options.left_to_right {
+        writeln!(&mut buffer, "    rankdir=LR;").unwrap();
+    }
+    writeln!(&mut buffer, "    /*Nodes*/").unwrap();
+    for node_id in 0..automaton.len() {
+        let items = automaton.items(node_id);
+        let kernel_len = automaton.kernel(node_id).len();
+        let lines: Vec<String> = items
+            .iter()
+            .map(|item| automaton.item(*item).to_string())
+            .collect();
+        let label = if options.html_labels {
+            let mut rows = String::new();
+            for (i, line) in lines.iter().enumerate() {
+                if options.kernel_separator && i == kernel_len && i < lines.len() {
+                    rows.push_str("<HR/>");
+                }
                 write!(
-                    &mut buffer,
-                    "Node{} [label=\"{}\" shape=\"{}\"];\n",
-                    node_id,
-                    {
-                        /*
-                        format LR(0) items.
-                        */
-                        let mut buffer = String::new();
-                        if let Some((last, left)) = b.split_last() {
-                            left.iter().for_each(|item| {
-                                write!(&mut buffer, "{}\n", item).unwrap();
-                            });
-                            write!(&mut buffer, "{}", last).unwrap();
-                        }
-                        buffer
-                    },
-                    if is_accept_node {
-                        "doublecircle"
-                    } else {
-                        "rectangle"
-                    }
+                    &mut rows,
+                    "<TR><TD ALIGN=\"LEFT\">{}</TD></TR>",
+                    escape_html(line)
                 )
                 .unwrap();
             }
-            buffer
-        },
-        {
-            use std::fmt::Write;
-            let mut buffer = String::new();
-            for ((from, symbol), to) in automaton.1.iter() {
-                write!(
+            format!(
+                "<<TABLE BORDER=\"0\" CELLBORDER=\"0\"><TR><TD><B>I{}</B></TD></TR><HR/>{}</TABLE>>",
+                node_id, rows
+            )
+        } else {
+            let mut lines: Vec<String> = lines.iter().map(|line| escape_dot(line)).collect();
+            if options.kernel_separator && kernel_len < lines.len() {
+                lines.insert(kernel_len, "----".to_owned());
+            }
+            format!("\"{}\"", lines.join("\n"))
+        };
+        let is_accept_node = items.iter().any(|item| automaton.is_complete(*item));
+        let mut attributes = format!(
+            "label={} shape=\"{}\"",
+            label,
+            if is_accept_node {
+                "doublecircle"
+            } else {
+                "rectangle"
+            }
+        );
+        if options.highlight_conflicts && automaton.conflict(node_id).is_some() {
+            write!(
+                &mut attributes,
+                " style=\"filled\" fillcolor=\"{}\"",
+                CONFLICT_COLOR
+            )
+            .unwrap();
+        }
+        writeln!(&mut buffer, "    Node{} [{}];", node_id, attributes).unwrap();
+    }
+    if options.reduce_edges {
+        writeln!(
+            &mut buffer,
+            "    Reduce [label=\"reduce\" shape=\"plaintext\"];"
+        )
+        .unwrap();
+    }
+    writeln!(&mut buffer, "    /*Relations*/").unwrap();
+    for from in 0..automaton.len() {
+        for (symbol, to) in automaton.transitions(from) {
+            let symbol = match symbol {
+                Symbol::Term(t) => format!("{:?}", t),
+                Symbol::NonTerm(nt) => format!("{:?}", nt),
+            };
+            writeln!(
+                &mut buffer,
+                "    Node{} -> Node{} [label=\"{}\"];",
+                from,
+                to,
+                escape_dot(&symbol)
+            )
+            .unwrap();
+        }
+        if options.reduce_edges {
+            for rule in automaton.reduce_rules(from) {
+                let label = format!("r{}: {}", rule, automaton.rule(rule));
+                writeln!(
                     &mut buffer,
-                    "Node{} -> Node{} [label={:?}];\n",
-                    node_with_id.get(from).unwrap().0,
-                    node_with_id.get(to).unwrap().0,
-                    match symbol {
-                        Symbol::Term(t) => {
-                            format!("{:?}", t)
-                        }
-                        Symbol::NonTerm(nt) => {
-                            format!("{:?}", nt)
-                        }
-                    }
+                    "    Node{} -> Reduce [label=\"{}\" style=\"dashed\"];",
+                    from,
+                    escape_dot(&label)
                 )
                 .unwrap();
             }
-
-            buffer
         }
-    )
+    }
+    writeln!(&mut buffer, "}}").unwrap();
+    buffer
 }
 
 pub trait IntoSymbolKind<NT, T, SymbolKind>
@@ -414,17 +854,21 @@ where
 #[cfg(test)]
 mod test {
     use super::generate_lr0_item_set;
-    use crate::bnf::{Expr, Grammer, Symbol};
+    use crate::alphabet::Alphabet;
+    use crate::bnf::test::Char;
+    use crate::bnf::{Derived, EOFSupply, Expr, Grammer, Symbol};
     use crate::item_set::{
-        compile_canonical_automaton_to_dot, generate_canonical_automaton, generate_goto_set,
-        generate_lr0_item_closure, LR0Item,
+        compile_canonical_automaton_to_dot, compile_canonical_automaton_to_dot_with_options,
+        generate_canonical_automaton, generate_goto_set, generate_lr0_item_closure, DotOptions,
+        LR0Item,
     };
+    use crate::parsing_table::{canonical_automaton_to_lr0_parser, ActionKind};
     use NonTerm::{E, S, T};
     use Symbol::NonTerm as NT;
     use Symbol::Term;
     #[test]
     fn test_generate_lr0_item_set() {
-        let grammer: Grammer<NonTerm, char, NonTerm, char> = Grammer {
+        let grammer: Grammer<NonTerm, Char, NonTerm, Char> = Grammer {
             rules: vec![
                 Expr {
                     left: S,
@@ -438,22 +882,22 @@ mod test {
                 },
                 Expr {
                     left: E,
-                    right: vec![Term('('), NT(E), Term(')')],
+                    right: vec![Term(Char('(')), NT(E), Term(Char(')'))],
                     reduce_action: None,
                 },
                 Expr {
                     left: T,
-                    right: vec![Term('n')],
+                    right: vec![Term(Char('n'))],
                     reduce_action: None,
                 },
                 Expr {
                     left: T,
-                    right: vec![Term('+'), NT(T)],
+                    right: vec![Term(Char('+')), NT(T)],
                     reduce_action: None,
                 },
                 Expr {
                     left: T,
-                    right: vec![NT(T), Term('+'), Term('n')],
+                    right: vec![NT(T), Term(Char('+')), Term(Char('n'))],
                     reduce_action: None,
                 },
             ],
@@ -472,13 +916,13 @@ mod test {
             T,
             F,
         }
-        let grammer: Grammer<NT, char, NT, char> = Grammer {
+        let grammer: Grammer<NT, Char, NT, Char> = Grammer {
             rules: vec![
                 Expr {
                     left: NT::E,
                     right: vec![
                         Symbol::NonTerm(NT::E),
-                        Symbol::Term('+'),
+                        Symbol::Term(Char('+')),
                         Symbol::NonTerm(NT::T),
                     ],
                     reduce_action: None,
@@ -492,7 +936,7 @@ mod test {
                     left: NT::T,
                     right: vec![
                         Symbol::NonTerm(NT::T),
-                        Symbol::Term('*'),
+                        Symbol::Term(Char('*')),
                         Symbol::NonTerm(NT::F),
                     ],
                     reduce_action: None,
@@ -504,12 +948,16 @@ mod test {
                 },
                 Expr {
                     left: NT::F,
-                    right: vec![Symbol::Term('('), Symbol::NonTerm(NT::E), Symbol::Term(')')],
+                    right: vec![
+                        Symbol::Term(Char('(')),
+                        Symbol::NonTerm(NT::E),
+                        Symbol::Term(Char(')')),
+                    ],
                     reduce_action: None,
                 },
                 Expr {
                     left: NT::F,
-                    right: vec![Symbol::Term('i')],
+                    right: vec![Symbol::Term(Char('i'))],
                     reduce_action: None,
                 },
             ],
@@ -518,7 +966,7 @@ mod test {
             left: NT::E,
             right: vec![
                 (Symbol::NonTerm(NT::E)),
-                (Symbol::Term('+')),
+                (Symbol::Term(Char('+'))),
                 (Symbol::NonTerm(NT::T)),
             ],
             dot_pos: 2,
@@ -538,13 +986,13 @@ mod test {
             T,
             F,
         }
-        let grammer: Grammer<NT, char, NT, char> = Grammer {
+        let grammer: Grammer<NT, Char, NT, Char> = Grammer {
             rules: vec![
                 Expr {
                     left: NT::E,
                     right: vec![
                         Symbol::NonTerm(NT::E),
-                        Symbol::Term('+'),
+                        Symbol::Term(Char('+')),
                         Symbol::NonTerm(NT::T),
                     ],
                     reduce_action: None,
@@ -558,7 +1006,7 @@ mod test {
                     left: NT::T,
                     right: vec![
                         Symbol::NonTerm(NT::T),
-                        Symbol::Term('*'),
+                        Symbol::Term(Char('*')),
                         Symbol::NonTerm(NT::F),
                     ],
                     reduce_action: None,
@@ -570,12 +1018,16 @@ mod test {
                 },
                 Expr {
                     left: NT::F,
-                    right: vec![Symbol::Term('('), Symbol::NonTerm(NT::E), Symbol::Term(')')],
+                    right: vec![
+                        Symbol::Term(Char('(')),
+                        Symbol::NonTerm(NT::E),
+                        Symbol::Term(Char(')')),
+                    ],
                     reduce_action: None,
                 },
                 Expr {
                     left: NT::F,
-                    right: vec![Symbol::Term('i')],
+                    right: vec![Symbol::Term(Char('i'))],
                     reduce_action: None,
                 },
             ],
@@ -587,12 +1039,12 @@ mod test {
                 left: NT::T,
                 right: vec![
                     (Symbol::NonTerm(NT::T)),
-                    (Symbol::Term('*')),
+                    (Symbol::Term(Char('*'))),
                     (Symbol::NonTerm(NT::F)),
                 ],
                 dot_pos: 1,
             }],
-            &Symbol::Term('*'),
+            &Symbol::Term(Char('*')),
         );
         for item in goto_set {
             println!("{}", item);
@@ -608,7 +1060,7 @@ mod test {
             T,
             F,
         }
-        let grammer: Grammer<NT, char, NT, char> = Grammer {
+        let grammer: Grammer<NT, Char, NT, Char> = Grammer {
             rules: vec![
                 Expr {
                     left: NT::S,
@@ -619,7 +1071,7 @@ mod test {
                     left: NT::E,
                     right: vec![
                         Symbol::NonTerm(NT::E),
-                        Symbol::Term('+'),
+                        Symbol::Term(Char('+')),
                         Symbol::NonTerm(NT::T),
                     ],
                     reduce_action: None,
@@ -633,7 +1085,7 @@ mod test {
                     left: NT::T,
                     right: vec![
                         Symbol::NonTerm(NT::T),
-                        Symbol::Term('*'),
+                        Symbol::Term(Char('*')),
                         Symbol::NonTerm(NT::F),
                     ],
                     reduce_action: None,
@@ -645,51 +1097,219 @@ mod test {
                 },
                 Expr {
                     left: NT::F,
-                    right: vec![Symbol::Term('('), Symbol::NonTerm(NT::E), Symbol::Term(')')],
+                    right: vec![
+                        Symbol::Term(Char('(')),
+                        Symbol::NonTerm(NT::E),
+                        Symbol::Term(Char(')')),
+                    ],
                     reduce_action: None,
                 },
                 Expr {
                     left: NT::F,
-                    right: vec![Symbol::Term('i')],
+                    right: vec![Symbol::Term(Char('i'))],
                     reduce_action: None,
                 },
             ],
         };
 
-        let canonical_automaton = generate_canonical_automaton(
-            grammer,
-            NT::S,
-            &[
-                Symbol::NonTerm(NT::S),
-                Symbol::NonTerm(NT::E),
-                Symbol::NonTerm(NT::T),
-                Symbol::NonTerm(NT::F),
-                Symbol::Term('i'),
-                Symbol::Term('('),
-                Symbol::Term(')'),
-                Symbol::Term('+'),
-                Symbol::Term('*'),
-            ],
+        let order = Alphabet::ordered(
+            &grammer,
+            &[Char('i'), Char('('), Char(')'), Char('+'), Char('*')],
+            &[NT::S, NT::E, NT::T, NT::F],
+            &[],
+        )
+        .unwrap();
+        let canonical_automaton =
+            generate_canonical_automaton(grammer, NT::S, Some(&order)).unwrap();
+        let (automaton, _) = canonical_automaton;
+        //開始状態は追加した S' -> ・S $ から始まる.
+        assert_eq!(
+            format!("{}", automaton.item(automaton.items(0)[0])),
+            "S' -> ・S'$'"
         );
-        let canonical_set = canonical_automaton.0.clone();
         //compress.
-        for items in canonical_set.iter().enumerate() {
-            println!("I{}", items.0);
-            for item in items.1 {
-                println!("{}", item);
+        for state in 0..automaton.len() {
+            println!("I{}", state);
+            for item in automaton.items(state) {
+                println!("{}", automaton.item(*item));
             }
         }
         println!(
             "graph:
         {}
         ",
-            compile_canonical_automaton_to_dot(
-                (&canonical_automaton.0, &canonical_automaton.1),
-                "G1"
-            )
+            compile_canonical_automaton_to_dot(&automaton, "G1")
         )
     }
 
+    /// 規則が数百ある文法でも状態を重複なく作る.
+    #[test]
+    fn test_generate_large_automaton() {
+        #[derive(Ord, PartialOrd, PartialEq, Eq, Clone, Debug)]
+        enum Tok {
+            X(usize),
+            Y(usize),
+            Z,
+            Eof,
+        }
+        impl EOFSupply<Tok> for Tok {
+            fn eof() -> Tok {
+                Tok::Eof
+            }
+        }
+        // A_i -> x_i A_{i+1} | y_i (i < 200) , A_200 -> z
+        let n = 200;
+        let mut rules = vec![];
+        for i in 0..n {
+            rules.push(Expr {
+                left: i,
+                right: vec![Symbol::Term(Tok::X(i)), Symbol::NonTerm(i + 1)],
+                reduce_action: None,
+            });
+            rules.push(Expr {
+                left: i,
+                right: vec![Symbol::Term(Tok::Y(i))],
+                reduce_action: None,
+            });
+        }
+        rules.push(Expr {
+            left: n,
+            right: vec![Symbol::Term(Tok::Z)],
+            reduce_action: None,
+        });
+        let grammer: Grammer<usize, Tok, usize, Tok> = Grammer { rules };
+        let (automaton, _) = generate_canonical_automaton(grammer, 0, None).unwrap();
+        // 開始, S' -> A_0・$, 受理, A_i ごとに x_i, y_i, A_{i+1} で遷移した先, A_200 -> z・
+        assert_eq!(automaton.len(), 3 + 3 * n + 1);
+        // すべての状態のクロージャを保存してある.
+        assert!((0..automaton.len())
+            .all(|state| automaton.items(state).starts_with(automaton.kernel(state))));
+    }
+
+    /// 項の並びが違っても核が同じなら同じ状態になり,
+    /// dot と構文解析表で同じ状態番号を使う.
+    #[test]
+    fn test_kernel_identity() {
+        #[derive(Ord, PartialOrd, PartialEq, Eq, Clone, Debug)]
+        enum NT {
+            S,
+            P,
+            Q,
+            X,
+            Y,
+        }
+        let rule = |left, right| Expr {
+            left,
+            right,
+            reduce_action: None,
+        };
+        // S -> p P | q Q , P -> X | Y , Q -> Y | X , X -> a c , Y -> a d
+        // p の後と q の後で X, Y の項の並びが逆になる.
+        let grammer: Grammer<NT, Char, NT, Char> = Grammer {
+            rules: vec![
+                rule(NT::S, vec![Term(Char('p')), Symbol::NonTerm(NT::P)]),
+                rule(NT::S, vec![Term(Char('q')), Symbol::NonTerm(NT::Q)]),
+                rule(NT::P, vec![Symbol::NonTerm(NT::X)]),
+                rule(NT::P, vec![Symbol::NonTerm(NT::Y)]),
+                rule(NT::Q, vec![Symbol::NonTerm(NT::Y)]),
+                rule(NT::Q, vec![Symbol::NonTerm(NT::X)]),
+                rule(NT::X, vec![Term(Char('a')), Term(Char('c'))]),
+                rule(NT::Y, vec![Term(Char('a')), Term(Char('d'))]),
+            ],
+        };
+        let (automaton, reduce_action) =
+            generate_canonical_automaton(grammer, NT::S, None).unwrap();
+        let goto_a: Vec<_> = (0..automaton.len())
+            .flat_map(|state| automaton.transitions(state))
+            .filter(|(symbol, _)| **symbol == Term(Char('a')))
+            .map(|(_, to)| to)
+            .collect();
+        assert_eq!(goto_a.len(), 2);
+        assert_eq!(goto_a[0], goto_a[1]);
+        let kernel = automaton.kernel(goto_a[0]);
+        assert!(kernel.windows(2).all(|pair| pair[0] < pair[1]));
+        assert_eq!(automaton.items(goto_a[0]), kernel);
+
+        let dot = compile_canonical_automaton_to_dot(&automaton, "G");
+        let table = canonical_automaton_to_lr0_parser(&automaton, reduce_action).unwrap();
+        assert_eq!(table.start_state(), automaton.start_state());
+        for from in 0..automaton.len() {
+            for (symbol, to) in automaton.transitions(from) {
+                assert!(dot.contains(&format!("Node{} -> Node{} ", from, to)));
+                match symbol {
+                    Symbol::Term(Char('$')) => {
+                        assert_eq!(table.action(from, &Char('$')), Some(&ActionKind::Accept))
+                    }
+                    Symbol::Term(t) => {
+                        assert_eq!(table.action(from, t), Some(&ActionKind::Shift(to)))
+                    }
+                    Symbol::NonTerm(Derived::Original(nt)) => {
+                        assert_eq!(table.goto(from, nt), Some(to))
+                    }
+                    Symbol::NonTerm(Derived::Fresh(..)) => panic!("S' has no transition"),
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_dot_options() {
+        // E -> E " E | \\ . 記号の Debug に引用符とバックスラッシュが入る.
+        let grammer: Grammer<NonTerm, Char, NonTerm, Char> = Grammer {
+            rules: vec![
+                Expr {
+                    left: E,
+                    right: vec![NT(E), Term(Char('"')), NT(E)],
+                    reduce_action: None,
+                },
+                Expr {
+                    left: E,
+                    right: vec![Term(Char('\\'))],
+                    reduce_action: None,
+                },
+            ],
+        };
+        let (automaton, _) = generate_canonical_automaton(grammer, E, None).unwrap();
+
+        let dot = compile_canonical_automaton_to_dot(&automaton, "G");
+        assert!(dot.contains(r#"[label="'\"'"]"#));
+        assert!(dot.contains(r#"E -> '\\\\'・"#));
+        assert!(!dot.contains("rankdir") && !dot.contains("Reduce") && !dot.contains("----"));
+
+        let options = DotOptions {
+            kernel_separator: true,
+            highlight_conflicts: true,
+            reduce_edges: true,
+            html_labels: false,
+            left_to_right: true,
+        };
+        let dot = compile_canonical_automaton_to_dot_with_options(&automaton, "G", &options);
+        assert!(dot.contains("rankdir=LR;"));
+        // E -> E'"'E・ と E -> E・'"'E が並ぶ状態だけが競合する.
+        let conflicts = (0..automaton.len())
+            .filter(|state| automaton.conflict(*state).is_some())
+            .count();
+        assert_eq!(conflicts, 1);
+        assert_eq!(dot.matches("fillcolor").count(), conflicts);
+        let reduces: usize = (0..automaton.len())
+            .map(|state| automaton.reduce_rules(state).len())
+            .sum();
+        assert_eq!(dot.matches("-> Reduce").count(), reduces);
+        assert!(dot.contains(r#"[label="r0: E -> E'\"'E・" style="dashed"]"#));
+        // 開始状態は核 S' -> ・E$ の後にクロージャが続く.
+        assert!(dot.contains("Node0 [label=\"E' -> ・E'$'\n----\n"));
+
+        let options = DotOptions {
+            html_labels: true,
+            ..options
+        };
+        let dot = compile_canonical_automaton_to_dot_with_options(&automaton, "G", &options);
+        assert!(dot.contains("<TD ALIGN=\"LEFT\">E -&gt; E'&quot;'E・</TD>"));
+        assert!(dot.contains("<TD ALIGN=\"LEFT\">E -&gt; '\\\\'・</TD>"));
+        // 見出しの下の線と,開始状態の核の下の線
+        assert!(dot.contains("<HR/><TR><TD ALIGN=\"LEFT\">E' -&gt; ・E'$'</TD></TR><HR/>"));
+    }
+
     #[derive(Clone, Ord, PartialOrd, PartialEq, Eq, Debug)]
     enum NonTerm {
         S,
@@ -0,0 +1,25 @@
+pub mod alphabet;
+pub mod bnf;
+pub mod compressed_table;
+pub mod construction_trace;
+pub mod cst;
+pub mod earley;
+pub mod first_set;
+pub mod follow_set;
+pub mod forest_filter;
+pub mod glr;
+pub mod incremental;
+pub mod item_set;
+pub mod ll1;
+pub mod lossless_tree;
+pub mod nullable_set;
+pub mod observer;
+pub mod parse_session;
+pub mod parse_tree;
+pub mod parsing_table;
+pub mod resolution;
+pub mod sppf;
+pub mod table_format;
+pub mod transform;
+pub mod typed_action;
+pub mod validate;
@@ -0,0 +1,473 @@
+use crate::{
+    bnf::{EOFSupply, Grammer, IntoKind, Symbol},
+    cst::ConcreteSyntaxTree,
+    first_set::{first_of_symbols, generate_first_set},
+    follow_set::generate_follow_set,
+    nullable_set::generate_null_set,
+    parse_tree::escape_latex,
+    parsing_table::ValueStackSymbol,
+};
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fmt::{Debug, Write},
+};
+
+/// LL(1) 表の1つのセルに複数の規則が入った.
+#[derive(Debug, PartialEq, Eq)]
+pub struct Ll1Conflict<NT, T> {
+    pub nonterm: NT,
+    pub term: T,
+    /// 競合している規則の番号 ( `Grammer::rules` の位置)
+    pub rules: Vec<usize>,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum Ll1Error<NT, T> {
+    /// 終端記号 expected を期待したが found が来た.
+    UnexpectedToken { expected: T, found: T },
+    /// nonterm を先読み token で展開する規則がない.
+    NoRule { nonterm: NT, token: T },
+    /// nonterm を先読み token で展開する規則が1つに決まらない.
+    Conflict {
+        nonterm: NT,
+        token: T,
+        rules: Vec<usize>,
+    },
+    /// 開始記号を導出した後にも字句が残っている.
+    TrailingInput(T),
+    /// 規則 rule の還元動作が値を受け取れなかった.
+    ActionMismatch { rule: usize },
+}
+
+/// 予測スタックの要素
+enum Prediction<NT, T>
+where
+    T: Ord + Eq + Clone,
+    NT: Ord + Eq + Clone,
+{
+    Symbol(Symbol<NT, T>),
+    /// 規則 r_{rule} の右辺をすべて読んだので還元動作を呼ぶ.
+    Reduce(usize),
+}
+
+/// LL(1) 表と,それで動く予測型構文解析器.
+///
+/// 表は First 集合と Follow 集合から作る. 競合があっても表は作り,
+/// 競合しているセルは `conflicts` で確かめられる. 規則の番号は `Grammer::rules` の位置.
+pub struct Ll1Parser<NT, T, NTV, TV>
+where
+    T: Ord + Eq + Clone,
+    NT: Ord + Eq + Clone,
+    TV: IntoKind<T>,
+    NTV: IntoKind<NT>,
+{
+    grammer: Grammer<NT, T, NTV, TV>,
+    start: NT,
+    eof: T,
+    table: BTreeMap<(NT, T), Vec<usize>>,
+}
+
+impl<NT, T, NTV, TV> Ll1Parser<NT, T, NTV, TV>
+where
+    T: Ord + Eq + Clone + Debug,
+    NT: Ord + Eq + Clone + Debug,
+    TV: IntoKind<T>,
+    NTV: IntoKind<NT>,
+{
+    pub fn new(grammer: Grammer<NT, T, NTV, TV>, start: NT, eof: T) -> Self {
+        let first_sets = generate_first_set(&grammer);
+        let nullable_set = generate_null_set(&grammer);
+        let follow_sets = generate_follow_set(&grammer, start.clone(), eof.clone());
+        let mut table: BTreeMap<(NT, T), Vec<usize>> = BTreeMap::new();
+        for (number, rule) in grammer.rules.iter().enumerate() {
+            let (mut lookahead, nullable) =
+                first_of_symbols(&first_sets, &nullable_set, &rule.right);
+            if nullable {
+                lookahead.extend(follow_sets[&rule.left].iter().cloned());
+            }
+            for t in lookahead {
+                table
+                    .entry((rule.left.clone(), t))
+                    .or_default()
+                    .push(number);
+            }
+        }
+        Self {
+            grammer,
+            start,
+            eof,
+            table,
+        }
+    }
+
+    /// nonterm を先読み t で展開する規則. 競合していれば複数になる.
+    pub fn rules(&self, nonterm: &NT, t: &T) -> &[usize] {
+        self.table
+            .get(&(nonterm.clone(), t.clone()))
+            .map(|rules| rules.as_slice())
+            .unwrap_or(&[])
+    }
+
+    /// 規則が2つ以上入ったセル. 空なら文法は LL(1).
+    pub fn conflicts(&self) -> Vec<Ll1Conflict<NT, T>> {
+        self.table
+            .iter()
+            .filter(|(_, rules)| rules.len() > 1)
+            .map(|((nonterm, term), rules)| Ll1Conflict {
+                nonterm: nonterm.clone(),
+                term: term.clone(),
+                rules: rules.clone(),
+            })
+            .collect()
+    }
+
+    /// tokens を構文解析して,規則の還元動作で評価する.
+    ///
+    /// 入力が尽きた後は終端記号 eof が続くものとして読む. 文法が eof を含むなら,
+    /// その値は `EOFSupply` で得たもの. 入力の最後に eof を1つだけ置いてもよい.
+    /// 還元動作のない規則は具象構文木になる.
+    pub fn parse<I>(&self, tokens: I) -> Result<ValueStackSymbol<NT, NTV, TV>, Ll1Error<NT, T>>
+    where
+        I: IntoIterator<Item = TV>,
+        TV: EOFSupply<TV>,
+    {
+        let mut tokens = tokens.into_iter().peekable();
+        let mut stack = vec![Prediction::Symbol(Symbol::NonTerm(self.start.clone()))];
+        let mut value_stack: Vec<ValueStackSymbol<NT, NTV, TV>> = vec![];
+        //文法の eof を読んだか
+        let mut eof_read = false;
+        while let Some(top) = stack.pop() {
+            match top {
+                Prediction::Symbol(Symbol::Term(t)) => {
+                    let token = tokens.next();
+                    let kind = token
+                        .as_ref()
+                        .map_or(self.eof.clone(), |token| token.into_kind());
+                    if t != kind {
+                        return Err(Ll1Error::UnexpectedToken {
+                            expected: t,
+                            found: kind,
+                        });
+                    }
+                    eof_read |= t == self.eof;
+                    value_stack.push(ValueStackSymbol::Term(token.unwrap_or_else(TV::eof)));
+                }
+                Prediction::Symbol(Symbol::NonTerm(nt)) => {
+                    let kind = tokens
+                        .peek()
+                        .map_or(self.eof.clone(), |token| token.into_kind());
+                    let rule = match self.rules(&nt, &kind) {
+                        [] => {
+                            return Err(Ll1Error::NoRule {
+                                nonterm: nt,
+                                token: kind,
+                            })
+                        }
+                        [rule] => *rule,
+                        rules => {
+                            return Err(Ll1Error::Conflict {
+                                nonterm: nt,
+                                token: kind,
+                                rules: rules.to_vec(),
+                            })
+                        }
+                    };
+                    stack.push(Prediction::Reduce(rule));
+                    for symbol in self.grammer.rules[rule].right.iter().rev() {
+                        stack.push(Prediction::Symbol(symbol.clone()));
+                    }
+                }
+                Prediction::Reduce(rule) => {
+                    let expr = &self.grammer.rules[rule];
+                    let args = value_stack.split_off(value_stack.len() - expr.right.len());
+                    value_stack.push(match &expr.reduce_action {
+                        Some(function) => ValueStackSymbol::NonTerm(
+                            function
+                                .call(args)
+                                .ok_or(Ll1Error::ActionMismatch { rule })?,
+                        ),
+                        None => ValueStackSymbol::Tree(ConcreteSyntaxTree {
+                            rule,
+                            left: expr.left.clone(),
+                            children: args,
+                        }),
+                    });
+                }
+            }
+        }
+        //入力の最後に置いた eof は読み飛ばす.
+        if !eof_read {
+            tokens.next_if(|token| token.into_kind() == self.eof);
+        }
+        if let Some(token) = tokens.next() {
+            return Err(Ll1Error::TrailingInput(token.into_kind()));
+        }
+        Ok(value_stack.pop().unwrap())
+    }
+
+    /// LL(1) 表を LaTeX の tabular として書き出す. 競合しているセルには規則を並べる.
+    /// 記号は `escape_latex` で文字として書く.
+    pub fn export_as_latex_src(&self, terms: &[T], nonterms: &[NT]) -> String {
+        let terms: BTreeSet<_> = terms.iter().collect();
+        let nonterms: BTreeSet<_> = nonterms.iter().collect();
+        let mut buffer = String::new();
+
+        writeln!(
+            &mut buffer,
+            "\\begin{{tabular}}{{{}}}",
+            "l".repeat(1 + terms.len())
+        )
+        .unwrap();
+        let mut header = String::new();
+        terms.iter().for_each(|term| {
+            write!(&mut header, " & {}", escape_latex(&format!("{:?}", term))).unwrap();
+        });
+        writeln!(&mut buffer, "{} \\\\ \\hline", header).unwrap();
+        for nt in nonterms.iter() {
+            let mut row = format!("{} ", escape_latex(&format!("{:?}", nt)));
+            for t in terms.iter() {
+                let rules: Vec<String> = self
+                    .rules(nt, t)
+                    .iter()
+                    .map(|rule| format!("$ r_{{{}}} $", rule))
+                    .collect();
+                write!(&mut row, "& {} ", rules.join(", ")).unwrap();
+            }
+            row.push_str(r"\\\hline");
+            writeln!(&mut buffer, "{}", row).unwrap();
+        }
+        writeln!(&mut buffer, r"\end{{tabular}}").unwrap();
+
+        // export rules.
+        writeln!(&mut buffer, "\\begin{{tabular}}{{ll}}").unwrap();
+        for (number, rule) in self.grammer.rules.iter().enumerate() {
+            let right: Vec<String> = rule
+                .right
+                .iter()
+                .map(|symbol| match symbol {
+                    Symbol::Term(t) => escape_latex(&format!("{:?}", t)),
+                    Symbol::NonTerm(nt) => escape_latex(&format!("{:?}", nt)),
+                })
+                .collect();
+            let right = if right.is_empty() {
+                "$ \\varepsilon $".to_owned()
+            } else {
+                right.join(" ")
+            };
+            writeln!(
+                &mut buffer,
+                "$ r_{{{}}} $ & {} $ \\to $ {} \\\\ \\hline",
+                number,
+                escape_latex(&format!("{:?}", rule.left)),
+                right
+            )
+            .unwrap();
+        }
+        writeln!(&mut buffer, "\\end{{tabular}}").unwrap();
+        buffer
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Ll1Conflict, Ll1Error, Ll1Parser};
+    use crate::bnf::test::{chars, Char};
+    use crate::bnf::{Expr, Grammer, IntoKind, ReduceArgs, Symbol};
+    use crate::parsing_table::ValueStackSymbol;
+
+    #[derive(Ord, PartialOrd, PartialEq, Eq, Clone, Debug)]
+    enum NT {
+        S,
+        E,
+        Edash,
+        T,
+    }
+
+    /// 式の値. E' は左の項に足す値の和を持つ.
+    #[derive(Debug)]
+    struct Value(NT, u32);
+
+    impl IntoKind<NT> for Value {
+        fn into_kind(&self) -> NT {
+            self.0.clone()
+        }
+    }
+
+    fn sum(nt: NT) -> impl Fn(ReduceArgs<NT, Value, Char>) -> Value {
+        move |args| {
+            let total = args
+                .iter()
+                .map(|arg| match arg {
+                    ValueStackSymbol::NonTerm(Value(_, n)) => *n,
+                    ValueStackSymbol::Term(c) => c.0.to_digit(10).unwrap_or(0),
+                    ValueStackSymbol::Tree(_) => 0,
+                })
+                .sum();
+            Value(nt.clone(), total)
+        }
+    }
+
+    fn grammer() -> Grammer<NT, Char, Value, Char> {
+        // E -> T E' , E' -> + T E' | ε , T -> ( E ) | digit
+        let mut rules = vec![
+            Expr {
+                left: NT::E,
+                right: vec![Symbol::NonTerm(NT::T), Symbol::NonTerm(NT::Edash)],
+                reduce_action: Some(Box::new(sum(NT::E))),
+            },
+            Expr {
+                left: NT::Edash,
+                right: vec![
+                    Symbol::Term(Char('+')),
+                    Symbol::NonTerm(NT::T),
+                    Symbol::NonTerm(NT::Edash),
+                ],
+                reduce_action: Some(Box::new(sum(NT::Edash))),
+            },
+            Expr {
+                left: NT::Edash,
+                right: vec![],
+                reduce_action: Some(Box::new(sum(NT::Edash))),
+            },
+            Expr {
+                left: NT::T,
+                right: vec![
+                    Symbol::Term(Char('(')),
+                    Symbol::NonTerm(NT::E),
+                    Symbol::Term(Char(')')),
+                ],
+                reduce_action: Some(Box::new(sum(NT::T))),
+            },
+        ];
+        for digit in ('1'..='3').map(Char) {
+            rules.push(Expr {
+                left: NT::T,
+                right: vec![Symbol::Term(digit)],
+                reduce_action: Some(Box::new(sum(NT::T))),
+            });
+        }
+        Grammer { rules }
+    }
+
+    #[test]
+    fn test_ll1_parse() {
+        let parser = Ll1Parser::new(grammer(), NT::E, Char('$'));
+        assert!(parser.conflicts().is_empty());
+        assert_eq!(parser.rules(&NT::Edash, &Char(')')), &[2]);
+        assert_eq!(parser.rules(&NT::Edash, &Char('$')), &[2]);
+
+        match parser.parse(chars("1+(2+3)+1")) {
+            Ok(ValueStackSymbol::NonTerm(Value(NT::E, 7))) => (),
+            other => panic!("expected 7 but got {:?}", other),
+        }
+        assert_eq!(
+            parser.parse(chars("1+")).err(),
+            Some(Ll1Error::NoRule {
+                nonterm: NT::T,
+                token: Char('$')
+            })
+        );
+        assert_eq!(
+            parser.parse(chars("(1")).err(),
+            Some(Ll1Error::UnexpectedToken {
+                expected: Char(')'),
+                found: Char('$')
+            })
+        );
+        let latex = parser.export_as_latex_src(
+            &[
+                Char('+'),
+                Char('('),
+                Char(')'),
+                Char('1'),
+                Char('2'),
+                Char('3'),
+                Char('$'),
+            ],
+            &[NT::E, NT::Edash, NT::T],
+        );
+        let lines: Vec<_> = latex.lines().collect();
+        assert_eq!(lines[0], r"\begin{tabular}{llllllll}");
+        assert_eq!(
+            lines[1],
+            r" & '\$' & '(' & ')' & '+' & '1' & '2' & '3' \\ \hline"
+        );
+        assert_eq!(
+            lines[3],
+            r"Edash & $ r_{2} $ &  & $ r_{2} $ & $ r_{1} $ &  &  &  \\\hline"
+        );
+        assert_eq!(
+            lines[9],
+            r"$ r_{2} $ & Edash $ \to $ $ \varepsilon $ \\ \hline"
+        );
+        assert_eq!(lines.last(), Some(&r"\end{tabular}"));
+    }
+
+    #[test]
+    fn test_ll1_eof_in_grammer() {
+        // S -> E $ . 文法の $ は入力が尽きた後の eof と合う.
+        let mut grammer = grammer();
+        grammer.rules.push(Expr {
+            left: NT::S,
+            right: vec![Symbol::NonTerm(NT::E), Symbol::Term(Char('$'))],
+            reduce_action: Some(Box::new(sum(NT::S))),
+        });
+        let parser = Ll1Parser::new(grammer, NT::S, Char('$'));
+        assert!(parser.conflicts().is_empty());
+        for input in ["1+2", "1+2$"] {
+            match parser.parse(chars(input)) {
+                Ok(ValueStackSymbol::NonTerm(Value(NT::S, 3))) => (),
+                other => panic!("expected 3 but got {:?}", other),
+            }
+        }
+        // $ の後の字句は読み捨てない.
+        assert_eq!(
+            parser.parse(chars("1$+2")).err(),
+            Some(Ll1Error::TrailingInput(Char('+')))
+        );
+        assert_eq!(
+            parser.parse(chars("1+2$$")).err(),
+            Some(Ll1Error::TrailingInput(Char('$')))
+        );
+        let parser = Ll1Parser::new(self::grammer(), NT::E, Char('$'));
+        assert_eq!(
+            parser.parse(chars("1$+2")).err(),
+            Some(Ll1Error::TrailingInput(Char('+')))
+        );
+    }
+
+    #[test]
+    fn test_ll1_conflict() {
+        // 左再帰 E -> E + T を足すと LL(1) でなくなる.
+        let mut grammer = grammer();
+        grammer.rules.push(Expr {
+            left: NT::E,
+            right: vec![
+                Symbol::NonTerm(NT::E),
+                Symbol::Term(Char('+')),
+                Symbol::NonTerm(NT::T),
+            ],
+            reduce_action: None,
+        });
+        let parser = Ll1Parser::new(grammer, NT::E, Char('$'));
+        let conflicts = parser.conflicts();
+        // E の4つと, Follow(E) に + が入ったことによる E' の1つ
+        assert_eq!(conflicts.len(), 5);
+        assert_eq!(
+            conflicts[0],
+            Ll1Conflict {
+                nonterm: NT::E,
+                term: Char('('),
+                rules: vec![0, 7]
+            }
+        );
+        assert_eq!(
+            parser.parse(chars("1")).err(),
+            Some(Ll1Error::Conflict {
+                nonterm: NT::E,
+                token: Char('1'),
+                rules: vec![0, 7]
+            })
+        );
+    }
+}
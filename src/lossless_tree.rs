@@ -0,0 +1,540 @@
+use crate::{
+    bnf::{EOFSupply, IntoKind, Symbol},
+    parse_session::ParseListener,
+};
+use std::{fmt::Debug, ops::Range, rc::Rc, sync::Arc};
+
+/// 空白や注釈のように文法には現れないが,元の文字列を復元するために残しておく字句.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Trivia<T> {
+    pub kind: T,
+    pub text: String,
+}
+
+/// 前後の trivia を付けた字句. 可逆な構文木を作るときは構文解析器にこれを入力する.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TriviaToken<T> {
+    pub kind: T,
+    pub text: String,
+    pub leading_trivia: Vec<Trivia<T>>,
+    pub trailing_trivia: Vec<Trivia<T>>,
+}
+
+impl<T> IntoKind<T> for TriviaToken<T>
+where
+    T: Clone,
+{
+    fn into_kind(&self) -> T {
+        self.kind.clone()
+    }
+}
+
+impl<T> EOFSupply<TriviaToken<T>> for TriviaToken<T>
+where
+    T: EOFSupply<T>,
+{
+    fn eof() -> TriviaToken<T> {
+        TriviaToken {
+            kind: T::eof(),
+            text: String::new(),
+            leading_trivia: vec![],
+            trailing_trivia: vec![],
+        }
+    }
+}
+
+/// 字句解析器の出力 (種類,文字列) から trivia を取り除いて,隣の字句に付ける.
+///
+/// trivia は直後の字句の leading_trivia になる. 最後の字句より後ろの trivia は
+/// 最後の字句の trailing_trivia になる. trivia しかない入力では何も返さない.
+pub fn attach_trivia<T, I>(tokens: I, is_trivia: impl Fn(&T) -> bool) -> Vec<TriviaToken<T>>
+where
+    I: IntoIterator<Item = (T, String)>,
+{
+    let mut attached: Vec<TriviaToken<T>> = vec![];
+    let mut pending = vec![];
+    for (kind, text) in tokens {
+        if is_trivia(&kind) {
+            pending.push(Trivia { kind, text });
+        } else {
+            attached.push(TriviaToken {
+                kind,
+                text,
+                leading_trivia: std::mem::take(&mut pending),
+                trailing_trivia: vec![],
+            });
+        }
+    }
+    if let Some(last) = attached.last_mut() {
+        last.trailing_trivia = pending;
+    }
+    attached
+}
+
+/// 不変な字句. trivia を含めた長さを持つ.
+#[derive(Debug, PartialEq, Eq)]
+pub struct GreenToken<T> {
+    kind: T,
+    text: String,
+    leading_trivia: Vec<Trivia<T>>,
+    trailing_trivia: Vec<Trivia<T>>,
+}
+
+impl<T> GreenToken<T> {
+    pub fn kind(&self) -> &T {
+        &self.kind
+    }
+    /// trivia を除いた字句の文字列
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+    pub fn leading_trivia(&self) -> &[Trivia<T>] {
+        &self.leading_trivia
+    }
+    pub fn trailing_trivia(&self) -> &[Trivia<T>] {
+        &self.trailing_trivia
+    }
+    fn leading_len(&self) -> usize {
+        self.leading_trivia.iter().map(|t| t.text.len()).sum()
+    }
+    /// trivia を含めた長さ
+    pub fn text_len(&self) -> usize {
+        self.leading_len()
+            + self.text.len()
+            + self
+                .trailing_trivia
+                .iter()
+                .map(|t| t.text.len())
+                .sum::<usize>()
+    }
+    fn write_text(&self, buffer: &mut String) {
+        self.leading_trivia
+            .iter()
+            .for_each(|t| buffer.push_str(&t.text));
+        buffer.push_str(&self.text);
+        self.trailing_trivia
+            .iter()
+            .for_each(|t| buffer.push_str(&t.text));
+    }
+}
+
+/// 不変な節. 同じ部分木は `Arc` で共有できる.
+#[derive(Debug, PartialEq, Eq)]
+pub struct GreenNode<NT, T> {
+    kind: NT,
+    text_len: usize,
+    children: Vec<GreenElement<NT, T>>,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum GreenElement<NT, T> {
+    Node(Arc<GreenNode<NT, T>>),
+    Token(Arc<GreenToken<T>>),
+}
+
+impl<NT, T> Clone for GreenElement<NT, T> {
+    fn clone(&self) -> Self {
+        match self {
+            Self::Node(node) => Self::Node(node.clone()),
+            Self::Token(token) => Self::Token(token.clone()),
+        }
+    }
+}
+
+impl<NT, T> GreenElement<NT, T>
+where
+    NT: Ord + Eq + Clone,
+    T: Ord + Eq + Clone,
+{
+    pub fn kind(&self) -> Symbol<NT, T> {
+        match self {
+            Self::Node(node) => Symbol::NonTerm(node.kind.clone()),
+            Self::Token(token) => Symbol::Term(token.kind.clone()),
+        }
+    }
+    pub fn text_len(&self) -> usize {
+        match self {
+            Self::Node(node) => node.text_len,
+            Self::Token(token) => token.text_len(),
+        }
+    }
+}
+
+impl<NT, T> GreenNode<NT, T> {
+    pub fn new(kind: NT, children: Vec<GreenElement<NT, T>>) -> Self {
+        let text_len = children
+            .iter()
+            .map(|child| match child {
+                GreenElement::Node(node) => node.text_len,
+                GreenElement::Token(token) => token.text_len(),
+            })
+            .sum();
+        GreenNode {
+            kind,
+            text_len,
+            children,
+        }
+    }
+    pub fn kind(&self) -> &NT {
+        &self.kind
+    }
+    pub fn text_len(&self) -> usize {
+        self.text_len
+    }
+    pub fn children(&self) -> &[GreenElement<NT, T>] {
+        &self.children
+    }
+    /// trivia を含めた元の文字列
+    pub fn text(&self) -> String {
+        let mut buffer = String::with_capacity(self.text_len);
+        self.write_text(&mut buffer);
+        buffer
+    }
+    fn write_text(&self, buffer: &mut String) {
+        for child in &self.children {
+            match child {
+                GreenElement::Node(node) => node.write_text(buffer),
+                GreenElement::Token(token) => token.write_text(buffer),
+            }
+        }
+    }
+}
+
+/// シフトと還元に合わせて green tree を組み立てる.
+///
+/// `ParseSession::parse_iter_with` に渡して使う.
+pub struct GreenTreeBuilder<NT, T> {
+    stack: Vec<GreenElement<NT, T>>,
+}
+
+impl<NT, T> Default for GreenTreeBuilder<NT, T> {
+    fn default() -> Self {
+        Self { stack: vec![] }
+    }
+}
+
+impl<NT, T> GreenTreeBuilder<NT, T> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// 受理した後に呼ぶと,開始記号の節を返す.
+    pub fn finish(mut self) -> Option<Arc<GreenNode<NT, T>>> {
+        match (self.stack.pop(), self.stack.is_empty()) {
+            (Some(GreenElement::Node(root)), true) => Some(root),
+            _ => None,
+        }
+    }
+}
+
+impl<NT, T> ParseListener<NT, TriviaToken<T>> for GreenTreeBuilder<NT, T>
+where
+    NT: Clone,
+    T: Clone,
+{
+    fn shift(&mut self, token: &TriviaToken<T>) {
+        let token = token.clone();
+        self.stack.push(GreenElement::Token(Arc::new(GreenToken {
+            kind: token.kind,
+            text: token.text,
+            leading_trivia: token.leading_trivia,
+            trailing_trivia: token.trailing_trivia,
+        })));
+    }
+
+    fn reduce(&mut self, _rule: usize, left: &NT, len: usize) {
+        let children = self.stack.split_off(self.stack.len() - len);
+        self.stack.push(GreenElement::Node(Arc::new(GreenNode::new(
+            left.clone(),
+            children,
+        ))));
+    }
+}
+
+struct NodeData<NT, T> {
+    green: Arc<GreenNode<NT, T>>,
+    parent: Option<SyntaxNode<NT, T>>,
+    offset: usize,
+}
+
+/// green tree の上を動くカーソル. 親への参照と元の文字列での位置を持つ.
+pub struct SyntaxNode<NT, T>(Rc<NodeData<NT, T>>);
+
+impl<NT, T> Clone for SyntaxNode<NT, T> {
+    fn clone(&self) -> Self {
+        Self(self.0.clone())
+    }
+}
+
+/// 字句のカーソル.
+pub struct SyntaxToken<NT, T> {
+    green: Arc<GreenToken<T>>,
+    parent: SyntaxNode<NT, T>,
+    offset: usize,
+}
+
+pub enum SyntaxElement<NT, T> {
+    Node(SyntaxNode<NT, T>),
+    Token(SyntaxToken<NT, T>),
+}
+
+impl<NT, T> SyntaxNode<NT, T> {
+    pub fn new_root(green: Arc<GreenNode<NT, T>>) -> Self {
+        Self(Rc::new(NodeData {
+            green,
+            parent: None,
+            offset: 0,
+        }))
+    }
+    pub fn kind(&self) -> &NT {
+        &self.0.green.kind
+    }
+    pub fn green(&self) -> &Arc<GreenNode<NT, T>> {
+        &self.0.green
+    }
+    pub fn parent(&self) -> Option<&SyntaxNode<NT, T>> {
+        self.0.parent.as_ref()
+    }
+    /// 自分から根までの節
+    pub fn ancestors(&self) -> impl Iterator<Item = SyntaxNode<NT, T>> {
+        std::iter::successors(Some(self.clone()), |node| node.parent().cloned())
+    }
+    /// trivia を含めた元の文字列での範囲
+    pub fn text_range(&self) -> Range<usize> {
+        self.0.offset..self.0.offset + self.0.green.text_len
+    }
+    pub fn text(&self) -> String {
+        self.0.green.text()
+    }
+    pub fn children(&self) -> Vec<SyntaxElement<NT, T>> {
+        let mut offset = self.0.offset;
+        self.0
+            .green
+            .children
+            .iter()
+            .map(|child| {
+                let start = offset;
+                match child {
+                    GreenElement::Node(node) => {
+                        offset += node.text_len;
+                        SyntaxElement::Node(SyntaxNode(Rc::new(NodeData {
+                            green: node.clone(),
+                            parent: Some(self.clone()),
+                            offset: start,
+                        })))
+                    }
+                    GreenElement::Token(token) => {
+                        offset += token.text_len();
+                        SyntaxElement::Token(SyntaxToken {
+                            green: token.clone(),
+                            parent: self.clone(),
+                            offset: start,
+                        })
+                    }
+                }
+            })
+            .collect()
+    }
+    /// 子の節だけ
+    pub fn child_nodes(&self) -> Vec<SyntaxNode<NT, T>> {
+        self.children()
+            .into_iter()
+            .filter_map(|child| match child {
+                SyntaxElement::Node(node) => Some(node),
+                SyntaxElement::Token(_) => None,
+            })
+            .collect()
+    }
+    /// 子孫の字句を左から順に
+    pub fn tokens(&self) -> Vec<SyntaxToken<NT, T>> {
+        let mut tokens = vec![];
+        for child in self.children() {
+            match child {
+                SyntaxElement::Node(node) => tokens.extend(node.tokens()),
+                SyntaxElement::Token(token) => tokens.push(token),
+            }
+        }
+        tokens
+    }
+    /// offset を含む字句 (trivia も含めて判定する)
+    pub fn token_at_offset(&self, offset: usize) -> Option<SyntaxToken<NT, T>> {
+        if !self.text_range().contains(&offset) {
+            return None;
+        }
+        self.children().into_iter().find_map(|child| match child {
+            SyntaxElement::Node(node) => node.token_at_offset(offset),
+            SyntaxElement::Token(token) => token.full_range().contains(&offset).then_some(token),
+        })
+    }
+}
+
+impl<NT, T> SyntaxToken<NT, T> {
+    pub fn kind(&self) -> &T {
+        &self.green.kind
+    }
+    pub fn text(&self) -> &str {
+        &self.green.text
+    }
+    pub fn green(&self) -> &Arc<GreenToken<T>> {
+        &self.green
+    }
+    pub fn parent(&self) -> &SyntaxNode<NT, T> {
+        &self.parent
+    }
+    /// trivia を除いた字句の範囲
+    pub fn text_range(&self) -> Range<usize> {
+        let start = self.offset + self.green.leading_len();
+        start..start + self.green.text.len()
+    }
+    /// trivia を含めた範囲
+    pub fn full_range(&self) -> Range<usize> {
+        self.offset..self.offset + self.green.text_len()
+    }
+}
+
+impl<NT, T> Debug for SyntaxNode<NT, T>
+where
+    NT: Debug,
+{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:?}@{:?}", self.kind(), self.text_range())
+    }
+}
+
+impl<NT, T> Debug for SyntaxToken<NT, T>
+where
+    T: Debug,
+{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{:?}@{:?} {:?}",
+            self.kind(),
+            self.text_range(),
+            self.text()
+        )
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{attach_trivia, GreenTreeBuilder, SyntaxNode, TriviaToken};
+    use crate::bnf::{EOFSupply, Expr, Grammer, Symbol};
+    use crate::item_set::generate_canonical_automaton;
+    use crate::parse_session::ParseSession;
+    use crate::parsing_table::canonical_automaton_to_lr0_parser;
+    use std::sync::Arc;
+
+    #[derive(Ord, PartialOrd, PartialEq, Eq, Clone, Debug)]
+    enum NT {
+        E,
+        P,
+    }
+
+    #[derive(Ord, PartialOrd, PartialEq, Eq, Clone, Debug)]
+    enum T {
+        Num,
+        Plus,
+        Space,
+        Comment,
+        Eof,
+    }
+
+    impl EOFSupply<T> for T {
+        fn eof() -> T {
+            T::Eof
+        }
+    }
+
+    fn lex(src: &str) -> Vec<(T, String)> {
+        let mut tokens = vec![];
+        let mut chars = src.chars().peekable();
+        while let Some(c) = chars.next() {
+            let (kind, mut text) = match c {
+                '+' => (T::Plus, c.to_string()),
+                ' ' | '\n' => (T::Space, c.to_string()),
+                '#' => (T::Comment, c.to_string()),
+                _ => (T::Num, c.to_string()),
+            };
+            let continues = |kind: &T, c: char| match kind {
+                T::Num => c.is_ascii_digit(),
+                T::Space => c == ' ' || c == '\n',
+                T::Comment => c != '\n',
+                _ => false,
+            };
+            while let Some(&c) = chars.peek().filter(|&&c| continues(&kind, c)) {
+                text.push(c);
+                chars.next();
+            }
+            tokens.push((kind, text));
+        }
+        tokens
+    }
+
+    #[test]
+    fn test_lossless_tree() {
+        // E -> E + P | P , P -> num
+        let grammer: Grammer<NT, T, NT, TriviaToken<T>> = Grammer {
+            rules: vec![
+                Expr {
+                    left: NT::E,
+                    right: vec![
+                        Symbol::NonTerm(NT::E),
+                        Symbol::Term(T::Plus),
+                        Symbol::NonTerm(NT::P),
+                    ],
+                    reduce_action: None,
+                },
+                Expr {
+                    left: NT::E,
+                    right: vec![Symbol::NonTerm(NT::P)],
+                    reduce_action: None,
+                },
+                Expr {
+                    left: NT::P,
+                    right: vec![Symbol::Term(T::Num)],
+                    reduce_action: None,
+                },
+            ],
+        };
+        let (automaton, reduce_action) =
+            generate_canonical_automaton(grammer, NT::E, None).unwrap();
+        let table = canonical_automaton_to_lr0_parser(&automaton, reduce_action).unwrap();
+
+        let src = " 12 + #note\n 3  ";
+        let tokens = attach_trivia(lex(src), |kind| matches!(kind, T::Space | T::Comment));
+        let mut session = ParseSession::new(Arc::new(table));
+        let mut builder = GreenTreeBuilder::new();
+        session.parse_iter_with(tokens, &mut builder).unwrap();
+        let green = builder.finish().unwrap();
+        assert_eq!(green.text(), src);
+        assert_eq!(green.text_len(), src.len());
+
+        let root = SyntaxNode::new_root(green);
+        assert_eq!(root.kind(), &NT::E);
+        let tokens = root.tokens();
+        let kinds: Vec<_> = tokens.iter().map(|token| token.kind().clone()).collect();
+        assert_eq!(kinds, vec![T::Num, T::Plus, T::Num]);
+        let three = &tokens[2];
+        assert_eq!(three.text(), "3");
+        assert_eq!(&src[three.text_range()], "3");
+        assert_eq!(three.full_range(), 5..src.len());
+        assert_eq!(three.green().leading_trivia().len(), 3);
+        let ancestors: Vec<_> = three
+            .parent()
+            .ancestors()
+            .map(|node| node.kind().clone())
+            .collect();
+        assert_eq!(ancestors, vec![NT::P, NT::E]);
+
+        let plus = root.token_at_offset(4).unwrap();
+        assert_eq!(plus.kind(), &T::Plus);
+        assert_eq!(&src[plus.text_range()], "+");
+        assert_eq!(plus.green().leading_trivia().len(), 1);
+        assert_eq!(plus.green().trailing_trivia().len(), 0);
+
+        let left = &root.child_nodes()[0];
+        assert_eq!(left.kind(), &NT::E);
+        assert_eq!(&src[left.text_range()], " 12");
+    }
+}
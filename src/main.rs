@@ -1,23 +1,28 @@
-use bnf::IntoKind;
-use item_set::compile_canonical_automaton_to_dot;
-
-use crate::{
-    bnf::{Expr, Grammer, ReduceAction, ReduceArgs, Symbol},
-    item_set::generate_canonical_automaton,
-    parsing_table::{canonical_automaton_to_lr0_parser, ValueStackSymbol},
+use rust_petit_compiler_compiler::{
+    alphabet::{Alphabet, AlphabetMismatch},
+    bnf::{EOFSupply, Expr, Grammer, IntoKind, Symbol},
+    first_set::generate_first_set_with,
+    item_set::{
+        compile_canonical_automaton_to_dot, generate_canonical_automaton,
+        generate_canonical_automaton_with, GrammerError,
+    },
+    observer::VerboseTracer,
+    parse_session::ParseSession,
+    parse_tree::ParseTreeRecorder,
+    parsing_table::{canonical_automaton_to_lr0_parser_with_resolution, ValueStackSymbol},
+    resolution::{Resolution, UnresolvedConflict},
+    typed_action::{FromStackValue, IntoNonTermValue, TypeMismatch},
+    validate::validate_grammer,
 };
 
-use std::fmt::Debug;
+use std::{
+    fmt::{Debug, Display},
+    sync::Arc,
+};
 
-mod bnf;
-mod first_set;
-mod item_set;
-mod nullable_set;
-mod parsing_table;
 #[derive(PartialEq, Eq, PartialOrd, Ord, Clone)]
 enum NT {
     S,
-    Sdash,
     E,
     P,
 }
@@ -26,7 +31,6 @@ impl Debug for NT {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
             Self::S => write!(f, "S"),
-            Self::Sdash => write!(f, "S'"),
             Self::E => write!(f, "E"),
             Self::P => write!(f, "P"),
         }
@@ -53,22 +57,32 @@ impl Debug for T {
     }
 }
 
+impl EOFSupply<T> for T {
+    fn eof() -> T {
+        T::Eof
+    }
+}
+
 //This is AST.
+#[allow(dead_code)]
 #[derive(Clone, Debug)]
 enum S {
     E(E),
 }
+#[allow(dead_code)]
 #[derive(Clone, Debug)]
 enum E {
     EPlusP(Box<E>, Box<P>),
     P(Box<P>),
 }
 
+#[allow(dead_code)]
 #[derive(Clone, Debug)]
 enum P {
     Expression(Box<E>),
     One,
 }
+#[allow(dead_code, clippy::upper_case_acronyms)]
 #[derive(Clone, Debug)]
 enum NTV {
     S(S),
@@ -85,120 +99,166 @@ impl IntoKind<NT> for NTV {
     }
 }
 
-fn main() {
+/// AST の型 $ty を非終端記号 NT::$ty の値として受け渡しできるようにする.
+macro_rules! nonterm_value {
+    ($ty:ident) => {
+        impl FromStackValue<NT, T, NTV, T> for $ty {
+            fn accepts(symbol: &Symbol<NT, T>) -> bool {
+                symbol == &Symbol::NonTerm(NT::$ty)
+            }
+            fn from_stack_value(value: ValueStackSymbol<NT, NTV, T>) -> Option<Self> {
+                match value {
+                    ValueStackSymbol::NonTerm(NTV::$ty(v)) => Some(v),
+                    _ => None,
+                }
+            }
+        }
+        impl IntoNonTermValue<NT, NTV> for $ty {
+            fn kind() -> NT {
+                NT::$ty
+            }
+            fn into_value(self) -> NTV {
+                NTV::$ty(self)
+            }
+        }
+    };
+}
+nonterm_value!(S);
+nonterm_value!(E);
+nonterm_value!(P);
+
+/// 文法から構文解析表を作るときのエラー
+enum BuildError {
+    Type(TypeMismatch<NT, T>),
+    Alphabet(AlphabetMismatch<NT, T>),
+    Conflict(Vec<UnresolvedConflict<T>>),
+}
+
+impl Display for BuildError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            BuildError::Type(error) => write!(f, "reduce action type mismatch: {:?}", error),
+            BuildError::Alphabet(error) => write!(f, "alphabet mismatch: {:?}", error),
+            BuildError::Conflict(conflicts) => write!(f, "unresolved conflicts: {:?}", conflicts),
+        }
+    }
+}
+
+//main がエラーを返したときに表示される.
+impl Debug for BuildError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self)
+    }
+}
+
+impl From<TypeMismatch<NT, T>> for BuildError {
+    fn from(error: TypeMismatch<NT, T>) -> Self {
+        BuildError::Type(error)
+    }
+}
+
+impl From<AlphabetMismatch<NT, T>> for BuildError {
+    fn from(error: AlphabetMismatch<NT, T>) -> Self {
+        BuildError::Alphabet(error)
+    }
+}
+
+impl From<Vec<UnresolvedConflict<T>>> for BuildError {
+    fn from(conflicts: Vec<UnresolvedConflict<T>>) -> Self {
+        BuildError::Conflict(conflicts)
+    }
+}
+
+impl From<GrammerError<NT, T>> for BuildError {
+    fn from(error: GrammerError<NT, T>) -> Self {
+        match error {
+            GrammerError::Alphabet(error) => BuildError::Alphabet(error),
+            GrammerError::Type(error) => BuildError::Type(error),
+        }
+    }
+}
+
+fn main() -> Result<(), BuildError> {
     let grammer = Grammer {
         rules: vec![
-            Expr {
-                left: NT::Sdash,
-                right: vec![Symbol::NonTerm(NT::S), Symbol::Term(T::Eof)],
-                reduce_action: None,
-            },
-            Expr {
-                left: NT::S,
-                right: vec![
+            Expr::typed(
+                NT::S,
+                vec![
                     Symbol::Term(T::LP),
                     Symbol::NonTerm(NT::E),
                     Symbol::Term(T::RP),
                 ],
-                reduce_action: Some(Box::new(|args: ReduceArgs<NTV, T>| -> NTV {
-                    if let ValueStackSymbol::NonTerm(NTV::E(e)) = &args[1] {
-                        NTV::S(S::E(e.clone()))
-                    } else {
-                        panic!("")
-                    }
-                }) as ReduceAction<_, _>),
-            },
-            Expr {
-                left: NT::E,
-                right: vec![
+                |_: (), e: E, _: ()| S::E(e),
+            )?,
+            Expr::typed(
+                NT::E,
+                vec![
                     Symbol::NonTerm(NT::E),
                     Symbol::Term(T::Plus),
                     Symbol::NonTerm(NT::P),
                 ],
-                reduce_action: Some(Box::new(|args: ReduceArgs<NTV, T>| -> NTV {
-                    let e = &args[0];
-                    let p = &args[2];
-                    if let (
-                        ValueStackSymbol::NonTerm(NTV::E(e)),
-                        ValueStackSymbol::NonTerm(NTV::P(p)),
-                    ) = (e, p)
-                    {
-                        NTV::E(E::EPlusP(Box::new(e.clone()), Box::new(p.clone())))
-                    } else {
-                        panic!("")
-                    }
-                })),
-            },
-            Expr {
-                left: NT::E,
-                right: vec![Symbol::NonTerm(NT::P)],
-                reduce_action: Some(Box::new(|args: ReduceArgs<NTV, T>| -> NTV {
-                    if let ValueStackSymbol::NonTerm(NTV::P(p)) = &args[0] {
-                        NTV::E(E::P(Box::new(p.clone())))
-                    } else {
-                        panic!("")
-                    }
-                })),
-            },
-            Expr {
-                left: NT::P,
-                right: vec![
+                |e: E, _: (), p: P| E::EPlusP(Box::new(e), Box::new(p)),
+            )?,
+            Expr::typed(NT::E, vec![Symbol::NonTerm(NT::P)], |p: P| {
+                E::P(Box::new(p))
+            })?,
+            Expr::typed(
+                NT::P,
+                vec![
                     Symbol::Term(T::LP),
                     Symbol::NonTerm(NT::E),
                     Symbol::Term(T::RP),
                 ],
-                reduce_action: Some(Box::new(|args: ReduceArgs<NTV, T>| -> NTV {
-                    if let ValueStackSymbol::NonTerm(NTV::E(e)) = &args[1] {
-                        NTV::P(P::Expression(Box::new(e.clone())))
-                    } else {
-                        panic!("")
-                    }
-                })),
-            },
-            Expr {
-                left: NT::P,
-                right: vec![Symbol::Term(T::One)],
-                reduce_action: Some(Box::new(|_: ReduceArgs<NTV, T>| -> NTV { NTV::P(P::One) })),
-            },
+                |_: (), e: E, _: ()| P::Expression(Box::new(e)),
+            )?,
+            Expr::typed(NT::P, vec![Symbol::Term(T::One)], |_: ()| P::One)?,
         ],
     };
-
-    let (states, goto, reduce_action) = generate_canonical_automaton(
-        grammer,
-        NT::Sdash,
-        &[
-            Symbol::NonTerm(NT::Sdash),
-            Symbol::NonTerm(NT::S),
-            Symbol::NonTerm(NT::E),
-            Symbol::NonTerm(NT::P),
-            Symbol::Term(T::One),
-            Symbol::Term(T::Plus),
-            Symbol::Term(T::LP),
-            Symbol::Term(T::RP),
-            Symbol::Term(T::Eof),
-        ],
-    );
-    println!(
-        "{}",
-        compile_canonical_automaton_to_dot((&states, &goto), "")
-    );
-    let terms = [T::One, T::Plus, T::LP, T::RP, T::Eof];
-    let parser = canonical_automaton_to_lr0_parser(
-        (&states, &goto, reduce_action),
-        NT::Sdash,
-        NT::S,
-        T::Eof,
-        &terms,
-    );
+    for diagnostic in validate_grammer(&grammer, &NT::S) {
+        eprintln!("warning: {:?}", diagnostic);
+    }
+    let alphabet = Alphabet::ordered(
+        &grammer,
+        &[T::One, T::Plus, T::LP, T::RP, T::Eof],
+        &[NT::S, NT::E, NT::P],
+        &[T::eof()],
+    )?;
+    //--verbose を渡すと表の作り方を標準エラー出力に書き出す.
+    let verbose = std::env::args().any(|arg| arg == "--verbose");
+    let (automaton, reduce_action) = if verbose {
+        //LR(0) の表には使わないが,FIRST 集合の求め方も書き出す.
+        generate_first_set_with(&grammer, &mut VerboseTracer);
+        generate_canonical_automaton_with(grammer, NT::S, Some(&alphabet), &mut VerboseTracer)?
+    } else {
+        generate_canonical_automaton(grammer, NT::S, Some(&alphabet))?
+    };
+    println!("{}", compile_canonical_automaton_to_dot(&automaton, ""));
+    //LR(0) で競合する文法なら表を作らない.
+    let resolution = Resolution::Error;
+    let table = if verbose {
+        canonical_automaton_to_lr0_parser_with_resolution(
+            &automaton,
+            reduce_action,
+            &resolution,
+            &mut VerboseTracer,
+        )?
+    } else {
+        canonical_automaton_to_lr0_parser_with_resolution(
+            &automaton,
+            reduce_action,
+            &resolution,
+            &mut (),
+        )?
+    };
 
     println!();
-    let nonterms = [NT::S, NT::E, NT::P];
-    parser.export_as_latex_src(&terms, &nonterms);
+    table.export_as_latex_src(alphabet.terms(), alphabet.nonterms());
+    let table = Arc::new(table);
 
     /*
      ((1)+(1+1))
     */
-    let mut parser = parser.input(vec![
+    let tokens = vec![
         T::LP,
         T::LP,
         T::One,
@@ -210,9 +270,23 @@ fn main() {
         T::One,
         T::RP,
         T::RP,
-        T::Eof,
-    ]);
+    ];
+    let mut parser = ParseSession::new(table).input(
+        tokens
+            .iter()
+            .cloned()
+            .chain(std::iter::once(T::eof()))
+            .collect(),
+    );
     println!();
     parser.export_parsing_as_latex_src();
-    println!("{:#?}", parser.get_syntax_tree());
+    parser.reset();
+    let mut recorder = ParseTreeRecorder::new();
+    println!("{:#?}", parser.parse_iter_with(tokens, &mut recorder));
+    if let Some(tree) = recorder.finish() {
+        println!("{}", tree.export_as_dot("ParseTree"));
+        println!("{}", tree.export_as_forest_src());
+        println!("{}", tree.export_as_qtree_src());
+    }
+    Ok(())
 }
@@ -2,7 +2,6 @@ use std::collections::BTreeSet;
 
 use crate::bnf::{Grammer, IntoKind};
 
-#[allow(dead_code)]
 pub fn generate_null_set<NT, T, NTV, TV>(bnf: &Grammer<NT, T, NTV, TV>) -> BTreeSet<NT>
 where
     T: Ord + Eq + Clone,
@@ -0,0 +1,218 @@
+use crate::bnf::Symbol;
+use crate::item_set::{LR0Item, StateId};
+use std::fmt::Debug;
+
+/// 競合の種類
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConflictKind {
+    ShiftReduce,
+    ReduceReduce,
+}
+
+/// 文法から表を作るときの出来事.
+#[derive(Debug)]
+pub enum BuildEvent<'a, NT, T>
+where
+    NT: Ord + Eq + Clone + Debug,
+    T: Ord + Eq + Clone + Debug,
+{
+    /// クロージャを作るために nonterm の導入項を要求した.
+    ClosureRequired(&'a NT),
+    /// クロージャに導入項を加えた.
+    ClosureAdded,
+    /// ドットを一つすすめた項 kernel のクロージャをとる.
+    GotoKernel(&'a [LR0Item<NT, T>]),
+    /// 状態 I_state からの遷移を調べる. items は状態の項.
+    Expand {
+        state: StateId,
+        items: &'a [LR0Item<NT, T>],
+    },
+    /// Goto(I_from, symbol) = I_to. kernel は I_to の核, new_state は初めて作った状態か.
+    Goto {
+        from: StateId,
+        symbol: &'a Symbol<NT, T>,
+        to: StateId,
+        kernel: &'a [LR0Item<NT, T>],
+        new_state: bool,
+    },
+    /// First(sub) は First(sup) に含まれる.
+    FirstConstraint { sup: &'a NT, sub: &'a Symbol<NT, T> },
+    /// First 集合の包含関係を1回解いた.
+    FirstIteration,
+    /// 構文解析表の状態 q_state で競合している.
+    Conflict { state: StateId, kind: ConflictKind },
+}
+
+/// 文法から表を作るときの出来事を受け取る.
+///
+/// `()` は何もしないので,既定では何も出力しない.
+pub trait BuildObserver<NT, T>
+where
+    NT: Ord + Eq + Clone + Debug,
+    T: Ord + Eq + Clone + Debug,
+{
+    /// false なら出来事を作らない.
+    fn enabled(&self) -> bool {
+        true
+    }
+    fn notify(&mut self, event: BuildEvent<'_, NT, T>);
+}
+
+impl<NT, T> BuildObserver<NT, T> for ()
+where
+    NT: Ord + Eq + Clone + Debug,
+    T: Ord + Eq + Clone + Debug,
+{
+    fn enabled(&self) -> bool {
+        false
+    }
+    fn notify(&mut self, _event: BuildEvent<'_, NT, T>) {}
+}
+
+/// 出来事を説明する文を標準エラー出力に書き出す. 授業で作り方を追うときに使う.
+pub struct VerboseTracer;
+
+impl<NT, T> BuildObserver<NT, T> for VerboseTracer
+where
+    NT: Ord + Eq + Clone + Debug,
+    T: Ord + Eq + Clone + Debug,
+{
+    fn notify(&mut self, event: BuildEvent<'_, NT, T>) {
+        match event {
+            BuildEvent::ClosureRequired(nt) => eprintln!("required {:?}", nt),
+            BuildEvent::ClosureAdded => eprintln!("added items"),
+            BuildEvent::GotoKernel(kernel) => {
+                eprintln!("generate for ");
+                for item in kernel {
+                    eprintln!("{}", item);
+                }
+            }
+            BuildEvent::Expand { state, items } => {
+                eprintln!("trying to generate next states for I{}={:?}", state, items)
+            }
+            BuildEvent::Goto {
+                from,
+                symbol,
+                to,
+                kernel,
+                new_state,
+            } => {
+                if !new_state {
+                    eprintln!("same goto set generated before.");
+                }
+                let symbol = match symbol {
+                    Symbol::Term(t) => format!("{:?}", t),
+                    Symbol::NonTerm(nt) => format!("{:?}", nt),
+                };
+                eprintln!("I{} = Goto(I{},{}) kernel = {:?}", to, from, symbol, kernel);
+            }
+            BuildEvent::FirstConstraint { sup, sub } => eprintln!("{:?} <-{:?}", sup, sub),
+            BuildEvent::FirstIteration => eprintln!("solving"),
+            BuildEvent::Conflict {
+                state,
+                kind: ConflictKind::ReduceReduce,
+            } => eprintln!("Reduce/Reduce conflict detected in q{}.", state),
+            BuildEvent::Conflict {
+                state,
+                kind: ConflictKind::ShiftReduce,
+            } => eprintln!("Shift/Reduce conflict detected in q{}.", state),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{BuildEvent, BuildObserver, ConflictKind};
+    use crate::bnf::test::Char;
+    use crate::bnf::{Expr, Grammer, Symbol};
+    use crate::item_set::generate_canonical_automaton_with;
+    use crate::parsing_table::canonical_automaton_to_lr0_parser_with;
+    use std::fmt::Debug;
+
+    /// 出来事を短い文字列にして集める.
+    #[derive(Default)]
+    struct Recorder(Vec<String>);
+
+    impl<NT, T> BuildObserver<NT, T> for Recorder
+    where
+        NT: Ord + Eq + Clone + Debug,
+        T: Ord + Eq + Clone + Debug,
+    {
+        fn notify(&mut self, event: BuildEvent<'_, NT, T>) {
+            self.0.push(match event {
+                BuildEvent::Expand { state, .. } => format!("expand {}", state),
+                BuildEvent::Goto {
+                    from,
+                    to,
+                    new_state,
+                    ..
+                } => format!("goto {} {} {}", from, to, new_state),
+                BuildEvent::Conflict { state, kind } => format!("{:?} {}", kind, state),
+                other => format!("{:?}", other),
+            });
+        }
+    }
+
+    #[derive(Ord, PartialOrd, PartialEq, Eq, Clone, Debug)]
+    enum NT {
+        E,
+    }
+
+    #[test]
+    fn test_observer() {
+        // E -> E + E | 1
+        let grammer: Grammer<NT, Char, NT, Char> = Grammer {
+            rules: vec![
+                Expr {
+                    left: NT::E,
+                    right: vec![
+                        Symbol::NonTerm(NT::E),
+                        Symbol::Term(Char('+')),
+                        Symbol::NonTerm(NT::E),
+                    ],
+                    reduce_action: None,
+                },
+                Expr {
+                    left: NT::E,
+                    right: vec![Symbol::Term(Char('1'))],
+                    reduce_action: None,
+                },
+            ],
+        };
+        let mut recorder = Recorder::default();
+        let (automaton, reduce_action) =
+            generate_canonical_automaton_with(grammer, NT::E, None, &mut recorder).unwrap();
+        // 競合があるので表は作らないが, observer には競合を知らせる.
+        assert!(
+            canonical_automaton_to_lr0_parser_with(&automaton, reduce_action, &mut recorder)
+                .is_err()
+        );
+        let events = recorder.0;
+        let expanded = events.iter().filter(|e| e.starts_with("expand")).count();
+        let new_states = events.iter().filter(|e| e.ends_with("true")).count();
+        assert_eq!(expanded, automaton.len());
+        assert_eq!(new_states + 1, automaton.len());
+        assert_eq!(
+            events.iter().find(|e| e.starts_with("expand")).unwrap(),
+            "expand 0"
+        );
+        // クロージャをとるときの出来事も知らせる.
+        assert!(events.iter().any(|e| e.starts_with("ClosureRequired")));
+        assert!(events.iter().any(|e| e == "ClosureAdded"));
+        assert_eq!(
+            events
+                .iter()
+                .filter(|e| e.starts_with("GotoKernel"))
+                .count(),
+            automaton.len() - 1
+        );
+        // E -> E+E・ と E -> E・+E が同じ状態にある.
+        assert_eq!(
+            events
+                .iter()
+                .filter(|e| e.starts_with(&format!("{:?}", ConflictKind::ShiftReduce)))
+                .count(),
+            1
+        );
+    }
+}
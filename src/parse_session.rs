@@ -0,0 +1,563 @@
+use crate::{
+    bnf::{EOFSupply, IntoKind},
+    cst::ConcreteSyntaxTree,
+    parsing_table::{ActionKind, ParseTable, ValueStackSymbol},
+};
+use std::{fmt::Debug, sync::Arc};
+
+/// 1回の構文解析の状態.
+///
+/// 構文解析表は `Arc` で共有するので,同じ表から複数のセッションを作って
+/// 別々のスレッドで同時に構文解析できる.
+pub struct ParseSession<NT, T, NTV, TV>
+where
+    NT: Debug + Clone + Eq + Ord,
+    T: Debug + Clone + Eq + Ord,
+    NTV: IntoKind<NT>,
+    TV: IntoKind<T>,
+{
+    table: Arc<ParseTable<NT, T, NTV, TV>>,
+    input: Vec<TV>,
+    stack: Vec<usize>,
+    // value_stack
+    value_stack: Vec<ValueStackSymbol<NT, NTV, TV>>,
+}
+
+/// 構文解析器がシフト・還元するたびに呼ばれる.
+///
+/// 値スタックとは別に木を組み立てたいときに使う. どちらのメソッドも既定では何もしない.
+pub trait ParseListener<NT, TV> {
+    /// token をシフトした.
+    fn shift(&mut self, _token: &TV) {}
+    /// 規則 r_{rule} (右辺の長さ len) で left に還元した.
+    fn reduce(&mut self, _rule: usize, _left: &NT, _len: usize) {}
+}
+
+impl<NT, TV> ParseListener<NT, TV> for () {}
+
+/// `ParseSession::push` の結果
+#[derive(Debug, PartialEq, Eq)]
+pub enum PushResult {
+    Shifted,
+    Accepted,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum ParseError<T> {
+    /// 状態 state で token に対する動作がない.
+    UnexpectedToken { state: usize, token: T },
+    /// 受理した後にも字句が残っている.
+    TrailingInput(T),
+    /// 受理する前に入力が尽きた.
+    UnexpectedEndOfInput,
+    /// 規則 r_{rule} の還元動作が値スタックの値を受け取れなかった.
+    ActionMismatch { rule: usize },
+    /// 状態 state で規則 r_{rule} で還元できない. 読み込んだ表の還元や遷移が食い違っている.
+    CorruptTable { state: usize, rule: usize },
+    /// 編集する字句の範囲 start..end が逆向きか,字句の数 len を超えている.
+    InvalidRange {
+        start: usize,
+        end: usize,
+        len: usize,
+    },
+}
+
+impl<NT, T, NTV, TV> ParseSession<NT, T, NTV, TV>
+where
+    NT: Clone + Eq + Ord + Debug,
+    T: Clone + Eq + Ord + Debug,
+    NTV: IntoKind<NT>,
+    TV: IntoKind<T>,
+{
+    pub fn new(table: Arc<ParseTable<NT, T, NTV, TV>>) -> Self {
+        Self {
+            input: vec![],
+            stack: vec![table.start_state()],
+            value_stack: Vec::new(),
+            table,
+        }
+    }
+
+    /// 状態を開始状態に戻す.
+    pub fn reset(&mut self) {
+        self.input.clear();
+        self.stack = vec![self.table.start_state()];
+        self.value_stack.clear();
+    }
+
+    pub fn input(self, input: Vec<TV>) -> Self {
+        Self {
+            input,
+            value_stack: Vec::new(),
+            ..self
+        }
+    }
+
+    pub fn export_parsing_as_latex_src(&mut self) {
+        println!(
+            "generating step by step parsing for {:?}.\n",
+            self.input
+                .iter()
+                .map(|x| { x.into_kind() })
+                .collect::<Vec<_>>()
+        );
+
+        println!("\\begin{{tabular}}{{lllll}}");
+        println!(r" & &remain input & stack & action \\ \hline");
+
+        let mut step_count = 1;
+
+        loop {
+            if let Some(x) = self.input.first() {
+                let top_index = self.stack.len();
+                let q = *self.stack.get(top_index - 1).unwrap();
+                if let Some(action) = self.table.action(q, &x.into_kind()) {
+                    match action {
+                        ActionKind::Accept => {
+                            println!(
+                                "{} & & {} & {} & Accept \\\\ \\hline ",
+                                step_count,
+                                self.dump_remain_input(),
+                                self.dump_stack_as_latex_src(),
+                            );
+                            break;
+                        }
+                        &ActionKind::Reduce(rule_number) => {
+                            println!(
+                                "{} & & {} & {} & Reduce($ r_{{{}}} $) \\\\ \\hline",
+                                step_count,
+                                self.dump_remain_input(),
+                                self.dump_stack_as_latex_src(),
+                                rule_number
+                            );
+
+                            if self.reduce(rule_number, &mut ()).is_err() {
+                                eprintln!("reduce action of r{} failed", rule_number);
+                                break;
+                            }
+                        }
+
+                        ActionKind::Shift(next_state) => {
+                            println!(
+                                "{} & & {} & {} & Shift($ q_{{{}}} $) \\\\ \\hline",
+                                step_count,
+                                self.dump_remain_input(),
+                                self.dump_stack_as_latex_src(),
+                                next_state
+                            );
+                            self.stack.push(*next_state);
+                            let value = self.input.remove(0);
+                            self.value_stack.push(ValueStackSymbol::Term(value));
+                        }
+                        ActionKind::Error => {
+                            eprintln!("error detected due to invalid input");
+                            break;
+                        }
+                    }
+                    step_count += 1;
+                } else {
+                    eprintln!("No action for ({},{:?})", q, x.into_kind());
+                    break;
+                }
+            } else {
+                eprintln!("input exhausted before accept");
+                break;
+            }
+        }
+
+        println!("\\end{{tabular}}")
+    }
+
+    /// 規則 rule_number で還元し,還元動作を呼んで値スタックを更新する.
+    fn reduce(
+        &mut self,
+        rule_number: usize,
+        listener: &mut impl ParseListener<NT, TV>,
+    ) -> Result<(), ParseError<T>> {
+        let table = self.table.clone();
+        let state = *self.stack.last().unwrap();
+        let corrupt = || ParseError::CorruptTable {
+            state,
+            rule: rule_number,
+        };
+        let lr0item = table.rule(rule_number).ok_or_else(corrupt)?;
+        let pops = lr0item.right.len();
+        //開始状態や値スタックの底より下は降ろせない.
+        if pops >= self.stack.len() || pops > self.value_stack.len() {
+            return Err(corrupt());
+        }
+        //空規則なら何も降ろさず,今の状態から遷移する.
+        let q = self.stack[self.stack.len() - 1 - pops];
+        let q_dash = table.goto(q, &lr0item.left).ok_or_else(corrupt)?;
+        listener.reduce(rule_number, &lr0item.left, pops);
+        self.stack.truncate(self.stack.len() - pops);
+        self.stack.push(q_dash);
+
+        let ln = self.value_stack.len();
+        let args = self.value_stack.split_off(ln - pops);
+        if let Some(function) = table.reduce_action(rule_number) {
+            let v = function
+                .call(args)
+                .ok_or(ParseError::ActionMismatch { rule: rule_number })?;
+            self.value_stack.push(ValueStackSymbol::NonTerm(v));
+        } else {
+            //還元動作がないので具象構文木を作る.
+            self.value_stack
+                .push(ValueStackSymbol::Tree(ConcreteSyntaxTree {
+                    rule: rule_number,
+                    left: lr0item.left.clone(),
+                    children: args,
+                }));
+        }
+        Ok(())
+    }
+
+    /// 字句を1つ押し込む.
+    ///
+    /// 字句がシフトされるまで還元を繰り返す. 受理した場合は `Accepted` を返し,
+    /// 字句は値スタックに積まない.
+    pub fn push(&mut self, token: TV) -> Result<PushResult, ParseError<T>> {
+        self.push_with(token, &mut ())
+    }
+
+    /// `push` と同じだが,シフトと還元を listener に知らせる.
+    pub fn push_with(
+        &mut self,
+        token: TV,
+        listener: &mut impl ParseListener<NT, TV>,
+    ) -> Result<PushResult, ParseError<T>> {
+        let kind = token.into_kind();
+        loop {
+            let q = *self.stack.last().unwrap();
+            match self.table.action(q, &kind) {
+                Some(ActionKind::Accept) => return Ok(PushResult::Accepted),
+                Some(ActionKind::Reduce(rule_number)) => self.reduce(*rule_number, listener)?,
+                Some(ActionKind::Shift(next_state)) => {
+                    listener.shift(&token);
+                    self.stack.push(*next_state);
+                    self.value_stack.push(ValueStackSymbol::Term(token));
+                    return Ok(PushResult::Shifted);
+                }
+                Some(ActionKind::Error) | None => {
+                    return Err(ParseError::UnexpectedToken {
+                        state: q,
+                        token: kind,
+                    })
+                }
+            }
+        }
+    }
+
+    /// 字句の列を遅延して読みながら構文解析する.
+    ///
+    /// 入力の末尾には `EOFSupply` で得た終端記号を自動的に追加するので,
+    /// 呼び出し側が入力に終端記号を入れる必要はない.
+    pub fn parse_iter<I>(
+        &mut self,
+        tokens: I,
+    ) -> Result<ValueStackSymbol<NT, NTV, TV>, ParseError<T>>
+    where
+        I: IntoIterator<Item = TV>,
+        TV: EOFSupply<TV>,
+    {
+        self.parse_iter_with(tokens, &mut ())
+    }
+
+    /// `parse_iter` と同じだが,シフトと還元を listener に知らせる.
+    pub fn parse_iter_with<I>(
+        &mut self,
+        tokens: I,
+        listener: &mut impl ParseListener<NT, TV>,
+    ) -> Result<ValueStackSymbol<NT, NTV, TV>, ParseError<T>>
+    where
+        I: IntoIterator<Item = TV>,
+        TV: EOFSupply<TV>,
+    {
+        let mut tokens = tokens.into_iter().chain(std::iter::once(TV::eof()));
+        while let Some(token) = tokens.next() {
+            if let PushResult::Accepted = self.push_with(token, listener)? {
+                return match tokens.next() {
+                    Some(token) => Err(ParseError::TrailingInput(token.into_kind())),
+                    None => self
+                        .value_stack
+                        .pop()
+                        .ok_or(ParseError::UnexpectedEndOfInput),
+                };
+            }
+        }
+        Err(ParseError::UnexpectedEndOfInput)
+    }
+
+    fn dump_remain_input(&self) -> String {
+        use std::fmt::Write;
+        let mut buffer = String::new();
+        for x in self.input.iter().map(|tv| tv.into_kind()) {
+            write!(&mut buffer, "{:?}", x).unwrap();
+        }
+        buffer
+    }
+
+    fn dump_stack_as_latex_src(&self) -> String {
+        use std::fmt::Write;
+
+        let mut buffer = String::new();
+        buffer.push_str("$ ");
+        let (last, left) = self.stack.split_last().unwrap();
+        for state in left {
+            write!(&mut buffer, "q_{{{}}}", state).unwrap();
+        }
+        write!(&mut buffer, "q_{{{}}}\\leftarrow", last).unwrap();
+        buffer.push_str(" $");
+        buffer
+    }
+
+    pub fn get_syntax_tree(&mut self) -> Option<ValueStackSymbol<NT, NTV, TV>> {
+        self.value_stack.pop()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{ParseError, ParseSession};
+    use crate::bnf::test::{chars, Char};
+    use crate::bnf::{Expr, Grammer, IntoKind, ReduceArgs, Symbol};
+    use crate::item_set::generate_canonical_automaton;
+    use crate::parsing_table::{
+        canonical_automaton_to_lr0_parser, ActionKind, ParseTable, ValueStackSymbol,
+    };
+    use std::sync::Arc;
+
+    #[derive(Ord, PartialOrd, PartialEq, Eq, Clone, Debug)]
+    enum NT {
+        S,
+    }
+
+    /// 括弧の深さ
+    #[derive(Debug, PartialEq)]
+    struct Depth(usize);
+
+    impl IntoKind<NT> for Depth {
+        fn into_kind(&self) -> NT {
+            NT::S
+        }
+    }
+
+    fn table() -> Arc<ParseTable<NT, Char, Depth, Char>> {
+        // S -> ( S ) | x
+        let grammer = Grammer {
+            rules: vec![
+                Expr {
+                    left: NT::S,
+                    right: vec![
+                        Symbol::Term(Char('(')),
+                        Symbol::NonTerm(NT::S),
+                        Symbol::Term(Char(')')),
+                    ],
+                    reduce_action: Some(Box::new(|mut args: ReduceArgs<NT, Depth, Char>| {
+                        if let ValueStackSymbol::NonTerm(Depth(d)) = args.remove(1) {
+                            Depth(d + 1)
+                        } else {
+                            panic!("")
+                        }
+                    })),
+                },
+                Expr {
+                    left: NT::S,
+                    right: vec![Symbol::Term(Char('x'))],
+                    reduce_action: Some(Box::new(|_| Depth(0))),
+                },
+            ],
+        };
+        let (automaton, reduce_action) =
+            generate_canonical_automaton(grammer, NT::S, None).unwrap();
+        Arc::new(canonical_automaton_to_lr0_parser(&automaton, reduce_action).unwrap())
+    }
+
+    #[test]
+    fn test_parse_iter() {
+        let mut parser = ParseSession::new(table());
+        let result = parser.parse_iter(chars("((x))"));
+        assert!(matches!(result, Ok(ValueStackSymbol::NonTerm(Depth(2)))));
+    }
+
+    #[test]
+    fn test_parse_iter_error() {
+        let table = table();
+        // ( を読んで S に還元した状態で $ が来る.
+        let start = table.start_state();
+        let open = match table.action(start, &Char('(')) {
+            Some(ActionKind::Shift(state)) => *state,
+            action => panic!("{:?}", action),
+        };
+        let state = table.goto(open, &NT::S).unwrap();
+        assert_eq!(table.action(state, &Char('$')), None);
+
+        let mut parser = ParseSession::new(table);
+        assert_eq!(
+            parser.parse_iter(chars("(x")).err(),
+            Some(ParseError::UnexpectedToken {
+                state,
+                token: Char('$')
+            })
+        );
+        parser.reset();
+        assert_eq!(parser.stack, vec![parser.table.start_state()]);
+        assert_eq!(
+            parser.parse_iter(chars("x$")).err(),
+            Some(ParseError::TrailingInput(Char('$')))
+        );
+    }
+
+    #[test]
+    fn test_shared_table() {
+        fn assert_send_sync<X: Send + Sync>() {}
+        assert_send_sync::<ParseTable<NT, Char, Depth, Char>>();
+
+        let table = table();
+        let handles: Vec<_> = (0..4)
+            .map(|depth| {
+                let table = table.clone();
+                std::thread::spawn(move || {
+                    let input = "(".repeat(depth) + "x" + &")".repeat(depth);
+                    let mut session = ParseSession::new(table);
+                    match session.parse_iter(chars(&input)) {
+                        Ok(ValueStackSymbol::NonTerm(Depth(d))) => d,
+                        _ => panic!("failed to parse {}", input),
+                    }
+                })
+            })
+            .collect();
+        let depths: Vec<_> = handles.into_iter().map(|h| h.join().unwrap()).collect();
+        assert_eq!(depths, vec![0, 1, 2, 3]);
+    }
+
+    #[test]
+    fn test_epsilon_rules() {
+        #[derive(Ord, PartialOrd, PartialEq, Eq, Clone, Debug)]
+        enum NT {
+            S,
+            L,
+        }
+        /// S は部分木の S の数, L は並んでいる S それぞれの値
+        #[derive(Debug)]
+        enum V {
+            S(usize),
+            L(Vec<usize>),
+        }
+        impl IntoKind<NT> for V {
+            fn into_kind(&self) -> NT {
+                match self {
+                    V::S(_) => NT::S,
+                    V::L(_) => NT::L,
+                }
+            }
+        }
+        // S -> ( L ) , L -> L S | ε
+        let grammer: Grammer<NT, Char, V, Char> = Grammer {
+            rules: vec![
+                Expr {
+                    left: NT::S,
+                    right: vec![
+                        Symbol::Term(Char('(')),
+                        Symbol::NonTerm(NT::L),
+                        Symbol::Term(Char(')')),
+                    ],
+                    reduce_action: Some(Box::new(|mut args: ReduceArgs<NT, V, Char>| {
+                        match args.remove(1) {
+                            ValueStackSymbol::NonTerm(V::L(list)) => {
+                                V::S(list.iter().sum::<usize>() + 1)
+                            }
+                            _ => panic!(""),
+                        }
+                    })),
+                },
+                Expr {
+                    left: NT::L,
+                    right: vec![Symbol::NonTerm(NT::L), Symbol::NonTerm(NT::S)],
+                    reduce_action: Some(Box::new(|mut args: ReduceArgs<NT, V, Char>| {
+                        match (args.remove(0), args.remove(0)) {
+                            (
+                                ValueStackSymbol::NonTerm(V::L(mut list)),
+                                ValueStackSymbol::NonTerm(V::S(n)),
+                            ) => {
+                                list.push(n);
+                                V::L(list)
+                            }
+                            _ => panic!(""),
+                        }
+                    })),
+                },
+                Expr {
+                    left: NT::L,
+                    right: vec![],
+                    reduce_action: Some(Box::new(|args: ReduceArgs<NT, V, Char>| {
+                        assert!(args.is_empty());
+                        V::L(vec![])
+                    })),
+                },
+            ],
+        };
+        let (automaton, reduce_action) =
+            generate_canonical_automaton(grammer, NT::S, None).unwrap();
+        let table = Arc::new(canonical_automaton_to_lr0_parser(&automaton, reduce_action).unwrap());
+        let mut parser = ParseSession::new(table);
+        for (input, count) in [("()", 1), ("(())", 2), ("(()(()))", 4), ("(()()())", 4)] {
+            parser.reset();
+            assert!(
+                matches!(parser.parse_iter(chars(input)), Ok(ValueStackSymbol::NonTerm(V::S(n))) if n == count),
+                "{}",
+                input
+            );
+        }
+        parser.reset();
+        assert!(parser.parse_iter(chars("(()")).is_err());
+    }
+
+    #[test]
+    fn test_empty_input() {
+        #[derive(Ord, PartialOrd, PartialEq, Eq, Clone, Debug)]
+        enum NT {
+            S,
+            A,
+            B,
+        }
+        // S -> A B , A -> ε , B -> ε
+        let grammer: Grammer<NT, Char, NT, Char> = Grammer {
+            rules: vec![
+                Expr {
+                    left: NT::S,
+                    right: vec![Symbol::NonTerm(NT::A), Symbol::NonTerm(NT::B)],
+                    reduce_action: None,
+                },
+                Expr {
+                    left: NT::A,
+                    right: vec![],
+                    reduce_action: None,
+                },
+                Expr {
+                    left: NT::B,
+                    right: vec![],
+                    reduce_action: None,
+                },
+            ],
+        };
+        let (automaton, reduce_action) =
+            generate_canonical_automaton(grammer, NT::S, None).unwrap();
+        let table = Arc::new(canonical_automaton_to_lr0_parser(&automaton, reduce_action).unwrap());
+        let start = table.start_state();
+        let mut parser = ParseSession::new(table);
+        match parser.parse_iter(chars("")) {
+            Ok(ValueStackSymbol::Tree(tree)) => assert_eq!(tree.export_as_sexpr(), "(S (A) (B))"),
+            other => panic!("expected concrete syntax tree but got {:?}", other),
+        }
+        // x は文法にない字句なので,開始状態で何もせずに止まる.
+        parser.reset();
+        assert_eq!(
+            parser.parse_iter(chars("x")).err(),
+            Some(ParseError::UnexpectedToken {
+                state: start,
+                token: Char('x')
+            })
+        );
+    }
+}
@@ -0,0 +1,329 @@
+use crate::{item_set::escape_dot, parse_session::ParseListener};
+use std::fmt::{Debug, Write};
+
+/// 規則の適用を並べた構文木.
+///
+/// 還元動作とは関係なく,どの規則で還元したかだけを持つ. 授業で木を図にするときに使う.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ParseTree<NT, TV> {
+    /// シフトした字句
+    Leaf(TV),
+    /// 規則 r_{rule} で left に還元した. children は右辺の記号の部分木.
+    Node {
+        rule: usize,
+        left: NT,
+        children: Vec<ParseTree<NT, TV>>,
+    },
+}
+
+/// シフトと還元に合わせて `ParseTree` を組み立てる.
+///
+/// `ParseSession::parse_iter_with` に渡して使う.
+pub struct ParseTreeRecorder<NT, TV> {
+    stack: Vec<ParseTree<NT, TV>>,
+}
+
+impl<NT, TV> Default for ParseTreeRecorder<NT, TV> {
+    fn default() -> Self {
+        Self { stack: vec![] }
+    }
+}
+
+impl<NT, TV> ParseTreeRecorder<NT, TV> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// 受理した後に呼ぶと,開始記号の木を返す.
+    pub fn finish(mut self) -> Option<ParseTree<NT, TV>> {
+        match (self.stack.pop(), self.stack.is_empty()) {
+            (Some(root @ ParseTree::Node { .. }), true) => Some(root),
+            _ => None,
+        }
+    }
+}
+
+impl<NT, TV> ParseListener<NT, TV> for ParseTreeRecorder<NT, TV>
+where
+    NT: Clone,
+    TV: Clone,
+{
+    fn shift(&mut self, token: &TV) {
+        self.stack.push(ParseTree::Leaf(token.clone()));
+    }
+
+    fn reduce(&mut self, rule: usize, left: &NT, len: usize) {
+        let children = self.stack.split_off(self.stack.len() - len);
+        self.stack.push(ParseTree::Node {
+            rule,
+            left: left.clone(),
+            children,
+        });
+    }
+}
+
+/// LaTeX の特殊文字を文字として書けるようにする.
+pub fn escape_latex(s: &str) -> String {
+    let mut buffer = String::new();
+    for c in s.chars() {
+        match c {
+            '\\' => buffer.push_str(r"\textbackslash{}"),
+            '~' => buffer.push_str(r"\textasciitilde{}"),
+            '^' => buffer.push_str(r"\textasciicircum{}"),
+            '#' | '$' | '%' | '&' | '_' | '{' | '}' => {
+                buffer.push('\\');
+                buffer.push(c);
+            }
+            c => buffer.push(c),
+        }
+    }
+    buffer
+}
+
+/// 空規則の子
+const EPSILON: &str = r"$\varepsilon$";
+
+impl<NT, TV> ParseTree<NT, TV>
+where
+    NT: Debug,
+    TV: Debug,
+{
+    fn label(&self) -> String {
+        match self {
+            ParseTree::Leaf(token) => format!("{:?}", token),
+            ParseTree::Node { left, .. } => format!("{:?}", left),
+        }
+    }
+
+    /// graphviz の dot で書き出す. 字句は入力の順に一番下に並ぶ.
+    pub fn export_as_dot(&self, tree_name: &str) -> String {
+        let mut nodes = String::new();
+        let mut edges = String::new();
+        let mut leaves = vec![];
+        self.write_dot(&mut 0, &mut nodes, &mut edges, &mut leaves);
+        format!(
+            "digraph {} {{\n    /*Nodes*/\n{}    /*Relations*/\n{}    {{rank=same; {}}}\n}}\n",
+            tree_name,
+            nodes,
+            edges,
+            leaves
+                .iter()
+                .map(|leaf| format!("Node{};", leaf))
+                .collect::<Vec<_>>()
+                .join(" ")
+        )
+    }
+
+    /// 前順で節に番号をつけて書く. 自分の番号を返す.
+    fn write_dot(
+        &self,
+        count: &mut usize,
+        nodes: &mut String,
+        edges: &mut String,
+        leaves: &mut Vec<usize>,
+    ) -> usize {
+        let id = *count;
+        *count += 1;
+        match self {
+            ParseTree::Leaf(_) => {
+                leaves.push(id);
+                writeln!(
+                    nodes,
+                    "    Node{} [label=\"{}\" shape=\"plaintext\"];",
+                    id,
+                    escape_dot(&self.label())
+                )
+                .unwrap();
+            }
+            ParseTree::Node { rule, children, .. } => {
+                writeln!(
+                    nodes,
+                    "    Node{} [label=\"{}\" tooltip=\"r{}\"];",
+                    id,
+                    escape_dot(&self.label()),
+                    rule
+                )
+                .unwrap();
+                if children.is_empty() {
+                    let epsilon = *count;
+                    *count += 1;
+                    leaves.push(epsilon);
+                    writeln!(
+                        nodes,
+                        "    Node{} [label=\"ε\" shape=\"plaintext\"];",
+                        epsilon
+                    )
+                    .unwrap();
+                    writeln!(edges, "    Node{} -> Node{};", id, epsilon).unwrap();
+                }
+                for child in children {
+                    let child = child.write_dot(count, nodes, edges, leaves);
+                    writeln!(edges, "    Node{} -> Node{};", id, child).unwrap();
+                }
+            }
+        }
+        id
+    }
+
+    /// LaTeX の forest パッケージの木として書き出す.
+    pub fn export_as_forest_src(&self) -> String {
+        let mut buffer = String::new();
+        buffer.push_str("\\begin{forest}\n");
+        self.write_forest(&mut buffer);
+        buffer.push_str("\n\\end{forest}\n");
+        buffer
+    }
+
+    fn write_forest(&self, buffer: &mut String) {
+        write!(buffer, "[{{{}}}", escape_latex(&self.label())).unwrap();
+        if let ParseTree::Node { children, .. } = self {
+            if children.is_empty() {
+                write!(buffer, " [{{{}}}]", EPSILON).unwrap();
+            }
+            for child in children {
+                buffer.push(' ');
+                child.write_forest(buffer);
+            }
+        }
+        buffer.push(']');
+    }
+
+    /// LaTeX の tikz-qtree パッケージの木として書き出す.
+    pub fn export_as_qtree_src(&self) -> String {
+        let mut buffer = String::new();
+        buffer.push_str("\\Tree ");
+        self.write_qtree(&mut buffer);
+        buffer.push('\n');
+        buffer
+    }
+
+    fn write_qtree(&self, buffer: &mut String) {
+        match self {
+            ParseTree::Leaf(_) => write!(buffer, "{{{}}}", escape_latex(&self.label())).unwrap(),
+            ParseTree::Node { children, .. } => {
+                write!(buffer, "[.{{{}}} ", escape_latex(&self.label())).unwrap();
+                if children.is_empty() {
+                    write!(buffer, "{{{}}} ", EPSILON).unwrap();
+                }
+                for child in children {
+                    child.write_qtree(buffer);
+                    buffer.push(' ');
+                }
+                buffer.push(']');
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{ParseTree, ParseTreeRecorder};
+    use crate::bnf::test::{chars, Char};
+    use crate::bnf::{Expr, Grammer, Symbol};
+    use crate::item_set::generate_canonical_automaton;
+    use crate::parse_session::ParseSession;
+    use crate::parsing_table::canonical_automaton_to_lr0_parser;
+    use std::sync::Arc;
+
+    #[derive(Ord, PartialOrd, PartialEq, Eq, Clone, Debug)]
+    enum NT {
+        S,
+        L,
+    }
+
+    #[test]
+    fn test_parse_tree() {
+        // S -> { L } , L -> L S | ε
+        let grammer: Grammer<NT, Char, NT, Char> = Grammer {
+            rules: vec![
+                Expr {
+                    left: NT::S,
+                    right: vec![
+                        Symbol::Term(Char('{')),
+                        Symbol::NonTerm(NT::L),
+                        Symbol::Term(Char('}')),
+                    ],
+                    reduce_action: None,
+                },
+                Expr {
+                    left: NT::L,
+                    right: vec![Symbol::NonTerm(NT::L), Symbol::NonTerm(NT::S)],
+                    reduce_action: None,
+                },
+                Expr {
+                    left: NT::L,
+                    right: vec![],
+                    reduce_action: None,
+                },
+            ],
+        };
+        let (automaton, reduce_action) =
+            generate_canonical_automaton(grammer, NT::S, None).unwrap();
+        let mut session = ParseSession::new(Arc::new(
+            canonical_automaton_to_lr0_parser(&automaton, reduce_action).unwrap(),
+        ));
+        let mut recorder = ParseTreeRecorder::new();
+        session
+            .parse_iter_with(chars("{{}}"), &mut recorder)
+            .unwrap();
+        let tree = recorder.finish().unwrap();
+
+        let empty = || ParseTree::Node {
+            rule: 2,
+            left: NT::L,
+            children: vec![],
+        };
+        let inner = ParseTree::Node {
+            rule: 0,
+            left: NT::S,
+            children: vec![
+                ParseTree::Leaf(Char('{')),
+                empty(),
+                ParseTree::Leaf(Char('}')),
+            ],
+        };
+        assert_eq!(
+            tree,
+            ParseTree::Node {
+                rule: 0,
+                left: NT::S,
+                children: vec![
+                    ParseTree::Leaf(Char('{')),
+                    ParseTree::Node {
+                        rule: 1,
+                        left: NT::L,
+                        children: vec![empty(), inner],
+                    },
+                    ParseTree::Leaf(Char('}')),
+                ],
+            }
+        );
+
+        let dot = tree.export_as_dot("T");
+        assert!(dot.starts_with("digraph T {"));
+        assert_eq!(dot.matches(" -> ").count(), 10);
+        assert!(dot.contains("Node1 [label=\"'{'\" shape=\"plaintext\"];"));
+        // 字句4つと ε 2つ
+        assert!(dot.contains("{rank=same; Node1; Node4; Node6; Node8; Node9; Node10;}"));
+
+        let forest = tree.export_as_forest_src();
+        assert_eq!(
+            forest,
+            "\\begin{forest}\n\
+             [{S} [{'\\{'}] [{L} [{L} [{$\\varepsilon$}]] [{S} [{'\\{'}] [{L} [{$\\varepsilon$}]] [{'\\}'}]]] [{'\\}'}]]\n\
+             \\end{forest}\n"
+        );
+
+        let qtree = tree.export_as_qtree_src();
+        assert_eq!(
+            qtree,
+            "\\Tree [.{S} {'\\{'} [.{L} [.{L} {$\\varepsilon$} ] [.{S} {'\\{'} [.{L} {$\\varepsilon$} ] {'\\}'} ] ] {'\\}'} ]\n"
+        );
+
+        // 受理する前なら木はできていない.
+        let mut recorder = ParseTreeRecorder::<NT, Char>::new();
+        session.reset();
+        session.push_with(Char('{'), &mut recorder).unwrap();
+        assert!(recorder.finish().is_none());
+    }
+}
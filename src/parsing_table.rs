@@ -1,166 +1,208 @@
-use crate::{bnf::{Symbol, IntoKind,  ReduceAction}, item_set::LR0Item};
+use crate::{
+    bnf::{Derived, IntoKind, ReduceAction, Symbol},
+    cst::ConcreteSyntaxTree,
+    item_set::{
+        original_item, CanonicalAutomaton, ItemId, LR0Item, ReduceActionTable, RuleId, StateId,
+    },
+    observer::{BuildEvent, BuildObserver},
+    resolution::{Resolution, UnresolvedConflict},
+};
 use std::{
     collections::{BTreeMap, BTreeSet},
     fmt::Debug,
 };
 
-#[derive(Debug)]
-enum ActionKind {
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ActionKind {
     Accept,
     Reduce(usize),
     Shift(usize),
+    /// 結合しない演算子が続いたときなど,競合を解消して誤りにしたセル
     Error,
 }
 
-pub struct LR0Parser<NT, T,NTV,TV>
+/// LR(0)構文解析表.
+///
+/// 構文解析中に書き換えることはないので,`Arc` に包んで複数の `ParseSession` から共有できる.
+pub struct ParseTable<NT, T, NTV, TV>
 where
     NT: Debug + Clone + Eq + Ord,
     T: Debug + Clone + Eq + Ord,
-    NTV:IntoKind<NT>,
-    TV:IntoKind<T>,
+    NTV: IntoKind<NT>,
+    TV: IntoKind<T>,
 {
-    input: Vec<TV>,
     // (q,a)->p
     action_table: BTreeMap<(usize, T), ActionKind>,
+    // 競合しているものも含めたすべての動作
+    all_actions: BTreeMap<(usize, T), Vec<ActionKind>>,
     goto_table: BTreeMap<(usize, NT), usize>,
-    stack: Vec<usize>,
-    //rules
+    // 文法の規則. r_{i} は `Grammer::rules` の i 番目.
     rule_table: Vec<LR0Item<NT, T>>,
-    // value_stack
-    value_stack:Vec<ValueStackSymbol<NTV,TV>>,
-    // reduce_action_table.
-    reduce_action_table:BTreeMap<LR0Item<NT,T>,ReduceAction<NTV,TV>>,
+    // 規則番号ごとの還元動作
+    reduce_action_table: ReduceActionTable<NT, NTV, TV>,
+    start_state: usize,
 }
 
 #[derive(Debug)]
-pub enum ValueStackSymbol<NTV,TV>{
+pub enum ValueStackSymbol<NT, NTV, TV> {
     NonTerm(NTV),
     Term(TV),
+    /// 還元動作のない規則で還元したときに作られる具象構文木
+    Tree(ConcreteSyntaxTree<NT, NTV, TV>),
 }
 
 /*
     正準オートマトン　から LR(0)構文解析器を作成する.
+    オートマトンは `generate_canonical_automaton` で作った拡大文法のもの.
+    状態番号はオートマトンの状態番号をそのまま使う.
+    競合があれば表を作らずに返す (`Resolution::Error`).
 */
-pub fn canonical_automaton_to_lr0_parser<NT, T,NTV,TV>(
-    automaton: (
-        &[Vec<LR0Item<NT, T>>],
-        &BTreeMap<(Vec<LR0Item<NT, T>>, Symbol<NT, T>), Vec<LR0Item<NT, T>>>,
-        BTreeMap<LR0Item<NT,T>,ReduceAction<NTV,TV>>
-    ),
-    extended_start_symbol: NT,
-    start_symbol: NT,
-    eof_symbol: T,
-    terms: &[T],
-) -> LR0Parser<NT, T,NTV,TV>
+pub fn canonical_automaton_to_lr0_parser<NT, T, NTV, TV>(
+    automaton: &CanonicalAutomaton<NT, T>,
+    reduce_action_table: ReduceActionTable<NT, NTV, TV>,
+) -> Result<ParseTable<NT, T, NTV, TV>, Vec<UnresolvedConflict<T>>>
 where
     NT: Ord + Eq + Clone + Debug,
     T: Ord + Eq + Clone + Debug,
-    NTV:IntoKind<NT>,
-    TV:IntoKind<T>
+    NTV: IntoKind<NT>,
+    TV: IntoKind<T>,
 {
-    //状態番号をつける
+    canonical_automaton_to_lr0_parser_with(automaton, reduce_action_table, &mut ())
+}
 
-    let state_number_table: BTreeMap<_, _> = automaton
-        .0
-        .iter()
-        .enumerate()
-        .map(|(id, state)| (state.clone(), id))
-        .collect();
-    let start_rule = LR0Item {
-        left: extended_start_symbol.clone(),
-        right: vec![
-            Symbol::NonTerm(start_symbol.clone()),
-            Symbol::Term(eof_symbol.clone()),
-        ],
-        dot_pos: 0,
+/// `canonical_automaton_to_lr0_parser` と同じだが,競合している状態を observer に知らせる.
+///
+/// 競合を解消するときは `canonical_automaton_to_lr0_parser_with_resolution` で選び方を渡す.
+pub fn canonical_automaton_to_lr0_parser_with<NT, T, NTV, TV>(
+    automaton: &CanonicalAutomaton<NT, T>,
+    reduce_action_table: ReduceActionTable<NT, NTV, TV>,
+    observer: &mut impl BuildObserver<Derived<NT>, T>,
+) -> Result<ParseTable<NT, T, NTV, TV>, Vec<UnresolvedConflict<T>>>
+where
+    NT: Ord + Eq + Clone + Debug,
+    T: Ord + Eq + Clone + Debug,
+    NTV: IntoKind<NT>,
+    TV: IntoKind<T>,
+{
+    canonical_automaton_to_lr0_parser_with_resolution(
+        automaton,
+        reduce_action_table,
+        &Resolution::Error,
+        observer,
+    )
+}
+
+/// 正準オートマトンから LR(0)構文解析表を作る.
+///
+/// まずセルごとにすべての動作の候補を集めた表 (`actions` で引ける) を作り,
+/// 2つ以上の候補があるセルでは resolution で `action` の動作を1つ選ぶ.
+/// 選べないセルがあれば,それらをすべて返す.
+pub fn canonical_automaton_to_lr0_parser_with_resolution<NT, T, NTV, TV>(
+    automaton: &CanonicalAutomaton<NT, T>,
+    reduce_action_table: ReduceActionTable<NT, NTV, TV>,
+    resolution: &Resolution<T>,
+    observer: &mut impl BuildObserver<Derived<NT>, T>,
+) -> Result<ParseTable<NT, T, NTV, TV>, Vec<UnresolvedConflict<T>>>
+where
+    NT: Ord + Eq + Clone + Debug,
+    T: Ord + Eq + Clone + Debug,
+    NTV: IntoKind<NT>,
+    TV: IntoKind<T>,
+{
+    // S' -> S $・ を含む状態が受理状態.
+    let accept_item = ItemId {
+        rule: automaton.augmented_rule(),
+        dot: 2,
     };
-    let start_state_number = state_number_table
+    //終端記号. S' -> S $ があるので $ も含む.
+    let terms: Vec<T> = automaton
+        .symbols()
         .iter()
-        .find(|(state, _)| state.contains(&start_rule))
-        .unwrap()
-        .1;
-    // Accept を探すために使う.
-    let accept_rule = LR0Item {
-        left: extended_start_symbol,
-        right: vec![Symbol::NonTerm(start_symbol), Symbol::Term(eof_symbol)],
-        dot_pos: 2,
-    };
-    let mut action_table = BTreeMap::new();
+        .filter_map(|symbol| match symbol {
+            Symbol::Term(t) => Some(t.clone()),
+            Symbol::NonTerm(_) => None,
+        })
+        .collect();
     let mut goto_table = BTreeMap::new();
     //還元を行う状態の集合.
-    let reduce_states: BTreeSet<_> = automaton
-        .0
-        .iter()
+    let reduce_states: Vec<StateId> = (0..automaton.len())
         .filter(|state| {
-            let reduce_state: BTreeSet<_> = state
-                .iter()
-                .filter(|lr0item| {
-                    lr0item.dot_pos == lr0item.right.len() && ((*lr0item).clone() != accept_rule)
-                })
-                .collect();
-            if reduce_state.len() > 1 {
-                eprintln!("Reduce/Reduce conflict detected.");
-                true
-            } else if reduce_state.len() == 1 && state.len() > 1 {
-                println!(" Shift/Reduce conflict detected.");
-                true
-            } else {
-                reduce_state.len() == 1
+            if let Some(kind) = automaton.conflict(*state) {
+                observer.notify(BuildEvent::Conflict {
+                    state: *state,
+                    kind,
+                });
             }
+            !automaton.reduce_rules(*state).is_empty()
         })
         .collect();
-    let mut rule_table = vec![];
-    for (rule_number, reduce_state) in reduce_states.iter().enumerate() {
-        if let Some(state_number) = state_number_table.get(*reduce_state) {
-            rule_table.push(reduce_state[0].clone());
-            for term in terms {
-                action_table.insert(
-                    (*state_number, term.clone()),
-                    ActionKind::Reduce(rule_number),
-                );
+    let rule_table: Vec<LR0Item<NT, T>> = (0..automaton.augmented_rule())
+        .map(|rule| original_item(&automaton.rule(rule)).unwrap())
+        .collect();
+    //競合を解消しない表. 還元を行う状態ではすべての完全項で還元する.
+    let mut all_actions: BTreeMap<(usize, T), Vec<ActionKind>> = BTreeMap::new();
+    for state_number in reduce_states.iter() {
+        for rule_number in automaton.reduce_rules(*state_number) {
+            for term in &terms {
+                all_actions
+                    .entry((*state_number, term.clone()))
+                    .or_default()
+                    .push(ActionKind::Reduce(rule_number));
             }
         }
     }
 
-    for ((from, symbol), to) in automaton.1 {
-        match symbol {
-            Symbol::Term(t) => {
-                let rule = if to.contains(&accept_rule) {
-                    ActionKind::Accept
-                } else if !to.is_empty() {
-                    ActionKind::Shift(*state_number_table.get(to).unwrap())
-                } else {
-                    ActionKind::Error
-                };
-                let action_entry = ((*state_number_table.get(from).unwrap(), t.clone()), rule);
-                action_table.insert(action_entry.0, action_entry.1);
+    for from in 0..automaton.len() {
+        for (symbol, to) in automaton.transitions(from) {
+            match symbol {
+                Symbol::Term(t) => {
+                    let rule = if automaton.items(to).contains(&accept_item) {
+                        ActionKind::Accept
+                    } else {
+                        ActionKind::Shift(to)
+                    };
+                    all_actions.entry((from, t.clone())).or_default().push(rule);
+                }
+                Symbol::NonTerm(Derived::Original(nt)) => {
+                    goto_table.insert((from, nt.clone()), to);
+                }
+                // S' は右辺に現れないので遷移もない.
+                Symbol::NonTerm(Derived::Fresh(..)) => {}
             }
-            Symbol::NonTerm(nt) => {
-                goto_table.insert(
-                    (*state_number_table.get(from).unwrap(), nt.clone()),
-                    *state_number_table.get(to).unwrap(),
-                );
+        }
+    }
+
+    //競合を解消した表
+    let mut action_table = BTreeMap::new();
+    let mut unresolved = vec![];
+    for ((state, t), actions) in &all_actions {
+        match resolution.resolve(*state, t, actions, &rule_table) {
+            Ok(action) => {
+                action_table.insert((*state, t.clone()), action);
             }
+            Err(conflict) => unresolved.push(conflict),
         }
     }
+    if !unresolved.is_empty() {
+        return Err(unresolved);
+    }
 
-    LR0Parser {
-        input: vec![],
+    Ok(ParseTable {
         action_table,
+        all_actions,
         goto_table,
-        stack: vec![*start_state_number],
         rule_table,
-        value_stack: Vec::new(),
-        reduce_action_table: automaton.2
-    }
+        reduce_action_table,
+        start_state: automaton.start_state(),
+    })
 }
 
-impl<NT, T,NTV,TV> LR0Parser<NT, T,NTV,TV>
+impl<NT, T, NTV, TV> ParseTable<NT, T, NTV, TV>
 where
     NT: Clone + Eq + Ord + Debug,
     T: Clone + Eq + Ord + Debug,
-    NTV:IntoKind<NT>,
-    TV:IntoKind<T>,
+    NTV: IntoKind<NT>,
+    TV: IntoKind<T>,
 {
     pub fn export_as_latex_src(&self, terms: &[T], nonterms: &[NT])
     where
@@ -256,140 +298,186 @@ where
         println!("\\end{{tabular}}")
 
     }
-    #[allow(dead_code)]
-    pub fn reset(&mut self) {
-        
-        self.input.clear();
-        self.stack = vec![0];
+    /// 開始状態
+    pub fn start_state(&self) -> usize {
+        self.start_state
     }
 
-    pub fn input(self, input: Vec<TV>) -> Self {
-        Self {
-            input,
-            
-            action_table: self.action_table,
-            goto_table: self.goto_table,
-            stack: self.stack,
-            rule_table: self.rule_table,
-            value_stack: Vec::new(),
-            reduce_action_table: self.reduce_action_table,
-        }
+    /// 状態 state で先読み t に対する動作
+    pub fn action(&self, state: usize, t: &T) -> Option<&ActionKind> {
+        self.action_table.get(&(state, t.clone()))
     }
 
+    /// 状態 state で先読み t に対するすべての動作. 競合していれば複数になる.
+    pub fn actions(&self, state: usize, t: &T) -> &[ActionKind] {
+        self.all_actions
+            .get(&(state, t.clone()))
+            .map(|actions| actions.as_slice())
+            .unwrap_or(&[])
+    }
 
-    pub fn export_parsing_as_latex_src(&mut self) {
-        println!("generating step by step parsing for {:?}.\n", self.input.iter().map(|x|{x.into_kind()}).collect::<Vec<_>>());
+    /// 表のすべての動作. ((状態,先読み),動作)
+    pub fn action_entries(&self) -> impl Iterator<Item = (&(usize, T), &ActionKind)> {
+        self.action_table.iter()
+    }
 
-        println!("\\begin{{tabular}}{{lllll}}");
-        println!(r" & &remain input & stack & action \\ \hline");
+    /// 表のすべての遷移. ((状態,非終端記号),遷移先)
+    pub fn goto_entries(&self) -> impl Iterator<Item = (&(usize, NT), &usize)> {
+        self.goto_table.iter()
+    }
 
-        let mut step_count = 1;
+    /// 2つ以上の動作があるセル. ((状態,先読み),すべての動作)
+    pub fn conflict_entries(&self) -> impl Iterator<Item = (&(usize, T), &Vec<ActionKind>)> {
+        self.all_actions
+            .iter()
+            .filter(|(_, actions)| actions.len() > 1)
+    }
 
-        loop {
-            if let Some(x) = self.input.get(0) {
-                let top_index = self.stack.len();
-                let q = *self.stack.get(top_index - 1).unwrap();
-                if let Some(action) = self.action_table.get(&(q, x.into_kind())) {
-                    match action {
-                        ActionKind::Accept => {
-                            println!(
-                                "{} & & {} & {} & Accept \\\\ \\hline ",
-                                step_count,
-                                self.dump_remain_input(),
-                                self.dump_stack_as_latex_src(),
-                            );
-                            break;
-                        }
-                        ActionKind::Reduce(rule_number) => {
-                            println!(
-                                "{} & & {} & {} & Reduce($ r_{{{}}} $) \\\\ \\hline",
-                                step_count,
-                                self.dump_remain_input(),
-                                self.dump_stack_as_latex_src(),
-                                rule_number
-                            );
-
-                            if let Some(lr0item) = self.rule_table.get(*rule_number) {
-                                let pops = lr0item.right.len();
-                                for _ in 0..pops {
-                                    self.stack.pop();
-                                }
-                                let top_index = self.stack.len() - 1;
-                                if let Some(q) = self.stack.get(top_index) {
-                                    let a = lr0item.left.clone();
-                                    let goto_key = (*q, a);
-                                    if let Some(q_dash) = self.goto_table.get(&goto_key) {
-                                        self.stack.push(*q_dash);
-
-                                        if let Some(function) = self.reduce_action_table.get(lr0item){
-                                            let ln =self.value_stack.len();
-                                            let args=self.value_stack.split_off(ln-lr0item.dot_pos);
-                                            let v=function(args);
-                                            self.value_stack.push(ValueStackSymbol::NonTerm(v));
-                                        }else{
-                                            panic!("undefined reduce action. for {:?}",lr0item);
-                                        }     
-                                    } else {
-                                        panic!("({},{:?}) -> ?", q, lr0item.left);
-                                    }
-                                } else {
-                                    panic!("stack is empty this is not acceptable.");
-                                }
-                            } else {
-                                panic!("can't get r{} from rule_table", rule_number);
-                            }
-                        }
+    /// 還元に使う規則. r_{i} は i 番目.
+    pub fn rules(&self) -> &[LR0Item<NT, T>] {
+        &self.rule_table
+    }
 
-                        ActionKind::Shift(next_state) => {
-                            println!(
-                                "{} & & {} & {} & Shift($ q_{{{}}} $) \\\\ \\hline",
-                                step_count,
-                                self.dump_remain_input(),
-                                self.dump_stack_as_latex_src(),
-                                next_state
-                            );
-                            self.stack.push(*next_state);
-                            let value=self.input.remove(0);
-                            self.value_stack.push(ValueStackSymbol::Term(value));
-                        }
-                        ActionKind::Error => {
-                            eprintln!("error detected due to invalid input");
-                        }
-                    }
-                    step_count += 1;
-                } else {
-                    eprintln!("No action for ({},{:?})", q, x.into_kind());
-                }
-            }
+    /// 表を直接与えて作る. 保存した表を読み込むときに使う.
+    ///
+    /// conflicts にないセルの動作は action_table のものだけとする.
+    pub fn from_tables(
+        action_table: BTreeMap<(usize, T), ActionKind>,
+        conflicts: BTreeMap<(usize, T), Vec<ActionKind>>,
+        goto_table: BTreeMap<(usize, NT), usize>,
+        rule_table: Vec<LR0Item<NT, T>>,
+        reduce_action_table: ReduceActionTable<NT, NTV, TV>,
+        start_state: usize,
+    ) -> Self {
+        let mut all_actions: BTreeMap<(usize, T), Vec<ActionKind>> = action_table
+            .iter()
+            .map(|(key, action)| (key.clone(), vec![*action]))
+            .collect();
+        all_actions.extend(conflicts);
+        Self {
+            action_table,
+            all_actions,
+            goto_table,
+            rule_table,
+            reduce_action_table,
+            start_state,
         }
+    }
 
-        println!("\\end{{tabular}}")
+    /// 状態 state から非終端記号 nt で遷移する先の状態
+    pub fn goto(&self, state: usize, nt: &NT) -> Option<usize> {
+        self.goto_table.get(&(state, nt.clone())).copied()
     }
 
-    fn dump_remain_input(&self) -> String {
-        use std::fmt::Write;
-        let mut buffer = String::new();
-        for x in self.input.iter().map(|tv|{tv.into_kind()}) {
-            write!(&mut buffer, "{:?}", x).unwrap();
-        }
-        buffer
+    /// 還元に使う規則 r_{rule_number}
+    pub fn rule(&self, rule_number: usize) -> Option<&LR0Item<NT, T>> {
+        self.rule_table.get(rule_number)
     }
 
-    fn dump_stack_as_latex_src(&self) -> String {
-        use std::fmt::Write;
+    /// 規則 r_{rule_number} の還元動作
+    pub fn reduce_action(&self, rule_number: RuleId) -> Option<&ReduceAction<NT, NTV, TV>> {
+        self.reduce_action_table.get(&rule_number)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{
+        canonical_automaton_to_lr0_parser, canonical_automaton_to_lr0_parser_with_resolution,
+        ActionKind,
+    };
+    use crate::bnf::test::Char;
+    use crate::bnf::{Expr, Grammer, IntoKind, Symbol};
+    use crate::item_set::generate_canonical_automaton;
+    use crate::resolution::Resolution;
 
-        let mut buffer = String::new();
-        buffer.push_str("$ ");
-        let (last, left) = self.stack.split_last().unwrap();
-        for state in left {
-            write!(&mut buffer, "q_{{{}}}", state).unwrap();
+    #[derive(Ord, PartialOrd, PartialEq, Eq, Clone, Debug)]
+    enum NT {
+        S,
+        A,
+    }
+
+    /// 還元した規則
+    #[derive(Debug, PartialEq)]
+    struct Reduced(usize);
+
+    impl IntoKind<NT> for Reduced {
+        fn into_kind(&self) -> NT {
+            NT::A
         }
-        write!(&mut buffer, "q_{{{}}}\\leftarrow", last).unwrap();
-        buffer.push_str(" $");
-        buffer
     }
 
-    pub fn get_syntax_tree(&mut self)->Option<ValueStackSymbol<NTV,TV>>{
-        self.value_stack.pop()
-    } 
+    #[test]
+    fn test_rule_ids() {
+        // S -> A y , A -> x , A -> x
+        let grammer = || -> Grammer<NT, Char, Reduced, Char> {
+            Grammer {
+                rules: vec![
+                    Expr {
+                        left: NT::S,
+                        right: vec![Symbol::NonTerm(NT::A), Symbol::Term(Char('y'))],
+                        reduce_action: None,
+                    },
+                    Expr {
+                        left: NT::A,
+                        right: vec![Symbol::Term(Char('x'))],
+                        reduce_action: Some(Box::new(|_| Reduced(1))),
+                    },
+                    Expr {
+                        left: NT::A,
+                        right: vec![Symbol::Term(Char('x'))],
+                        reduce_action: Some(Box::new(|_| Reduced(2))),
+                    },
+                ],
+            }
+        };
+        // 選び方を渡さなければ,競合しているセルを返して表を作らない.
+        let (automaton, reduce_action) =
+            generate_canonical_automaton(grammer(), NT::S, None).unwrap();
+        let conflicts = canonical_automaton_to_lr0_parser(&automaton, reduce_action)
+            .err()
+            .unwrap();
+        assert_eq!(conflicts.len(), 3);
+        assert!(conflicts
+            .iter()
+            .all(|conflict| conflict.actions == [ActionKind::Reduce(1), ActionKind::Reduce(2)]));
+
+        let (automaton, reduce_action) =
+            generate_canonical_automaton(grammer(), NT::S, None).unwrap();
+        let table = canonical_automaton_to_lr0_parser_with_resolution(
+            &automaton,
+            reduce_action,
+            &Resolution::PreferShift,
+            &mut (),
+        )
+        .unwrap();
+
+        // r_i は文法の i 番目の規則.
+        let rules: Vec<String> = table.rules().iter().map(|rule| rule.to_string()).collect();
+        assert_eq!(rules, ["S -> A'y'・", "A -> 'x'・", "A -> 'x'・"]);
+        // 同じ規則でも還元動作は別.
+        assert_eq!(
+            table.reduce_action(1).unwrap().call(vec![]),
+            Some(Reduced(1))
+        );
+        assert_eq!(
+            table.reduce_action(2).unwrap().call(vec![]),
+            Some(Reduced(2))
+        );
+        assert!(table.reduce_action(0).is_none());
+
+        // x を読んだ状態では両方の規則で還元できる.
+        let ActionKind::Shift(state) = *table.action(table.start_state(), &Char('x')).unwrap()
+        else {
+            panic!("x must be shifted");
+        };
+        assert_eq!(
+            table.action(state, &Char('y')),
+            Some(&ActionKind::Reduce(1))
+        );
+        assert_eq!(
+            table.actions(state, &Char('y')),
+            [ActionKind::Reduce(1), ActionKind::Reduce(2)]
+        );
+    }
 }
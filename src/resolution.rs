@@ -0,0 +1,285 @@
+use crate::{
+    bnf::Symbol,
+    item_set::{LR0Item, RuleId, StateId},
+    parsing_table::ActionKind,
+};
+use std::{collections::BTreeMap, fmt::Debug};
+
+/// 1つのセルに動作が2つ以上あるときの選び方.
+pub enum Resolution<T> {
+    /// 選ばない. 競合が1つでもあれば表を作らない.
+    Error,
+    /// シフト (と受理) を選ぶ. 還元だけなら番号の一番小さい規則. yacc の既定と同じ.
+    PreferShift,
+    /// シフトできても,番号の一番小さい規則で還元する.
+    EarliestRule,
+    /// 規則と先読みの優先順位と結合性で選ぶ. 決まらなければシフト.
+    Precedence(Precedence<T>),
+}
+
+/// 優先順位が同じときの結合性
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Associativity {
+    /// 還元する. 1+1+1 は (1+1)+1.
+    Left,
+    /// シフトする. 1+1+1 は 1+(1+1).
+    Right,
+    /// どちらもしない (ActionKind::Error). 1+1+1 は誤り.
+    NonAssoc,
+}
+
+/// 終端記号の優先順位 (yacc の %left, %right, %nonassoc).
+///
+/// levels の前にある組ほど強く結合する. 規則の優先順位は右辺の最後の終端記号のもので,
+/// `with_rule` で別の終端記号のもの (yacc の %prec) にできる.
+pub struct Precedence<T> {
+    levels: Vec<(Associativity, Vec<T>)>,
+    rule_terms: BTreeMap<RuleId, T>,
+}
+
+impl<T> Precedence<T>
+where
+    T: Ord + Eq + Clone + Debug,
+{
+    pub fn new(levels: Vec<(Associativity, Vec<T>)>) -> Self {
+        Self {
+            levels,
+            rule_terms: BTreeMap::new(),
+        }
+    }
+
+    /// 規則 r_{rule} の優先順位を終端記号 t のものにする.
+    pub fn with_rule(mut self, rule: RuleId, t: T) -> Self {
+        self.rule_terms.insert(rule, t);
+        self
+    }
+
+    /// (強さの順位, 結合性). 順位は小さいほど強い.
+    fn level(&self, t: &T) -> Option<(usize, Associativity)> {
+        self.levels
+            .iter()
+            .position(|(_, terms)| terms.contains(t))
+            .map(|level| (level, self.levels[level].0))
+    }
+
+    fn rule_level<NT>(
+        &self,
+        rule: RuleId,
+        rules: &[LR0Item<NT, T>],
+    ) -> Option<(usize, Associativity)>
+    where
+        NT: Ord + Eq + Clone + Debug,
+    {
+        let t = self.rule_terms.get(&rule).or_else(|| {
+            rules[rule]
+                .right
+                .iter()
+                .rev()
+                .find_map(|symbol| match symbol {
+                    Symbol::Term(t) => Some(t),
+                    Symbol::NonTerm(_) => None,
+                })
+        })?;
+        self.level(t)
+    }
+}
+
+/// 選べなかったセル
+#[derive(Debug, PartialEq, Eq)]
+pub struct UnresolvedConflict<T> {
+    pub state: StateId,
+    pub lookahead: T,
+    pub actions: Vec<ActionKind>,
+}
+
+impl<T> Resolution<T>
+where
+    T: Ord + Eq + Clone + Debug,
+{
+    /// 状態 state で先読み lookahead に対する動作 actions から1つ選ぶ. rules は文法の規則.
+    pub fn resolve<NT>(
+        &self,
+        state: StateId,
+        lookahead: &T,
+        actions: &[ActionKind],
+        rules: &[LR0Item<NT, T>],
+    ) -> Result<ActionKind, UnresolvedConflict<T>>
+    where
+        NT: Ord + Eq + Clone + Debug,
+    {
+        if let [action] = actions {
+            return Ok(*action);
+        }
+        //シフトと受理は同じセルに1つしかない.
+        let shift = actions
+            .iter()
+            .find(|action| matches!(action, ActionKind::Shift(_) | ActionKind::Accept))
+            .copied();
+        let reduce = actions
+            .iter()
+            .filter_map(|action| match action {
+                ActionKind::Reduce(rule) => Some(*rule),
+                _ => None,
+            })
+            .min();
+        let resolved = match (self, shift, reduce) {
+            (Resolution::Error, _, _) => None,
+            (_, Some(shift), None) => Some(shift),
+            (_, None, Some(rule)) => Some(ActionKind::Reduce(rule)),
+            (Resolution::PreferShift, Some(shift), Some(_)) => Some(shift),
+            (Resolution::EarliestRule, Some(_), Some(rule)) => Some(ActionKind::Reduce(rule)),
+            (Resolution::Precedence(precedence), Some(shift), Some(rule)) => {
+                match (
+                    precedence.rule_level(rule, rules),
+                    precedence.level(lookahead),
+                ) {
+                    (Some((rule_level, _)), Some((term_level, _))) if rule_level < term_level => {
+                        Some(ActionKind::Reduce(rule))
+                    }
+                    (Some((rule_level, _)), Some((term_level, _))) if rule_level > term_level => {
+                        Some(shift)
+                    }
+                    (Some(_), Some((_, Associativity::Left))) => Some(ActionKind::Reduce(rule)),
+                    (Some(_), Some((_, Associativity::Right))) => Some(shift),
+                    (Some(_), Some((_, Associativity::NonAssoc))) => Some(ActionKind::Error),
+                    _ => Some(shift),
+                }
+            }
+            (_, None, None) => None,
+        };
+        resolved.ok_or_else(|| UnresolvedConflict {
+            state,
+            lookahead: lookahead.clone(),
+            actions: actions.to_vec(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Associativity, Precedence, Resolution, UnresolvedConflict};
+    use crate::bnf::test::{chars, Char};
+    use crate::bnf::{Expr, Grammer, Symbol};
+    use crate::item_set::generate_canonical_automaton;
+    use crate::parse_session::ParseSession;
+    use crate::parsing_table::{
+        canonical_automaton_to_lr0_parser_with_resolution, ActionKind, ParseTable,
+    };
+    use std::sync::Arc;
+
+    #[derive(Ord, PartialOrd, PartialEq, Eq, Clone, Debug)]
+    enum NT {
+        E,
+    }
+
+    fn grammer() -> Grammer<NT, Char, NT, Char> {
+        // E -> E + E | E * E | E ^ E | E < E | 1
+        let binary = |op: Char| Expr {
+            left: NT::E,
+            right: vec![
+                Symbol::NonTerm(NT::E),
+                Symbol::Term(op),
+                Symbol::NonTerm(NT::E),
+            ],
+            reduce_action: None,
+        };
+        Grammer {
+            rules: vec![
+                binary(Char('+')),
+                binary(Char('*')),
+                binary(Char('^')),
+                binary(Char('<')),
+                Expr {
+                    left: NT::E,
+                    right: vec![Symbol::Term(Char('1'))],
+                    reduce_action: None,
+                },
+            ],
+        }
+    }
+
+    fn build(
+        resolution: &Resolution<Char>,
+    ) -> Result<ParseTable<NT, Char, NT, Char>, Vec<UnresolvedConflict<Char>>> {
+        let (automaton, reduce_action) =
+            generate_canonical_automaton(grammer(), NT::E, None).unwrap();
+        canonical_automaton_to_lr0_parser_with_resolution(
+            &automaton,
+            reduce_action,
+            resolution,
+            &mut (),
+        )
+    }
+
+    fn sexpr(table: ParseTable<NT, Char, NT, Char>, input: &str) -> Option<String> {
+        let mut session = ParseSession::new(Arc::new(table));
+        match session.parse_iter(chars(input)) {
+            Ok(crate::parsing_table::ValueStackSymbol::Tree(tree)) => Some(tree.export_as_sexpr()),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn test_resolution() {
+        // E op E・ と E・op' E が同じ状態にあるので,演算子の組ごとにシフト・還元競合する.
+        let conflicts = build(&Resolution::Error).err().unwrap();
+        assert_eq!(conflicts.len(), 4 * 4);
+        assert!(conflicts.iter().all(|conflict| conflict.actions.len() == 2));
+
+        // 右に伸ばす.
+        let table = build(&Resolution::PreferShift).ok().unwrap();
+        assert_eq!(
+            sexpr(table, "1+1*1").unwrap(),
+            "(E (E '1') '+' (E (E '1') '*' (E '1')))"
+        );
+        // 左に伸ばす.
+        let table = build(&Resolution::EarliestRule).ok().unwrap();
+        assert_eq!(
+            sexpr(table, "1*1+1").unwrap(),
+            "(E (E (E '1') '*' (E '1')) '+' (E '1'))"
+        );
+
+        // ^ は右結合で一番強い. < は結合せず一番弱い.
+        let precedence = Precedence::new(vec![
+            (Associativity::Right, vec![Char('^')]),
+            (Associativity::Left, vec![Char('*')]),
+            (Associativity::Left, vec![Char('+')]),
+            (Associativity::NonAssoc, vec![Char('<')]),
+        ]);
+        let resolution = Resolution::Precedence(precedence);
+        let table = || build(&resolution).ok().unwrap();
+        assert_eq!(
+            sexpr(table(), "1+1*1+1").unwrap(),
+            "(E (E (E '1') '+' (E (E '1') '*' (E '1'))) '+' (E '1'))"
+        );
+        assert_eq!(
+            sexpr(table(), "1^1^1*1").unwrap(),
+            "(E (E (E '1') '^' (E (E '1') '^' (E '1'))) '*' (E '1'))"
+        );
+        assert_eq!(
+            sexpr(table(), "1+1<1").unwrap(),
+            "(E (E (E '1') '+' (E '1')) '<' (E '1'))"
+        );
+        assert_eq!(sexpr(table(), "1<1<1"), None);
+        // E < E・ で < を読むのは誤り. 競合しているのでセルには両方の候補が残る.
+        let table = table();
+        let (&(state, _), _) = table
+            .action_entries()
+            .find(|((_, t), action)| *t == Char('<') && **action == ActionKind::Error)
+            .unwrap();
+        assert_eq!(table.actions(state, &Char('<')).len(), 2);
+
+        // %prec で E -> E + E を ^ と同じ強さにすると 1+1*1 は (1+1)*1.
+        let precedence = Precedence::new(vec![
+            (Associativity::Left, vec![Char('^')]),
+            (Associativity::Left, vec![Char('*')]),
+            (Associativity::Left, vec![Char('+')]),
+        ])
+        .with_rule(0, Char('^'));
+        let table = build(&Resolution::Precedence(precedence)).ok().unwrap();
+        assert_eq!(
+            sexpr(table, "1+1*1").unwrap(),
+            "(E (E (E '1') '+' (E '1')) '*' (E '1'))"
+        );
+    }
+}
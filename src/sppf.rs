@@ -0,0 +1,282 @@
+use std::{collections::BTreeMap, fmt::Debug};
+
+/// 森の節の番号
+pub type ForestNodeId = usize;
+
+/// 記号節の1つの導出. 規則 r_{rule} の右辺に children を並べたもの.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct PackedNode {
+    pub rule: usize,
+    pub children: Vec<ForestNodeId>,
+    /// シフトもできるセルで還元して作った導出か. prefer-shift の絞り込みに使う.
+    pub reduced_over_shift: bool,
+}
+
+/// 森から取り出した1つの構文木
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Derivation {
+    /// position 番目の字句
+    Token(usize),
+    /// 記号節 node を規則 r_{rule} で導出したもの
+    Node {
+        node: ForestNodeId,
+        rule: usize,
+        children: Vec<Derivation>,
+    },
+}
+
+#[derive(Debug)]
+pub enum ForestNode<NT> {
+    /// position 番目の字句
+    Token { position: usize },
+    /// 字句 start..end を left として導出するすべての方法
+    Symbol {
+        left: NT,
+        start: usize,
+        end: usize,
+        packed: Vec<PackedNode>,
+    },
+}
+
+/// 共有圧縮構文森 (shared packed parse forest).
+///
+/// 同じ記号・同じ範囲の部分木は1つの記号節にまとめ,導出の違いは packed node として持つ.
+pub struct ParseForest<NT, TV> {
+    tokens: Vec<TV>,
+    nodes: Vec<ForestNode<NT>>,
+    symbol_index: BTreeMap<(NT, usize, usize), ForestNodeId>,
+    root: Option<ForestNodeId>,
+}
+
+impl<NT, TV> Default for ParseForest<NT, TV> {
+    fn default() -> Self {
+        Self {
+            tokens: vec![],
+            nodes: vec![],
+            symbol_index: BTreeMap::new(),
+            root: None,
+        }
+    }
+}
+
+impl<NT, TV> ParseForest<NT, TV>
+where
+    NT: Ord + Clone,
+{
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// 字句を末尾に追加して,その節を返す.
+    pub fn push_token(&mut self, token: TV) -> ForestNodeId {
+        let position = self.tokens.len();
+        self.tokens.push(token);
+        self.nodes.push(ForestNode::Token { position });
+        self.nodes.len() - 1
+    }
+
+    /// (left,start,end) の記号節. なければ作る.
+    pub fn symbol_node(&mut self, left: NT, start: usize, end: usize) -> ForestNodeId {
+        if let Some(id) = self.symbol_index.get(&(left.clone(), start, end)) {
+            return *id;
+        }
+        self.nodes.push(ForestNode::Symbol {
+            left: left.clone(),
+            start,
+            end,
+            packed: vec![],
+        });
+        let id = self.nodes.len() - 1;
+        self.symbol_index.insert((left, start, end), id);
+        id
+    }
+
+    /// 記号節に導出を追加する. 新しい導出なら true.
+    pub fn add_packed(&mut self, id: ForestNodeId, packed_node: PackedNode) -> bool {
+        let packed = match &mut self.nodes[id] {
+            ForestNode::Symbol { packed, .. } => packed,
+            ForestNode::Token { .. } => return false,
+        };
+        match packed
+            .iter_mut()
+            .find(|p| p.rule == packed_node.rule && p.children == packed_node.children)
+        {
+            Some(p) => {
+                p.reduced_over_shift |= packed_node.reduced_over_shift;
+                false
+            }
+            None => {
+                packed.push(packed_node);
+                true
+            }
+        }
+    }
+
+    pub fn set_root(&mut self, root: ForestNodeId) {
+        self.root = Some(root);
+    }
+
+    /// 開始記号の節
+    pub fn root(&self) -> Option<ForestNodeId> {
+        self.root
+    }
+
+    pub fn node(&self, id: ForestNodeId) -> &ForestNode<NT> {
+        &self.nodes[id]
+    }
+
+    pub fn nodes(&self) -> &[ForestNode<NT>] {
+        &self.nodes
+    }
+
+    pub fn tokens(&self) -> &[TV] {
+        &self.tokens
+    }
+
+    /// 記号節の導出. 字句の節なら空.
+    pub fn packed(&self, id: ForestNodeId) -> &[PackedNode] {
+        match &self.nodes[id] {
+            ForestNode::Symbol { packed, .. } => packed,
+            ForestNode::Token { .. } => &[],
+        }
+    }
+
+    /// 導出が2つ以上ある記号節があるか
+    pub fn is_ambiguous(&self) -> bool {
+        !self.ambiguities().is_empty()
+    }
+
+    /// 根から届く,導出が2つ以上ある記号節 (番号順)
+    pub fn ambiguities(&self) -> Vec<ForestNodeId> {
+        let mut reachable = vec![false; self.nodes.len()];
+        let mut stack: Vec<ForestNodeId> = self.root.into_iter().collect();
+        while let Some(id) = stack.pop() {
+            if std::mem::replace(&mut reachable[id], true) {
+                continue;
+            }
+            for packed in self.packed(id) {
+                stack.extend(packed.children.iter().copied());
+            }
+        }
+        (0..self.nodes.len())
+            .filter(|id| reachable[*id] && self.packed(*id).len() > 1)
+            .collect()
+    }
+
+    /// 根から作れる構文木の数. usize を超えるときは usize::MAX.
+    pub fn count_derivations(&self) -> usize {
+        let mut memo = vec![None; self.nodes.len()];
+        self.root
+            .map(|root| self.count_from(root, &mut memo))
+            .unwrap_or(0)
+    }
+
+    fn count_from(&self, id: ForestNodeId, memo: &mut Vec<Option<usize>>) -> usize {
+        if let ForestNode::Token { .. } = self.nodes[id] {
+            return 1;
+        }
+        if let Some(count) = memo[id] {
+            return count;
+        }
+        //導出が自分自身を含む場合に備えて,数え終わるまでは 0 としておく.
+        memo[id] = Some(0);
+        let count = self
+            .packed(id)
+            .iter()
+            .map(|packed| {
+                packed.children.iter().fold(1usize, |acc, child| {
+                    acc.saturating_mul(self.count_from(*child, memo))
+                })
+            })
+            .fold(0usize, |acc, n| acc.saturating_add(n));
+        memo[id] = Some(count);
+        count
+    }
+
+    /// 根から作れる構文木をすべて並べる. 数が多いときは limit 個で打ち切る.
+    ///
+    /// 森が循環している (A =>+ A) ときは,同じ節を2回通る導出は並べない.
+    pub fn derivations(&self, limit: usize) -> Vec<Derivation> {
+        match self.root {
+            Some(root) => self.derivations_from(root, limit, &mut vec![]),
+            None => vec![],
+        }
+    }
+
+    /// path は根から id の親までの記号節.
+    fn derivations_from(
+        &self,
+        id: ForestNodeId,
+        limit: usize,
+        path: &mut Vec<ForestNodeId>,
+    ) -> Vec<Derivation> {
+        let packed = match &self.nodes[id] {
+            ForestNode::Token { position } => return vec![Derivation::Token(*position)],
+            ForestNode::Symbol { packed, .. } => packed,
+        };
+        path.push(id);
+        let mut result = vec![];
+        for packed in packed {
+            if packed.children.iter().any(|child| path.contains(child)) {
+                continue;
+            }
+            //子ごとの木の直積
+            let mut partials: Vec<Vec<Derivation>> = vec![vec![]];
+            for child in &packed.children {
+                let subtrees = self.derivations_from(*child, limit, path);
+                partials = partials
+                    .iter()
+                    .flat_map(|partial| {
+                        subtrees.iter().map(move |subtree| {
+                            let mut partial = partial.clone();
+                            partial.push(subtree.clone());
+                            partial
+                        })
+                    })
+                    .take(limit)
+                    .collect();
+            }
+            for children in partials {
+                if result.len() >= limit {
+                    path.pop();
+                    return result;
+                }
+                result.push(Derivation::Node {
+                    node: id,
+                    rule: packed.rule,
+                    children,
+                });
+            }
+        }
+        path.pop();
+        result
+    }
+
+    /// 構文木を1行の S式 として書き出す. 字句は `{:?}` で書く.
+    pub fn export_derivation_as_sexpr(&self, derivation: &Derivation) -> String
+    where
+        NT: Debug,
+        TV: Debug,
+    {
+        match derivation {
+            Derivation::Token(position) => format!("{:?}", self.tokens[*position]),
+            Derivation::Node { node, children, .. } => {
+                let mut buffer = match &self.nodes[*node] {
+                    ForestNode::Symbol { left, .. } => format!("({:?}", left),
+                    ForestNode::Token { .. } => unreachable!(),
+                };
+                for child in children {
+                    buffer.push(' ');
+                    buffer.push_str(&self.export_derivation_as_sexpr(child));
+                }
+                buffer.push(')');
+                buffer
+            }
+        }
+    }
+
+    /// 字句を取り出す. 構文木を還元動作で評価するときに使う.
+    pub fn into_tokens(self) -> Vec<TV> {
+        self.tokens
+    }
+}
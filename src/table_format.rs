@@ -0,0 +1,1076 @@
+use crate::{
+    bnf::{IntoKind, Symbol},
+    item_set::{LR0Item, ReduceActionTable},
+    parsing_table::{ActionKind, ParseTable},
+};
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fmt::Debug,
+    fmt::Write,
+};
+
+/// 保存形式の版. 形式を変えたら上げる.
+pub const FORMAT_VERSION: usize = 1;
+
+/// バイナリ形式の先頭
+const MAGIC: &[u8; 4] = b"PPCT";
+
+/// 保存した表を読み込めなかった.
+#[derive(Debug, PartialEq, Eq)]
+pub enum LoadError {
+    /// 形式が壊れている.
+    Malformed(String),
+    /// 読み込めない版
+    UnsupportedVersion(usize),
+    /// 表にある記号名が,与えた記号の中にない.
+    UnknownSymbol(String),
+    /// 還元動作を登録した規則番号,または還元する規則番号が表にない.
+    UnknownRule(usize),
+    /// シフト先,遷移先,開始状態が表にない状態.
+    UnknownState(usize),
+}
+
+/// 記号を名前の番号で表した構文解析表. JSON とバイナリはこれを書き出したもの.
+///
+/// 記号の名前は `Debug` で書いたもの.
+#[derive(Debug, PartialEq, Eq)]
+struct TableImage {
+    start_state: usize,
+    terms: Vec<String>,
+    nonterms: Vec<String>,
+    /// (左辺,右辺)
+    rules: Vec<(usize, Vec<Symbol<usize, usize>>)>,
+    /// (状態,先読み,動作)
+    actions: Vec<(usize, usize, ActionKind)>,
+    /// (状態,先読み,すべての動作). 2つ以上の動作があるセルだけ.
+    conflicts: Vec<(usize, usize, Vec<ActionKind>)>,
+    /// (状態,非終端記号,遷移先)
+    gotos: Vec<(usize, usize, usize)>,
+}
+
+/// 構文解析表を JSON で書き出す. 中身を見るためのもの.
+pub fn export_as_json<NT, T, NTV, TV>(table: &ParseTable<NT, T, NTV, TV>) -> String
+where
+    NT: Debug + Clone + Eq + Ord,
+    T: Debug + Clone + Eq + Ord,
+    NTV: IntoKind<NT>,
+    TV: IntoKind<T>,
+{
+    TableImage::from_table(table).to_json()
+}
+
+/// 構文解析表を小さなバイナリで書き出す. ソースに埋め込むためのもの.
+pub fn export_as_binary<NT, T, NTV, TV>(table: &ParseTable<NT, T, NTV, TV>) -> Vec<u8>
+where
+    NT: Debug + Clone + Eq + Ord,
+    T: Debug + Clone + Eq + Ord,
+    NTV: IntoKind<NT>,
+    TV: IntoKind<T>,
+{
+    TableImage::from_table(table).to_binary()
+}
+
+/// `export_as_json` で書き出した表を読み込む.
+///
+/// 記号は terms と nonterms から名前で探す. 入力の終わりの記号も terms に入れる.
+/// reduce_actions は規則番号 (`Grammer::rules` での位置) ごとの還元動作で,ない規則は具象構文木を作る.
+/// 状態や規則の番号は調べるが,還元と遷移が食い違っていないかは調べない. 食い違った表で
+/// 構文解析すると `ParseError::CorruptTable` を返す.
+pub fn load_from_json<NT, T, NTV, TV>(
+    src: &str,
+    terms: &[T],
+    nonterms: &[NT],
+    reduce_actions: ReduceActionTable<NT, NTV, TV>,
+) -> Result<ParseTable<NT, T, NTV, TV>, LoadError>
+where
+    NT: Debug + Clone + Eq + Ord,
+    T: Debug + Clone + Eq + Ord,
+    NTV: IntoKind<NT>,
+    TV: IntoKind<T>,
+{
+    TableImage::from_json(src)?.into_table(terms, nonterms, reduce_actions)
+}
+
+/// `export_as_binary` で書き出した表を読み込む. 引数は `load_from_json` と同じ.
+pub fn load_from_binary<NT, T, NTV, TV>(
+    bytes: &[u8],
+    terms: &[T],
+    nonterms: &[NT],
+    reduce_actions: ReduceActionTable<NT, NTV, TV>,
+) -> Result<ParseTable<NT, T, NTV, TV>, LoadError>
+where
+    NT: Debug + Clone + Eq + Ord,
+    T: Debug + Clone + Eq + Ord,
+    NTV: IntoKind<NT>,
+    TV: IntoKind<T>,
+{
+    TableImage::from_binary(bytes)?.into_table(terms, nonterms, reduce_actions)
+}
+
+/// 名前から番号を引く表を作りながら,名前を順に並べる.
+struct Names<K: Ord> {
+    index: BTreeMap<K, usize>,
+    names: Vec<String>,
+}
+
+impl<K: Ord + Clone + Debug> Names<K> {
+    fn new() -> Self {
+        Self {
+            index: BTreeMap::new(),
+            names: vec![],
+        }
+    }
+
+    fn get(&mut self, key: &K) -> usize {
+        if let Some(index) = self.index.get(key) {
+            return *index;
+        }
+        self.names.push(format!("{:?}", key));
+        self.index.insert(key.clone(), self.names.len() - 1);
+        self.names.len() - 1
+    }
+}
+
+impl TableImage {
+    fn from_table<NT, T, NTV, TV>(table: &ParseTable<NT, T, NTV, TV>) -> Self
+    where
+        NT: Debug + Clone + Eq + Ord,
+        T: Debug + Clone + Eq + Ord,
+        NTV: IntoKind<NT>,
+        TV: IntoKind<T>,
+    {
+        let mut terms = Names::new();
+        let mut nonterms = Names::new();
+        let rules = table
+            .rules()
+            .iter()
+            .map(|rule| {
+                let right = rule
+                    .right
+                    .iter()
+                    .map(|symbol| match symbol {
+                        Symbol::Term(t) => Symbol::Term(terms.get(t)),
+                        Symbol::NonTerm(nt) => Symbol::NonTerm(nonterms.get(nt)),
+                    })
+                    .collect();
+                (nonterms.get(&rule.left), right)
+            })
+            .collect();
+        let actions = table
+            .action_entries()
+            .map(|((state, t), action)| (*state, terms.get(t), *action))
+            .collect();
+        let conflicts = table
+            .conflict_entries()
+            .map(|((state, t), actions)| (*state, terms.get(t), actions.clone()))
+            .collect();
+        let gotos = table
+            .goto_entries()
+            .map(|((state, nt), to)| (*state, nonterms.get(nt), *to))
+            .collect();
+        Self {
+            start_state: table.start_state(),
+            terms: terms.names,
+            nonterms: nonterms.names,
+            rules,
+            actions,
+            conflicts,
+            gotos,
+        }
+    }
+
+    fn into_table<NT, T, NTV, TV>(
+        self,
+        terms: &[T],
+        nonterms: &[NT],
+        reduce_actions: ReduceActionTable<NT, NTV, TV>,
+    ) -> Result<ParseTable<NT, T, NTV, TV>, LoadError>
+    where
+        NT: Debug + Clone + Eq + Ord,
+        T: Debug + Clone + Eq + Ord,
+        NTV: IntoKind<NT>,
+        TV: IntoKind<T>,
+    {
+        fn resolve<K: Clone + Debug>(names: &[String], symbols: &[K]) -> Result<Vec<K>, LoadError> {
+            names
+                .iter()
+                .map(|name| {
+                    symbols
+                        .iter()
+                        .find(|symbol| format!("{:?}", symbol) == *name)
+                        .cloned()
+                        .ok_or_else(|| LoadError::UnknownSymbol(name.clone()))
+                })
+                .collect()
+        }
+        let terms = resolve(&self.terms, terms)?;
+        let nonterms = resolve(&self.nonterms, nonterms)?;
+        let term = |index: usize| {
+            terms
+                .get(index)
+                .cloned()
+                .ok_or_else(|| LoadError::Malformed(format!("no term {}", index)))
+        };
+        let nonterm = |index: usize| {
+            nonterms
+                .get(index)
+                .cloned()
+                .ok_or_else(|| LoadError::Malformed(format!("no nonterm {}", index)))
+        };
+
+        let mut rule_table = vec![];
+        for (left, right) in self.rules {
+            let right = right
+                .into_iter()
+                .map(|symbol| match symbol {
+                    Symbol::Term(t) => term(t).map(Symbol::Term),
+                    Symbol::NonTerm(nt) => nonterm(nt).map(Symbol::NonTerm),
+                })
+                .collect::<Result<Vec<_>, _>>()?;
+            rule_table.push(LR0Item {
+                left: nonterm(left)?,
+                dot_pos: right.len(),
+                right,
+            });
+        }
+        if let Some(rule) = reduce_actions
+            .keys()
+            .find(|rule| **rule >= rule_table.len())
+        {
+            return Err(LoadError::UnknownRule(*rule));
+        }
+
+        //表にある状態は,動作か遷移の行を持つ状態.
+        let states: BTreeSet<usize> = self
+            .actions
+            .iter()
+            .map(|(state, _, _)| *state)
+            .chain(self.gotos.iter().map(|(state, _, _)| *state))
+            .collect();
+        let state = |state: usize| {
+            if states.contains(&state) {
+                Ok(state)
+            } else {
+                Err(LoadError::UnknownState(state))
+            }
+        };
+        let action = |action: ActionKind| match action {
+            ActionKind::Shift(to) => state(to).map(ActionKind::Shift),
+            ActionKind::Reduce(rule) if rule >= rule_table.len() => {
+                Err(LoadError::UnknownRule(rule))
+            }
+            action => Ok(action),
+        };
+        let start_state = state(self.start_state)?;
+
+        let mut action_table = BTreeMap::new();
+        for (from, t, kind) in self.actions {
+            action_table.insert((from, term(t)?), action(kind)?);
+        }
+        let mut conflicts = BTreeMap::new();
+        for (from, t, kinds) in self.conflicts {
+            let kinds = kinds.into_iter().map(action).collect::<Result<_, _>>()?;
+            conflicts.insert((state(from)?, term(t)?), kinds);
+        }
+        let mut goto_table = BTreeMap::new();
+        for (from, nt, to) in self.gotos {
+            goto_table.insert((from, nonterm(nt)?), state(to)?);
+        }
+        Ok(ParseTable::from_tables(
+            action_table,
+            conflicts,
+            goto_table,
+            rule_table,
+            reduce_actions,
+            start_state,
+        ))
+    }
+
+    fn to_json(&self) -> String {
+        let mut buffer = String::new();
+        let strings = |names: &[String]| {
+            names
+                .iter()
+                .map(|name| Json::String(name.clone()).to_string())
+                .collect::<Vec<_>>()
+                .join(", ")
+        };
+        writeln!(&mut buffer, "{{").unwrap();
+        writeln!(&mut buffer, "  \"version\": {},", FORMAT_VERSION).unwrap();
+        writeln!(&mut buffer, "  \"start_state\": {},", self.start_state).unwrap();
+        writeln!(&mut buffer, "  \"terms\": [{}],", strings(&self.terms)).unwrap();
+        writeln!(
+            &mut buffer,
+            "  \"nonterms\": [{}],",
+            strings(&self.nonterms)
+        )
+        .unwrap();
+        let rules: Vec<String> = self
+            .rules
+            .iter()
+            .map(|(left, right)| {
+                let right: Vec<String> = right
+                    .iter()
+                    .map(|symbol| match symbol {
+                        Symbol::Term(t) => format!("[\"t\", {}]", t),
+                        Symbol::NonTerm(nt) => format!("[\"n\", {}]", nt),
+                    })
+                    .collect();
+                format!("{{\"left\": {}, \"right\": [{}]}}", left, right.join(", "))
+            })
+            .collect();
+        let actions: Vec<String> = self
+            .actions
+            .iter()
+            .map(|(state, t, action)| format!("[{}, {}, {}]", state, t, action_to_json(action)))
+            .collect();
+        let conflicts: Vec<String> = self
+            .conflicts
+            .iter()
+            .map(|(state, t, actions)| {
+                let actions: Vec<String> = actions.iter().map(action_to_json).collect();
+                format!("[{}, {}, [{}]]", state, t, actions.join(", "))
+            })
+            .collect();
+        let gotos: Vec<String> = self
+            .gotos
+            .iter()
+            .map(|(state, nt, to)| format!("[{}, {}, {}]", state, nt, to))
+            .collect();
+        for (key, lines, last) in [
+            ("rules", rules, false),
+            ("actions", actions, false),
+            ("conflicts", conflicts, false),
+            ("gotos", gotos, true),
+        ] {
+            if lines.is_empty() {
+                write!(&mut buffer, "  \"{}\": []", key).unwrap();
+            } else {
+                write!(
+                    &mut buffer,
+                    "  \"{}\": [\n    {}\n  ]",
+                    key,
+                    lines.join(",\n    ")
+                )
+                .unwrap();
+            }
+            writeln!(&mut buffer, "{}", if last { "" } else { "," }).unwrap();
+        }
+        writeln!(&mut buffer, "}}").unwrap();
+        buffer
+    }
+
+    fn from_json(src: &str) -> Result<Self, LoadError> {
+        let json = JsonParser::parse(src)?;
+        let version = json.field("version")?.number()?;
+        if version != FORMAT_VERSION {
+            return Err(LoadError::UnsupportedVersion(version));
+        }
+        let strings = |key: &str| -> Result<Vec<String>, LoadError> {
+            json.field(key)?
+                .array()?
+                .iter()
+                .map(|name| name.string().map(|name| name.to_owned()))
+                .collect()
+        };
+        let triples = |key: &str| -> Result<Vec<(usize, usize, &Json)>, LoadError> {
+            json.field(key)?
+                .array()?
+                .iter()
+                .map(|entry| match entry.array()? {
+                    [state, symbol, value] => Ok((state.number()?, symbol.number()?, value)),
+                    _ => Err(LoadError::Malformed(format!("{} entry", key))),
+                })
+                .collect()
+        };
+        let rules = json
+            .field("rules")?
+            .array()?
+            .iter()
+            .map(|rule| {
+                let right = rule
+                    .field("right")?
+                    .array()?
+                    .iter()
+                    .map(|symbol| match symbol.array()? {
+                        [kind, index] => match kind.string()? {
+                            "t" => Ok(Symbol::Term(index.number()?)),
+                            "n" => Ok(Symbol::NonTerm(index.number()?)),
+                            kind => Err(LoadError::Malformed(format!("symbol kind {}", kind))),
+                        },
+                        _ => Err(LoadError::Malformed("symbol".to_owned())),
+                    })
+                    .collect::<Result<_, _>>()?;
+                Ok((rule.field("left")?.number()?, right))
+            })
+            .collect::<Result<_, LoadError>>()?;
+        Ok(Self {
+            start_state: json.field("start_state")?.number()?,
+            terms: strings("terms")?,
+            nonterms: strings("nonterms")?,
+            rules,
+            actions: triples("actions")?
+                .into_iter()
+                .map(|(state, t, action)| Ok((state, t, action_from_json(action)?)))
+                .collect::<Result<_, LoadError>>()?,
+            conflicts: triples("conflicts")?
+                .into_iter()
+                .map(|(state, t, actions)| {
+                    let actions = actions
+                        .array()?
+                        .iter()
+                        .map(action_from_json)
+                        .collect::<Result<_, _>>()?;
+                    Ok((state, t, actions))
+                })
+                .collect::<Result<_, LoadError>>()?,
+            gotos: triples("gotos")?
+                .into_iter()
+                .map(|(state, nt, to)| Ok((state, nt, to.number()?)))
+                .collect::<Result<_, LoadError>>()?,
+        })
+    }
+
+    fn to_binary(&self) -> Vec<u8> {
+        let mut writer = BinaryWriter(MAGIC.to_vec());
+        writer.number(FORMAT_VERSION);
+        writer.number(self.start_state);
+        for names in [&self.terms, &self.nonterms] {
+            writer.number(names.len());
+            for name in names {
+                writer.number(name.len());
+                writer.0.extend_from_slice(name.as_bytes());
+            }
+        }
+        writer.number(self.rules.len());
+        for (left, right) in &self.rules {
+            writer.number(*left);
+            writer.number(right.len());
+            for symbol in right {
+                match symbol {
+                    Symbol::Term(t) => {
+                        writer.0.push(0);
+                        writer.number(*t);
+                    }
+                    Symbol::NonTerm(nt) => {
+                        writer.0.push(1);
+                        writer.number(*nt);
+                    }
+                }
+            }
+        }
+        writer.number(self.actions.len());
+        for (state, t, action) in &self.actions {
+            writer.number(*state);
+            writer.number(*t);
+            writer.action(action);
+        }
+        writer.number(self.conflicts.len());
+        for (state, t, actions) in &self.conflicts {
+            writer.number(*state);
+            writer.number(*t);
+            writer.number(actions.len());
+            for action in actions {
+                writer.action(action);
+            }
+        }
+        writer.number(self.gotos.len());
+        for (state, nt, to) in &self.gotos {
+            writer.number(*state);
+            writer.number(*nt);
+            writer.number(*to);
+        }
+        writer.0
+    }
+
+    fn from_binary(bytes: &[u8]) -> Result<Self, LoadError> {
+        if !bytes.starts_with(MAGIC) {
+            return Err(LoadError::Malformed("not a parse table".to_owned()));
+        }
+        let mut reader = BinaryReader {
+            bytes,
+            position: MAGIC.len(),
+        };
+        let version = reader.number()?;
+        if version != FORMAT_VERSION {
+            return Err(LoadError::UnsupportedVersion(version));
+        }
+        let start_state = reader.number()?;
+        let terms = reader.names()?;
+        let nonterms = reader.names()?;
+        let mut rules = vec![];
+        for _ in 0..reader.number()? {
+            let left = reader.number()?;
+            let mut right = vec![];
+            for _ in 0..reader.number()? {
+                right.push(match reader.byte()? {
+                    0 => Symbol::Term(reader.number()?),
+                    1 => Symbol::NonTerm(reader.number()?),
+                    kind => return Err(LoadError::Malformed(format!("symbol kind {}", kind))),
+                });
+            }
+            rules.push((left, right));
+        }
+        let mut actions = vec![];
+        for _ in 0..reader.number()? {
+            actions.push((reader.number()?, reader.number()?, reader.action()?));
+        }
+        let mut conflicts = vec![];
+        for _ in 0..reader.number()? {
+            let state = reader.number()?;
+            let t = reader.number()?;
+            let mut cell = vec![];
+            for _ in 0..reader.number()? {
+                cell.push(reader.action()?);
+            }
+            conflicts.push((state, t, cell));
+        }
+        let mut gotos = vec![];
+        for _ in 0..reader.number()? {
+            gotos.push((reader.number()?, reader.number()?, reader.number()?));
+        }
+        if reader.position != bytes.len() {
+            return Err(LoadError::Malformed("trailing bytes".to_owned()));
+        }
+        Ok(Self {
+            start_state,
+            terms,
+            nonterms,
+            rules,
+            actions,
+            conflicts,
+            gotos,
+        })
+    }
+}
+
+fn action_to_json(action: &ActionKind) -> String {
+    match action {
+        ActionKind::Accept => "[\"accept\"]".to_owned(),
+        ActionKind::Shift(state) => format!("[\"shift\", {}]", state),
+        ActionKind::Reduce(rule) => format!("[\"reduce\", {}]", rule),
+        ActionKind::Error => "[\"error\"]".to_owned(),
+    }
+}
+
+fn action_from_json(json: &Json) -> Result<ActionKind, LoadError> {
+    match json.array()? {
+        [kind] if kind.string()? == "accept" => Ok(ActionKind::Accept),
+        [kind] if kind.string()? == "error" => Ok(ActionKind::Error),
+        [kind, n] if kind.string()? == "shift" => Ok(ActionKind::Shift(n.number()?)),
+        [kind, n] if kind.string()? == "reduce" => Ok(ActionKind::Reduce(n.number()?)),
+        _ => Err(LoadError::Malformed("action".to_owned())),
+    }
+}
+
+/// 保存形式で使う分だけの JSON の値
+#[derive(Debug)]
+enum Json {
+    Number(usize),
+    String(String),
+    Array(Vec<Json>),
+    Object(Vec<(String, Json)>),
+}
+
+impl std::fmt::Display for Json {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Json::Number(n) => write!(f, "{}", n),
+            Json::String(s) => {
+                f.write_char('"')?;
+                for c in s.chars() {
+                    match c {
+                        '"' => f.write_str("\\\"")?,
+                        '\\' => f.write_str("\\\\")?,
+                        '\n' => f.write_str("\\n")?,
+                        '\t' => f.write_str("\\t")?,
+                        '\r' => f.write_str("\\r")?,
+                        c if (c as u32) < 0x20 => write!(f, "\\u{:04x}", c as u32)?,
+                        c => f.write_char(c)?,
+                    }
+                }
+                f.write_char('"')
+            }
+            Json::Array(values) => {
+                let values: Vec<String> = values.iter().map(|value| value.to_string()).collect();
+                write!(f, "[{}]", values.join(", "))
+            }
+            Json::Object(fields) => {
+                let fields: Vec<String> = fields
+                    .iter()
+                    .map(|(key, value)| format!("{}: {}", Json::String(key.clone()), value))
+                    .collect();
+                write!(f, "{{{}}}", fields.join(", "))
+            }
+        }
+    }
+}
+
+impl Json {
+    fn field(&self, key: &str) -> Result<&Json, LoadError> {
+        match self {
+            Json::Object(fields) => fields
+                .iter()
+                .find(|(k, _)| k == key)
+                .map(|(_, value)| value)
+                .ok_or_else(|| LoadError::Malformed(format!("missing {}", key))),
+            _ => Err(LoadError::Malformed(format!("expected object for {}", key))),
+        }
+    }
+
+    fn number(&self) -> Result<usize, LoadError> {
+        match self {
+            Json::Number(n) => Ok(*n),
+            _ => Err(LoadError::Malformed("expected number".to_owned())),
+        }
+    }
+
+    fn string(&self) -> Result<&str, LoadError> {
+        match self {
+            Json::String(s) => Ok(s),
+            _ => Err(LoadError::Malformed("expected string".to_owned())),
+        }
+    }
+
+    fn array(&self) -> Result<&[Json], LoadError> {
+        match self {
+            Json::Array(values) => Ok(values),
+            _ => Err(LoadError::Malformed("expected array".to_owned())),
+        }
+    }
+}
+
+struct JsonParser<'a> {
+    chars: std::iter::Peekable<std::str::Chars<'a>>,
+}
+
+impl JsonParser<'_> {
+    fn parse(src: &str) -> Result<Json, LoadError> {
+        let mut parser = JsonParser {
+            chars: src.chars().peekable(),
+        };
+        let value = parser.value()?;
+        parser.skip_whitespace();
+        match parser.chars.next() {
+            None => Ok(value),
+            Some(c) => Err(LoadError::Malformed(format!("unexpected {:?}", c))),
+        }
+    }
+
+    fn skip_whitespace(&mut self) {
+        while self.chars.next_if(|c| c.is_whitespace()).is_some() {}
+    }
+
+    fn expect(&mut self, expected: char) -> Result<(), LoadError> {
+        self.skip_whitespace();
+        match self.chars.next() {
+            Some(c) if c == expected => Ok(()),
+            other => Err(LoadError::Malformed(format!(
+                "expected {:?}, found {:?}",
+                expected, other
+            ))),
+        }
+    }
+
+    /// 開き括弧の後の要素を,区切り , と閉じ括弧 close まで読む.
+    fn elements<V>(
+        &mut self,
+        close: char,
+        mut element: impl FnMut(&mut Self) -> Result<V, LoadError>,
+    ) -> Result<Vec<V>, LoadError> {
+        let mut values = vec![];
+        self.skip_whitespace();
+        if self.chars.next_if_eq(&close).is_some() {
+            return Ok(values);
+        }
+        loop {
+            values.push(element(self)?);
+            self.skip_whitespace();
+            match self.chars.next() {
+                Some(',') => {}
+                Some(c) if c == close => return Ok(values),
+                other => return Err(LoadError::Malformed(format!("unexpected {:?}", other))),
+            }
+        }
+    }
+
+    fn value(&mut self) -> Result<Json, LoadError> {
+        self.skip_whitespace();
+        match self.chars.peek() {
+            Some('[') => {
+                self.chars.next();
+                Ok(Json::Array(self.elements(']', |parser| parser.value())?))
+            }
+            Some('{') => {
+                self.chars.next();
+                Ok(Json::Object(self.elements('}', |parser| {
+                    parser.skip_whitespace();
+                    let key = parser.string()?;
+                    parser.expect(':')?;
+                    Ok((key, parser.value()?))
+                })?))
+            }
+            Some('"') => Ok(Json::String(self.string()?)),
+            Some(c) if c.is_ascii_digit() => {
+                let mut digits = String::new();
+                while let Some(c) = self.chars.next_if(|c| c.is_ascii_digit()) {
+                    digits.push(c);
+                }
+                digits
+                    .parse()
+                    .map(Json::Number)
+                    .map_err(|_| LoadError::Malformed(format!("number {}", digits)))
+            }
+            other => Err(LoadError::Malformed(format!("unexpected {:?}", other))),
+        }
+    }
+
+    fn string(&mut self) -> Result<String, LoadError> {
+        self.expect('"')?;
+        let mut s = String::new();
+        loop {
+            match self.chars.next() {
+                Some('"') => return Ok(s),
+                Some('\\') => s.push(match self.chars.next() {
+                    Some('n') => '\n',
+                    Some('t') => '\t',
+                    Some('r') => '\r',
+                    Some('u') => {
+                        let code: String = (0..4).filter_map(|_| self.chars.next()).collect();
+                        u32::from_str_radix(&code, 16)
+                            .ok()
+                            .and_then(char::from_u32)
+                            .ok_or_else(|| LoadError::Malformed(format!("\\u{}", code)))?
+                    }
+                    Some(c @ ('"' | '\\' | '/')) => c,
+                    other => return Err(LoadError::Malformed(format!("escape {:?}", other))),
+                }),
+                Some(c) => s.push(c),
+                None => return Err(LoadError::Malformed("unterminated string".to_owned())),
+            }
+        }
+    }
+}
+
+/// 数は7ビットずつ下から書く (LEB128).
+struct BinaryWriter(Vec<u8>);
+
+impl BinaryWriter {
+    fn number(&mut self, mut n: usize) {
+        while n >= 0x80 {
+            self.0.push((n as u8 & 0x7f) | 0x80);
+            n >>= 7;
+        }
+        self.0.push(n as u8);
+    }
+
+    fn action(&mut self, action: &ActionKind) {
+        match action {
+            ActionKind::Accept => self.0.push(0),
+            ActionKind::Shift(state) => {
+                self.0.push(1);
+                self.number(*state);
+            }
+            ActionKind::Reduce(rule) => {
+                self.0.push(2);
+                self.number(*rule);
+            }
+            ActionKind::Error => self.0.push(3),
+        }
+    }
+}
+
+struct BinaryReader<'a> {
+    bytes: &'a [u8],
+    position: usize,
+}
+
+impl BinaryReader<'_> {
+    fn byte(&mut self) -> Result<u8, LoadError> {
+        let byte = *self
+            .bytes
+            .get(self.position)
+            .ok_or_else(|| LoadError::Malformed("unexpected end".to_owned()))?;
+        self.position += 1;
+        Ok(byte)
+    }
+
+    fn number(&mut self) -> Result<usize, LoadError> {
+        let mut n = 0usize;
+        for shift in (0..usize::BITS).step_by(7) {
+            let byte = self.byte()?;
+            n |= ((byte & 0x7f) as usize) << shift;
+            if byte & 0x80 == 0 {
+                return Ok(n);
+            }
+        }
+        Err(LoadError::Malformed("number too large".to_owned()))
+    }
+
+    fn names(&mut self) -> Result<Vec<String>, LoadError> {
+        let mut names = vec![];
+        for _ in 0..self.number()? {
+            let len = self.number()?;
+            let end = self
+                .position
+                .checked_add(len)
+                .ok_or_else(|| LoadError::Malformed("name too long".to_owned()))?;
+            let bytes = self
+                .bytes
+                .get(self.position..end)
+                .ok_or_else(|| LoadError::Malformed("unexpected end".to_owned()))?;
+            self.position = end;
+            names.push(
+                String::from_utf8(bytes.to_vec())
+                    .map_err(|_| LoadError::Malformed("name is not utf-8".to_owned()))?,
+            );
+        }
+        Ok(names)
+    }
+
+    fn action(&mut self) -> Result<ActionKind, LoadError> {
+        match self.byte()? {
+            0 => Ok(ActionKind::Accept),
+            1 => Ok(ActionKind::Shift(self.number()?)),
+            2 => Ok(ActionKind::Reduce(self.number()?)),
+            3 => Ok(ActionKind::Error),
+            kind => Err(LoadError::Malformed(format!("action kind {}", kind))),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{
+        export_as_binary, export_as_json, load_from_binary, load_from_json, BinaryWriter,
+        LoadError, TableImage, FORMAT_VERSION, MAGIC,
+    };
+    use crate::bnf::test::{chars, Char};
+    use crate::bnf::{Expr, Grammer, ReduceAction, Symbol};
+    use crate::item_set::generate_canonical_automaton;
+    use crate::parse_session::{ParseError, ParseSession};
+    use crate::parsing_table::{
+        canonical_automaton_to_lr0_parser, ActionKind, ParseTable, ValueStackSymbol,
+    };
+    use std::collections::BTreeMap;
+    use std::sync::Arc;
+
+    #[derive(Ord, PartialOrd, PartialEq, Eq, Clone, Debug)]
+    enum NT {
+        E,
+        P,
+    }
+
+    fn grammer() -> Grammer<NT, Char, NT, Char> {
+        // E -> E + P | P , P -> 1 | "
+        Grammer {
+            rules: vec![
+                Expr {
+                    left: NT::E,
+                    right: vec![
+                        Symbol::NonTerm(NT::E),
+                        Symbol::Term(Char('+')),
+                        Symbol::NonTerm(NT::P),
+                    ],
+                    reduce_action: None,
+                },
+                Expr {
+                    left: NT::E,
+                    right: vec![Symbol::NonTerm(NT::P)],
+                    reduce_action: None,
+                },
+                Expr {
+                    left: NT::P,
+                    right: vec![Symbol::Term(Char('1'))],
+                    reduce_action: None,
+                },
+                Expr {
+                    left: NT::P,
+                    right: vec![Symbol::Term(Char('"'))],
+                    reduce_action: None,
+                },
+            ],
+        }
+    }
+
+    fn no_actions() -> BTreeMap<usize, ReduceAction<NT, NT, Char>> {
+        BTreeMap::new()
+    }
+
+    const TERMS: [Char; 4] = [Char('+'), Char('1'), Char('"'), Char('$')];
+    const NONTERMS: [NT; 2] = [NT::E, NT::P];
+
+    fn assert_same_table(
+        loaded: &ParseTable<NT, Char, NT, Char>,
+        table: &ParseTable<NT, Char, NT, Char>,
+    ) {
+        assert_eq!(loaded.start_state(), table.start_state());
+        assert!(loaded.action_entries().eq(table.action_entries()));
+        assert!(loaded.conflict_entries().eq(table.conflict_entries()));
+        assert!(loaded.goto_entries().eq(table.goto_entries()));
+        assert_eq!(loaded.rules(), table.rules());
+    }
+
+    #[test]
+    fn test_table_format() {
+        let (automaton, reduce_action) =
+            generate_canonical_automaton(grammer(), NT::E, None).unwrap();
+        let table = canonical_automaton_to_lr0_parser(&automaton, reduce_action).unwrap();
+
+        let json = export_as_json(&table);
+        let loaded = load_from_json(&json, &TERMS, &NONTERMS, no_actions()).unwrap();
+        assert_same_table(&loaded, &table);
+
+        let binary = export_as_binary(&table);
+        assert!(binary.len() < json.len());
+        let loaded = load_from_binary(&binary, &TERMS, &NONTERMS, no_actions()).unwrap();
+        assert_same_table(&loaded, &table);
+
+        // 文法の3番目の規則 P -> 1 に還元動作をつけて読み込む.
+        let mut reduce_actions = no_actions();
+        reduce_actions.insert(2, Box::new(|_| NT::P));
+        let loaded = load_from_binary(&binary, &TERMS, &NONTERMS, reduce_actions).unwrap();
+        let mut session = ParseSession::new(Arc::new(loaded));
+        match session.parse_iter(chars("1+\"")).unwrap() {
+            ValueStackSymbol::Tree(tree) => {
+                assert_eq!(tree.export_as_sexpr(), "(E (E P) '+' (P '\"'))")
+            }
+            other => panic!("{:?}", other),
+        }
+
+        assert_eq!(
+            load_from_json(&json, &TERMS[..3], &NONTERMS, no_actions()).err(),
+            Some(LoadError::UnknownSymbol("'$'".to_owned()))
+        );
+        assert_eq!(
+            load_from_json(
+                &json.replace("\"version\": 1", "\"version\": 2"),
+                &TERMS,
+                &NONTERMS,
+                no_actions()
+            )
+            .err(),
+            Some(LoadError::UnsupportedVersion(2))
+        );
+        assert!(matches!(
+            load_from_binary(&binary[..binary.len() - 1], &TERMS, &NONTERMS, no_actions()),
+            Err(LoadError::Malformed(_))
+        ));
+        // 名前の長さが大きすぎる.
+        let mut writer = BinaryWriter(MAGIC.to_vec());
+        writer.number(FORMAT_VERSION);
+        writer.number(0);
+        writer.number(1);
+        writer.number(usize::MAX);
+        assert!(matches!(
+            load_from_binary(&writer.0, &TERMS, &NONTERMS, no_actions()),
+            Err(LoadError::Malformed(_))
+        ));
+    }
+
+    #[test]
+    fn test_table_format_validation() {
+        let (automaton, reduce_action) =
+            generate_canonical_automaton(grammer(), NT::E, None).unwrap();
+        let table = canonical_automaton_to_lr0_parser(&automaton, reduce_action).unwrap();
+        let json = export_as_json(&table);
+        //読み込んだ表を書き換えて,表に戻す.
+        let load = |edit: &dyn Fn(&mut TableImage)| {
+            let mut image = TableImage::from_json(&json).unwrap();
+            edit(&mut image);
+            image.into_table(&TERMS, &NONTERMS, no_actions()).err()
+        };
+        assert_eq!(load(&|_| ()), None);
+        assert_eq!(
+            load(&|image| {
+                let (_, _, action) = image
+                    .actions
+                    .iter_mut()
+                    .find(|(_, _, action)| matches!(action, ActionKind::Reduce(_)))
+                    .unwrap();
+                *action = ActionKind::Reduce(99);
+            }),
+            Some(LoadError::UnknownRule(99))
+        );
+        assert_eq!(
+            load(&|image| {
+                let (_, _, action) = image
+                    .actions
+                    .iter_mut()
+                    .find(|(_, _, action)| matches!(action, ActionKind::Shift(_)))
+                    .unwrap();
+                *action = ActionKind::Shift(99);
+            }),
+            Some(LoadError::UnknownState(99))
+        );
+        assert_eq!(
+            load(&|image| image.gotos[0].2 = 99),
+            Some(LoadError::UnknownState(99))
+        );
+        assert_eq!(
+            load(&|image| image.start_state = 99),
+            Some(LoadError::UnknownState(99))
+        );
+        assert_eq!(
+            load(&|image| image.conflicts.push((
+                0,
+                0,
+                vec![ActionKind::Shift(1), ActionKind::Reduce(99)]
+            ))),
+            Some(LoadError::UnknownRule(99))
+        );
+        assert_eq!(
+            load(&|image| image.conflicts.push((
+                99,
+                0,
+                vec![ActionKind::Shift(1), ActionKind::Reduce(0)]
+            ))),
+            Some(LoadError::UnknownState(99))
+        );
+    }
+
+    #[test]
+    fn test_inconsistent_table() {
+        let (automaton, reduce_action) =
+            generate_canonical_automaton(grammer(), NT::E, None).unwrap();
+        let table = canonical_automaton_to_lr0_parser(&automaton, reduce_action).unwrap();
+        let start = table.start_state();
+        let json = export_as_json(&table);
+        //形は正しいが食い違った表を読み込んで,構文解析する.
+        let parse = |edit: &dyn Fn(&mut TableImage)| {
+            let mut image = TableImage::from_json(&json).unwrap();
+            edit(&mut image);
+            let table = image.into_table(&TERMS, &NONTERMS, no_actions()).unwrap();
+            ParseSession::new(Arc::new(table))
+                .parse_iter(chars("1+1"))
+                .err()
+        };
+        assert_eq!(parse(&|_| ()), None);
+        // 1 をシフトして P -> 1 で還元するが,開始状態から P への遷移がない.
+        let one = match table.action(start, &Char('1')) {
+            Some(ActionKind::Shift(state)) => *state,
+            action => panic!("{:?}", action),
+        };
+        assert_eq!(
+            parse(&|image| image.gotos.retain(|(from, _, _)| *from != start)),
+            Some(ParseError::CorruptTable {
+                state: one,
+                rule: 2
+            })
+        );
+        // 何も積んでいない開始状態で E -> E + P に還元する.
+        assert_eq!(
+            parse(&|image| {
+                for (from, _, action) in &mut image.actions {
+                    if *from == start {
+                        *action = ActionKind::Reduce(0);
+                    }
+                }
+            }),
+            Some(ParseError::CorruptTable {
+                state: start,
+                rule: 0
+            })
+        );
+    }
+}
@@ -0,0 +1,744 @@
+use crate::{
+    bnf::{Derived, Expr, Grammer, IntoKind, ReduceAction, ReduceArgs, ReduceFn, Symbol},
+    cst::ConcreteSyntaxTree,
+    parsing_table::ValueStackSymbol,
+};
+use std::{collections::BTreeMap, fmt::Debug, sync::Arc};
+
+/// 後置記法で書いた元の文法での還元の列の要素
+pub enum ProgramItem<NT, NTV, TV> {
+    Value(ValueStackSymbol<NT, NTV, TV>),
+    /// 元の規則 r_{rule} で還元する.
+    Reduce(usize),
+}
+
+/// 変換後の文法の非終端記号の値.
+pub enum TransformedValue<NT, NTV, TV> {
+    /// 元の非終端記号の値. 元の還元動作を呼んだ結果.
+    Value(NT, ValueStackSymbol<NT, NTV, TV>),
+    /// 作った非終端記号の値. 元の還元のうち,まだ呼んでいないもの.
+    Partial(Derived<NT>, Vec<ProgramItem<NT, NTV, TV>>),
+}
+
+impl<NT, NTV, TV> IntoKind<Derived<NT>> for TransformedValue<NT, NTV, TV>
+where
+    NT: Clone,
+{
+    fn into_kind(&self) -> Derived<NT> {
+        match self {
+            TransformedValue::Value(nt, _) => Derived::Original(nt.clone()),
+            TransformedValue::Partial(kind, _) => kind.clone(),
+        }
+    }
+}
+
+impl<NT, NTV, TV> TransformedValue<NT, NTV, TV> {
+    /// 元の文法で構文解析したときと同じ値を取り出す.
+    pub fn into_original(self) -> Option<ValueStackSymbol<NT, NTV, TV>> {
+        match self {
+            TransformedValue::Value(_, value) => Some(value),
+            TransformedValue::Partial(..) => None,
+        }
+    }
+}
+
+/// 変換後の規則の値の作り方.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Step {
+    /// 右辺の i 番目の値を置く. 作った非終端記号なら,その還元の列をつなげる.
+    Arg(usize),
+    /// 元の規則 r_{rule} で還元する.
+    Reduce(usize),
+}
+
+#[derive(Clone)]
+struct WorkRule<NT, T>
+where
+    T: Ord + Eq + Clone,
+    NT: Ord + Eq + Clone,
+{
+    left: Derived<NT>,
+    right: Vec<Symbol<Derived<NT>, T>>,
+    template: Vec<Step>,
+}
+
+impl<NT, T> WorkRule<NT, T>
+where
+    T: Ord + Eq + Clone,
+    NT: Ord + Eq + Clone,
+{
+    /// 右辺の pos 番目の非終端記号を規則 by の右辺で置き換える.
+    fn substitute(&self, pos: usize, by: &WorkRule<NT, T>) -> Self {
+        let mut right = self.right[..pos].to_vec();
+        right.extend(by.right.iter().cloned());
+        right.extend(self.right[pos + 1..].iter().cloned());
+        let mut template = vec![];
+        for step in &self.template {
+            match step {
+                Step::Arg(i) if *i == pos => {
+                    template.extend(by.template.iter().map(|step| match step {
+                        Step::Arg(j) => Step::Arg(j + pos),
+                        reduce => *reduce,
+                    }))
+                }
+                Step::Arg(i) if *i > pos => template.push(Step::Arg(i + by.right.len() - 1)),
+                step => template.push(*step),
+            }
+        }
+        Self {
+            left: self.left.clone(),
+            right,
+            template,
+        }
+    }
+
+    /// 右辺の先頭 n 個を取り除いた規則. 還元の列からも先頭 n 個の値を除く.
+    fn drop_prefix(&self, left: Derived<NT>, n: usize) -> Self {
+        Self {
+            left,
+            right: self.right[n..].to_vec(),
+            template: self
+                .template
+                .iter()
+                .filter_map(|step| match step {
+                    Step::Arg(i) if *i < n => None,
+                    Step::Arg(i) => Some(Step::Arg(i - n)),
+                    reduce => Some(*reduce),
+                })
+                .collect(),
+        }
+    }
+
+    /// 最初の還元より前に置かれる値の数
+    fn leading_args(&self) -> usize {
+        self.template
+            .iter()
+            .take_while(|step| matches!(step, Step::Arg(_)))
+            .count()
+    }
+}
+
+/// 元の文法の規則. 変換後の還元動作から呼ぶ.
+struct OriginalRule<NT, NTV, TV> {
+    left: NT,
+    len: usize,
+    reduce_action: Option<ReduceAction<NT, NTV, TV>>,
+}
+
+struct OriginalRules<NT, NTV, TV> {
+    rules: Vec<OriginalRule<NT, NTV, TV>>,
+}
+
+impl<NT, NTV, TV> OriginalRules<NT, NTV, TV>
+where
+    NT: Clone,
+{
+    /// 元の還元動作が値を受け取れなければ None.
+    fn run(&self, program: Vec<ProgramItem<NT, NTV, TV>>) -> Option<ValueStackSymbol<NT, NTV, TV>> {
+        let mut stack = vec![];
+        for item in program {
+            match item {
+                ProgramItem::Value(value) => stack.push(value),
+                ProgramItem::Reduce(rule) => {
+                    let OriginalRule {
+                        left,
+                        len,
+                        reduce_action,
+                    } = &self.rules[rule];
+                    let args = stack.split_off(stack.len() - len);
+                    stack.push(match reduce_action {
+                        Some(function) => ValueStackSymbol::NonTerm(function.call(args)?),
+                        None => ValueStackSymbol::Tree(ConcreteSyntaxTree {
+                            rule,
+                            left: left.clone(),
+                            children: args,
+                        }),
+                    });
+                }
+            }
+        }
+        assert_eq!(stack.len(), 1, "program must reduce to a single value");
+        stack.pop()
+    }
+}
+
+/// 変換後の規則の還元動作. template の順に値を並べて元の規則の還元動作を呼ぶ.
+struct Replay<NT, NTV, TV> {
+    original: Arc<OriginalRules<NT, NTV, TV>>,
+    template: Vec<Step>,
+    left: Derived<NT>,
+}
+
+impl<NT, NTV, TV> ReduceFn<Derived<NT>, TransformedValue<NT, NTV, TV>, TV> for Replay<NT, NTV, TV>
+where
+    NT: Clone + Send + Sync,
+{
+    fn call(
+        &self,
+        args: ReduceArgs<Derived<NT>, TransformedValue<NT, NTV, TV>, TV>,
+    ) -> Option<TransformedValue<NT, NTV, TV>> {
+        let mut args: Vec<Option<_>> = args.into_iter().map(Some).collect();
+        let mut program = vec![];
+        for step in &self.template {
+            match step {
+                Step::Arg(i) => match args[*i].take()? {
+                    ValueStackSymbol::Term(tv) => {
+                        program.push(ProgramItem::Value(ValueStackSymbol::Term(tv)))
+                    }
+                    ValueStackSymbol::NonTerm(TransformedValue::Value(_, value)) => {
+                        program.push(ProgramItem::Value(value))
+                    }
+                    ValueStackSymbol::NonTerm(TransformedValue::Partial(_, items)) => {
+                        program.extend(items)
+                    }
+                    ValueStackSymbol::Tree(_) => {
+                        unreachable!("every transformed rule has a reduce action")
+                    }
+                },
+                Step::Reduce(rule) => program.push(ProgramItem::Reduce(*rule)),
+            }
+        }
+        Some(match &self.left {
+            Derived::Original(nt) => {
+                TransformedValue::Value(nt.clone(), self.original.run(program)?)
+            }
+            Derived::Fresh(..) => TransformedValue::Partial(self.left.clone(), program),
+        })
+    }
+}
+
+/// 文法の変換. 左再帰の除去と左くくり出し.
+///
+/// 変換後の規則にはすべて還元動作が付き,元の規則の還元動作を元の文法で構文解析したときと
+/// 同じ順に同じ引数で呼ぶ. 還元動作のない元の規則は元の規則番号の具象構文木になる.
+pub struct GrammerTransformer<NT, T, NTV, TV>
+where
+    T: Ord + Eq + Clone,
+    NT: Ord + Eq + Clone,
+{
+    rules: Vec<WorkRule<NT, T>>,
+    original: OriginalRules<NT, NTV, TV>,
+    fresh_count: BTreeMap<NT, usize>,
+}
+
+impl<NT, T, NTV, TV> GrammerTransformer<NT, T, NTV, TV>
+where
+    T: Ord + Eq + Clone + Send + Sync + 'static,
+    NT: Ord + Eq + Clone + Send + Sync + 'static,
+    NTV: IntoKind<NT> + Send + Sync + 'static,
+    TV: IntoKind<T> + Send + Sync + 'static,
+{
+    pub fn new(grammer: Grammer<NT, T, NTV, TV>) -> Self {
+        let mut rules = vec![];
+        let mut original = vec![];
+        for (number, expr) in grammer.rules.into_iter().enumerate() {
+            let len = expr.right.len();
+            rules.push(WorkRule {
+                left: Derived::Original(expr.left.clone()),
+                right: expr
+                    .right
+                    .into_iter()
+                    .map(|symbol| match symbol {
+                        Symbol::Term(t) => Symbol::Term(t),
+                        Symbol::NonTerm(nt) => Symbol::NonTerm(Derived::Original(nt)),
+                    })
+                    .collect(),
+                template: (0..len)
+                    .map(Step::Arg)
+                    .chain(std::iter::once(Step::Reduce(number)))
+                    .collect(),
+            });
+            original.push(OriginalRule {
+                left: expr.left,
+                len,
+                reduce_action: expr.reduce_action,
+            });
+        }
+        Self {
+            rules,
+            original: OriginalRules { rules: original },
+            fresh_count: BTreeMap::new(),
+        }
+    }
+
+    fn fresh(&mut self, nt: &NT) -> Derived<NT> {
+        let count = self.fresh_count.entry(nt.clone()).or_insert(0);
+        *count += 1;
+        Derived::Fresh(nt.clone(), *count)
+    }
+
+    fn rules_of(&self, left: &Derived<NT>) -> Vec<&WorkRule<NT, T>> {
+        self.rules
+            .iter()
+            .filter(|rule| rule.left == *left)
+            .collect()
+    }
+
+    /// left の規則をすべて new_rules で置き換える. 位置は元の最初の規則の位置.
+    fn replace_rules(&mut self, left: &Derived<NT>, new_rules: Vec<WorkRule<NT, T>>) {
+        let position = self
+            .rules
+            .iter()
+            .position(|rule| rule.left == *left)
+            .unwrap_or(self.rules.len());
+        self.rules.retain(|rule| rule.left != *left);
+        let tail = self.rules.split_off(position.min(self.rules.len()));
+        self.rules.extend(new_rules);
+        self.rules.extend(tail);
+    }
+
+    /// 間接的なものも含めて左再帰を取り除く.
+    ///
+    /// 非終端記号を左辺に最初に現れた順に並べ,前の記号で始まる規則を展開してから
+    /// 直接の左再帰 A -> A α | β を A -> β A' , A' -> α A' | ε に書き換える.
+    /// A -> A のような循環する規則は捨てる. 空規則や循環のある文法では左再帰が残ることがある.
+    /// 左端の A より前に元の規則の還元が入る規則があれば,その A の左再帰は書き換えない.
+    pub fn eliminate_left_recursion(mut self) -> Self {
+        let mut order: Vec<Derived<NT>> = vec![];
+        for rule in &self.rules {
+            if matches!(rule.left, Derived::Original(_)) && !order.contains(&rule.left) {
+                order.push(rule.left.clone());
+            }
+        }
+        for i in 0..order.len() {
+            for j in 0..i {
+                let first = Symbol::NonTerm(order[j].clone());
+                let expanded: Vec<WorkRule<NT, T>> = self
+                    .rules_of(&order[i])
+                    .into_iter()
+                    .flat_map(|rule| {
+                        if rule.right.first() == Some(&first) {
+                            self.rules_of(&order[j])
+                                .into_iter()
+                                .map(|by| rule.substitute(0, by))
+                                .collect()
+                        } else {
+                            vec![rule.clone()]
+                        }
+                    })
+                    .collect();
+                self.replace_rules(&order[i], expanded);
+            }
+            self.eliminate_immediate_left_recursion(&order[i]);
+        }
+        self
+    }
+
+    fn eliminate_immediate_left_recursion(&mut self, left: &Derived<NT>) {
+        let head = Symbol::NonTerm(left.clone());
+        let recursive: Vec<_> = self
+            .rules_of(left)
+            .into_iter()
+            .filter(|rule| rule.right.first() == Some(&head))
+            .collect();
+        // 空規則を展開すると,左端の A の値より前に還元が入ることがある. その順は保てないので書き換えない.
+        if recursive.is_empty()
+            || recursive
+                .iter()
+                .any(|rule| rule.template.first() != Some(&Step::Arg(0)))
+        {
+            return;
+        }
+        let fresh = self.fresh(&Self::base(left));
+        let (recursive, others): (Vec<_>, Vec<_>) = self
+            .rules_of(left)
+            .into_iter()
+            .partition(|rule| rule.right.first() == Some(&head));
+        let mut new_rules = vec![];
+        for rule in others {
+            // A -> β A'
+            let mut rule = rule.clone();
+            rule.template.push(Step::Arg(rule.right.len()));
+            rule.right.push(Symbol::NonTerm(fresh.clone()));
+            new_rules.push(rule);
+        }
+        for rule in recursive {
+            if rule.right.len() == 1 {
+                continue;
+            }
+            // A' -> α A' . 左端の A の値は還元の列の手前ですでに置かれている.
+            let mut tail = rule.drop_prefix(fresh.clone(), 1);
+            tail.template.push(Step::Arg(tail.right.len()));
+            tail.right.push(Symbol::NonTerm(fresh.clone()));
+            new_rules.push(tail);
+        }
+        // A' -> ε
+        new_rules.push(WorkRule {
+            left: fresh,
+            right: vec![],
+            template: vec![],
+        });
+        self.replace_rules(left, new_rules);
+    }
+
+    fn base(left: &Derived<NT>) -> NT {
+        match left {
+            Derived::Original(nt) | Derived::Fresh(nt, _) => nt.clone(),
+        }
+    }
+
+    /// 共通の接頭辞 α を持つ規則 A -> α β1 | α β2 を A -> α A' , A' -> β1 | β2 にくくり出す.
+    ///
+    /// 作った記号の規則もくくり出せなくなるまで繰り返す.
+    /// 接頭辞の途中で元の規則の還元が起きる場合は,その手前までしかくくり出さない.
+    pub fn left_factor(mut self) -> Self {
+        let mut changed = true;
+        while changed {
+            changed = false;
+            let mut lefts: Vec<Derived<NT>> = vec![];
+            for rule in &self.rules {
+                if !lefts.contains(&rule.left) {
+                    lefts.push(rule.left.clone());
+                }
+            }
+            for left in lefts {
+                if self.factor_once(&left) {
+                    changed = true;
+                }
+            }
+        }
+        self
+    }
+
+    fn factor_once(&mut self, left: &Derived<NT>) -> bool {
+        let rules = self.rules_of(left);
+        for (i, rule) in rules.iter().enumerate() {
+            let first = match rule.right.first() {
+                Some(first) => first,
+                None => continue,
+            };
+            let group: Vec<&WorkRule<NT, T>> = rules[i..]
+                .iter()
+                .filter(|other| other.right.first() == Some(first))
+                .copied()
+                .collect();
+            if group.len() < 2 {
+                continue;
+            }
+            let mut len = group
+                .iter()
+                .map(|rule| rule.right.len().min(rule.leading_args()))
+                .min()
+                .unwrap();
+            while group
+                .iter()
+                .any(|other| other.right[..len] != rule.right[..len])
+            {
+                len -= 1;
+            }
+            if len == 0 {
+                continue;
+            }
+            let prefix = rule.right[..len].to_vec();
+            let fresh = self.fresh(&Self::base(left));
+            let rules = self.rules_of(left);
+            let mut new_rules = vec![];
+            let mut factored = vec![];
+            for rule in rules {
+                if rule.right.len() >= len
+                    && rule.right[..len] == prefix[..]
+                    && rule.leading_args() >= len
+                {
+                    if factored.is_empty() {
+                        // A -> α A'
+                        let mut right = prefix.clone();
+                        right.push(Symbol::NonTerm(fresh.clone()));
+                        new_rules.push(WorkRule {
+                            left: left.clone(),
+                            right,
+                            template: (0..=len).map(Step::Arg).collect(),
+                        });
+                    }
+                    factored.push(rule.drop_prefix(fresh.clone(), len));
+                } else {
+                    new_rules.push(rule.clone());
+                }
+            }
+            new_rules.extend(factored);
+            self.replace_rules(left, new_rules);
+            return true;
+        }
+        false
+    }
+
+    /// 変換後の文法. 規則の順は変換前の順をなるべく保つ.
+    pub fn finish(self) -> Grammer<Derived<NT>, T, TransformedValue<NT, NTV, TV>, TV> {
+        let original = Arc::new(self.original);
+        Grammer {
+            rules: self
+                .rules
+                .into_iter()
+                .map(|rule| {
+                    let action: ReduceAction<Derived<NT>, TransformedValue<NT, NTV, TV>, TV> =
+                        Box::new(Replay {
+                            original: original.clone(),
+                            template: rule.template,
+                            left: rule.left.clone(),
+                        });
+                    Expr {
+                        left: rule.left,
+                        right: rule.right,
+                        reduce_action: Some(action),
+                    }
+                })
+                .collect(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{GrammerTransformer, TransformedValue};
+    use crate::bnf::test::{chars, Char};
+    use crate::bnf::{Derived, Expr, Grammer, IntoKind, ReduceArgs, Symbol};
+    use crate::earley::EarleyParser;
+    use crate::ll1::Ll1Parser;
+    use crate::parsing_table::ValueStackSymbol;
+
+    #[derive(Ord, PartialOrd, PartialEq, Eq, Clone, Debug)]
+    enum NT {
+        A,
+        B,
+        E,
+        T,
+        F,
+    }
+
+    #[derive(Debug)]
+    struct Num(NT, i64);
+
+    impl IntoKind<NT> for Num {
+        fn into_kind(&self) -> NT {
+            self.0.clone()
+        }
+    }
+
+    fn num(arg: &ValueStackSymbol<NT, Num, Char>) -> i64 {
+        match arg {
+            ValueStackSymbol::NonTerm(Num(_, n)) => *n,
+            ValueStackSymbol::Term(c) => c.0.to_digit(10).unwrap() as i64,
+            ValueStackSymbol::Tree(_) => panic!("unexpected tree"),
+        }
+    }
+
+    fn expr(left: NT, right: Vec<Symbol<NT, Char>>) -> Expr<NT, Char, NT, Char> {
+        Expr {
+            left,
+            right,
+            reduce_action: None,
+        }
+    }
+
+    fn sexpr<NTV: std::fmt::Debug>(value: ValueStackSymbol<NT, NTV, Char>) -> String {
+        match value {
+            ValueStackSymbol::Tree(tree) => tree.export_as_sexpr(),
+            other => panic!("expected concrete syntax tree but got {:?}", other),
+        }
+    }
+
+    fn original<NTV>(
+        value: ValueStackSymbol<Derived<NT>, TransformedValue<NT, NTV, Char>, Char>,
+    ) -> ValueStackSymbol<NT, NTV, Char> {
+        match value {
+            ValueStackSymbol::NonTerm(value) => value.into_original().unwrap(),
+            _ => panic!("transformed rules always have reduce actions"),
+        }
+    }
+
+    #[test]
+    fn test_eliminate_left_recursion() {
+        // E -> E - T | T , T -> T / F | F , F -> ( E ) | digit
+        let mut rules: Vec<Expr<NT, Char, Num, Char>> = vec![
+            Expr {
+                left: NT::E,
+                right: vec![
+                    Symbol::NonTerm(NT::E),
+                    Symbol::Term(Char('-')),
+                    Symbol::NonTerm(NT::T),
+                ],
+                reduce_action: Some(Box::new(|args: ReduceArgs<NT, Num, Char>| {
+                    Num(NT::E, num(&args[0]) - num(&args[2]))
+                })),
+            },
+            Expr {
+                left: NT::E,
+                right: vec![Symbol::NonTerm(NT::T)],
+                reduce_action: Some(Box::new(|args: ReduceArgs<NT, Num, Char>| {
+                    Num(NT::E, num(&args[0]))
+                })),
+            },
+            Expr {
+                left: NT::T,
+                right: vec![
+                    Symbol::NonTerm(NT::T),
+                    Symbol::Term(Char('/')),
+                    Symbol::NonTerm(NT::F),
+                ],
+                reduce_action: Some(Box::new(|args: ReduceArgs<NT, Num, Char>| {
+                    Num(NT::T, num(&args[0]) / num(&args[2]))
+                })),
+            },
+            Expr {
+                left: NT::T,
+                right: vec![Symbol::NonTerm(NT::F)],
+                reduce_action: Some(Box::new(|args: ReduceArgs<NT, Num, Char>| {
+                    Num(NT::T, num(&args[0]))
+                })),
+            },
+            Expr {
+                left: NT::F,
+                right: vec![
+                    Symbol::Term(Char('(')),
+                    Symbol::NonTerm(NT::E),
+                    Symbol::Term(Char(')')),
+                ],
+                reduce_action: Some(Box::new(|args: ReduceArgs<NT, Num, Char>| {
+                    Num(NT::F, num(&args[1]))
+                })),
+            },
+        ];
+        for digit in ('1'..='9').map(Char) {
+            rules.push(Expr {
+                left: NT::F,
+                right: vec![Symbol::Term(digit)],
+                reduce_action: Some(Box::new(|args: ReduceArgs<NT, Num, Char>| {
+                    Num(NT::F, num(&args[0]))
+                })),
+            });
+        }
+        let grammer = GrammerTransformer::new(Grammer { rules })
+            .eliminate_left_recursion()
+            .finish();
+        assert!(grammer
+            .rules
+            .iter()
+            .all(|rule| rule.right.first() != Some(&Symbol::NonTerm(rule.left.clone()))));
+
+        let parser = Ll1Parser::new(grammer, Derived::Original(NT::E), Char('$'));
+        assert!(parser.conflicts().is_empty());
+        for (input, value) in [("8-2-1", 5), ("8/2/2-1", 1), ("8-(2-1)", 7), ("7", 7)] {
+            match original(parser.parse(chars(input)).unwrap()) {
+                ValueStackSymbol::NonTerm(Num(NT::E, n)) => assert_eq!(n, value, "{}", input),
+                other => panic!("unexpected value {:?}", other),
+            }
+        }
+    }
+
+    #[test]
+    fn test_eliminate_indirect_left_recursion() {
+        // A -> B x | y , B -> A z | w
+        let grammer = || Grammer {
+            rules: vec![
+                expr(NT::A, vec![Symbol::NonTerm(NT::B), Symbol::Term(Char('x'))]),
+                expr(NT::A, vec![Symbol::Term(Char('y'))]),
+                expr(NT::B, vec![Symbol::NonTerm(NT::A), Symbol::Term(Char('z'))]),
+                expr(NT::B, vec![Symbol::Term(Char('w'))]),
+            ],
+        };
+        let transformed = GrammerTransformer::new(grammer())
+            .eliminate_left_recursion()
+            .finish();
+        // B -> y z B' | w x z B' ... のように, B から始まる左再帰がなくなる.
+        assert!(transformed
+            .rules
+            .iter()
+            .all(|rule| rule.right.first() != Some(&Symbol::NonTerm(rule.left.clone()))));
+        assert!(transformed
+            .rules
+            .iter()
+            .any(|rule| rule.left == Derived::Fresh(NT::B, 1)));
+
+        let reference = EarleyParser::new(grammer(), NT::A);
+        let parser = EarleyParser::new(transformed, Derived::Original(NT::A));
+        for input in ["y", "wx", "yzx", "wxzx", "yzxzx"] {
+            let expected = sexpr(reference.parse(chars(input).collect()).unwrap());
+            let found = sexpr(original(parser.parse(chars(input).collect()).unwrap()));
+            assert_eq!(found, expected, "{}", input);
+        }
+    }
+
+    #[test]
+    fn test_eliminate_left_recursion_after_empty_rule() {
+        // A -> ε , B -> A B x | y . 展開すると B -> B x の左端の B より前で A に還元する.
+        let grammer = || Grammer {
+            rules: vec![
+                expr(NT::A, vec![]),
+                expr(
+                    NT::B,
+                    vec![
+                        Symbol::NonTerm(NT::A),
+                        Symbol::NonTerm(NT::B),
+                        Symbol::Term(Char('x')),
+                    ],
+                ),
+                expr(NT::B, vec![Symbol::Term(Char('y'))]),
+            ],
+        };
+        let transformed = GrammerTransformer::new(grammer())
+            .eliminate_left_recursion()
+            .finish();
+        // 還元の順を保てないので,この左再帰は残す.
+        assert!(transformed
+            .rules
+            .iter()
+            .all(|rule| rule.left != Derived::Fresh(NT::B, 1)));
+
+        let reference = EarleyParser::new(grammer(), NT::B);
+        let parser = EarleyParser::new(transformed, Derived::Original(NT::B));
+        for input in ["y", "yx", "yxx"] {
+            let expected = sexpr(reference.parse(chars(input).collect()).unwrap());
+            let found = sexpr(original(parser.parse(chars(input).collect()).unwrap()));
+            assert_eq!(found, expected, "{}", input);
+        }
+    }
+
+    #[test]
+    fn test_left_factor() {
+        // A -> x y z | x y w | v
+        let grammer = || Grammer {
+            rules: vec![
+                expr(
+                    NT::A,
+                    vec![
+                        Symbol::Term(Char('x')),
+                        Symbol::Term(Char('y')),
+                        Symbol::Term(Char('z')),
+                    ],
+                ),
+                expr(
+                    NT::A,
+                    vec![
+                        Symbol::Term(Char('x')),
+                        Symbol::Term(Char('y')),
+                        Symbol::Term(Char('w')),
+                    ],
+                ),
+                expr(NT::A, vec![Symbol::Term(Char('v'))]),
+            ],
+        };
+        let transformed = GrammerTransformer::new(grammer()).left_factor().finish();
+        let rules: Vec<String> = transformed
+            .rules
+            .iter()
+            .map(|rule| format!("{:?} -> {:?}", rule.left, rule.right))
+            .collect();
+        assert_eq!(
+            rules,
+            [
+                "A -> [Term('x'), Term('y'), NonTerm(A')]",
+                "A -> [Term('v')]",
+                "A' -> [Term('z')]",
+                "A' -> [Term('w')]",
+            ]
+        );
+
+        let reference = EarleyParser::new(grammer(), NT::A);
+        let parser = Ll1Parser::new(transformed, Derived::Original(NT::A), Char('$'));
+        assert!(parser.conflicts().is_empty());
+        for input in ["xyz", "xyw", "v"] {
+            let expected = sexpr(reference.parse(chars(input).collect()).unwrap());
+            let found = sexpr(original(parser.parse(chars(input)).unwrap()));
+            assert_eq!(found, expected, "{}", input);
+        }
+    }
+}
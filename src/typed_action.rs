@@ -0,0 +1,670 @@
+use crate::{
+    bnf::{Expr, Grammer, IntoKind, ReduceAction, ReduceArgs, ReduceFn, Symbol},
+    cst::ConcreteSyntaxTree,
+    parsing_table::ValueStackSymbol,
+};
+use std::{any::type_name, fmt::Debug, marker::PhantomData};
+
+/// 右辺の記号の値として受け取れる型.
+///
+/// 文法を作るときに `accepts` で記号と型が合っているかを調べる.
+/// 具象構文木として受け取る型は `takes_tree` を, どんな値でも受け取る型は `takes_any` を true にする.
+/// それ以外の型は還元動作の値を受け取る. その記号の規則がすべて還元動作を持つか
+/// (具象構文木なら持たないか) は `check_tree_arguments` で表を作るときに調べる.
+/// 調べずに作った表では,構文解析中に `from_stack_value` が失敗して `ParseError::ActionMismatch` になる.
+pub trait FromStackValue<NT, T, NTV, TV>: Sized
+where
+    NT: Ord + Eq + Clone,
+    T: Ord + Eq + Clone,
+{
+    /// symbol の値をこの型で受け取れるか
+    fn accepts(symbol: &Symbol<NT, T>) -> bool;
+    /// 具象構文木として受け取るか
+    fn takes_tree() -> bool {
+        false
+    }
+    /// 具象構文木も還元動作の値も受け取るか
+    fn takes_any() -> bool {
+        false
+    }
+    fn from_stack_value(value: ValueStackSymbol<NT, NTV, TV>) -> Option<Self>;
+}
+
+/// 還元結果として返せる型.
+pub trait IntoNonTermValue<NT, NTV> {
+    /// この型の値が表す非終端記号
+    fn kind() -> NT;
+    fn into_value(self) -> NTV;
+}
+
+/// 終端記号の値をそのまま受け取る.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Token<TV>(pub TV);
+
+impl<NT, T, NTV, TV> FromStackValue<NT, T, NTV, TV> for Token<TV>
+where
+    NT: Ord + Eq + Clone,
+    T: Ord + Eq + Clone,
+{
+    fn accepts(symbol: &Symbol<NT, T>) -> bool {
+        matches!(symbol, Symbol::Term(_))
+    }
+    fn from_stack_value(value: ValueStackSymbol<NT, NTV, TV>) -> Option<Self> {
+        match value {
+            ValueStackSymbol::Term(tv) => Some(Token(tv)),
+            _ => None,
+        }
+    }
+}
+
+/// 値を使わない記号に使う.
+impl<NT, T, NTV, TV> FromStackValue<NT, T, NTV, TV> for ()
+where
+    NT: Ord + Eq + Clone,
+    T: Ord + Eq + Clone,
+{
+    fn accepts(_: &Symbol<NT, T>) -> bool {
+        true
+    }
+    fn takes_any() -> bool {
+        true
+    }
+    fn from_stack_value(_: ValueStackSymbol<NT, NTV, TV>) -> Option<Self> {
+        Some(())
+    }
+}
+
+/// 型を付けずに値スタックの値をそのまま受け取る.
+impl<NT, T, NTV, TV> FromStackValue<NT, T, NTV, TV> for ValueStackSymbol<NT, NTV, TV>
+where
+    NT: Ord + Eq + Clone,
+    T: Ord + Eq + Clone,
+{
+    fn accepts(_: &Symbol<NT, T>) -> bool {
+        true
+    }
+    fn takes_any() -> bool {
+        true
+    }
+    fn from_stack_value(value: ValueStackSymbol<NT, NTV, TV>) -> Option<Self> {
+        Some(value)
+    }
+}
+
+/// 還元動作のない規則で作られた具象構文木を受け取る.
+impl<NT, T, NTV, TV> FromStackValue<NT, T, NTV, TV> for ConcreteSyntaxTree<NT, NTV, TV>
+where
+    NT: Ord + Eq + Clone,
+    T: Ord + Eq + Clone,
+{
+    fn accepts(symbol: &Symbol<NT, T>) -> bool {
+        matches!(symbol, Symbol::NonTerm(_))
+    }
+    fn takes_tree() -> bool {
+        true
+    }
+    fn from_stack_value(value: ValueStackSymbol<NT, NTV, TV>) -> Option<Self> {
+        match value {
+            ValueStackSymbol::Tree(tree) => Some(tree),
+            _ => None,
+        }
+    }
+}
+
+/// 規則と還元動作の型が合わない.
+#[derive(Debug, PartialEq, Eq)]
+pub enum TypeMismatch<NT, T>
+where
+    NT: Ord + Eq + Clone,
+    T: Ord + Eq + Clone,
+{
+    /// 右辺の長さと引数の数が違う.
+    Arity {
+        left: NT,
+        expected: usize,
+        found: usize,
+    },
+    /// 右辺の position 番目の記号を type_name で受け取れない.
+    Argument {
+        left: NT,
+        position: usize,
+        symbol: Symbol<NT, T>,
+        type_name: &'static str,
+    },
+    /// 還元結果の型が左辺と違う非終端記号を表している.
+    Result {
+        left: NT,
+        found: NT,
+        type_name: &'static str,
+    },
+    /// 右辺の position 番目の記号を具象構文木で受け取るが,その記号の規則 r_{rule} には
+    /// 還元動作がある.
+    Tree {
+        left: NT,
+        position: usize,
+        symbol: Symbol<NT, T>,
+        rule: usize,
+    },
+    /// 右辺の position 番目の記号を還元動作の値で受け取るが,その記号の規則 r_{rule} には
+    /// 還元動作がない.
+    Value {
+        left: NT,
+        position: usize,
+        symbol: Symbol<NT, T>,
+        rule: usize,
+    },
+}
+
+/// 型付きの還元動作. 引数の型 Args ごとに実装する.
+pub trait TypedReduceFn<NT, T, NTV, TV, Args>
+where
+    NT: Ord + Eq + Clone,
+    T: Ord + Eq + Clone,
+{
+    /// 規則 left -> right に使えるか調べる.
+    fn check(left: &NT, right: &[Symbol<NT, T>]) -> Result<(), TypeMismatch<NT, T>>;
+    fn into_reduce_action(self) -> ReduceAction<NT, NTV, TV>;
+}
+
+/// 型付きの還元動作. 引数は値スタックから `FromStackValue` で取り出す.
+struct TypedAction<F, T, Args> {
+    function: F,
+    args: PhantomData<fn() -> (T, Args)>,
+}
+
+macro_rules! impl_typed_reduce_fn {
+    ($n:expr $(, $arg:ident)*) => {
+        impl<F, R, NT, T, NTV, TV, $($arg,)*> TypedReduceFn<NT, T, NTV, TV, ($($arg,)*)> for F
+        where
+            F: Fn($($arg),*) -> R + Send + Sync + 'static,
+            R: IntoNonTermValue<NT, NTV> + 'static,
+            NT: Ord + Eq + Clone + 'static,
+            T: Ord + Eq + Clone + 'static,
+            NTV: 'static,
+            TV: 'static,
+            $($arg: FromStackValue<NT, T, NTV, TV> + 'static,)*
+        {
+            #[allow(unused_variables, unused_mut)]
+            fn check(left: &NT, right: &[Symbol<NT, T>]) -> Result<(), TypeMismatch<NT, T>> {
+                if right.len() != $n {
+                    return Err(TypeMismatch::Arity {
+                        left: left.clone(),
+                        expected: right.len(),
+                        found: $n,
+                    });
+                }
+                let found = R::kind();
+                if &found != left {
+                    return Err(TypeMismatch::Result {
+                        left: left.clone(),
+                        found,
+                        type_name: type_name::<R>(),
+                    });
+                }
+                let mut symbols = right.iter().enumerate();
+                $(
+                    let (position, symbol) = symbols.next().unwrap();
+                    if !$arg::accepts(symbol) {
+                        return Err(TypeMismatch::Argument {
+                            left: left.clone(),
+                            position,
+                            symbol: symbol.clone(),
+                            type_name: type_name::<$arg>(),
+                        });
+                    }
+                )*
+                Ok(())
+            }
+
+            fn into_reduce_action(self) -> ReduceAction<NT, NTV, TV> {
+                Box::new(TypedAction::<F, T, ($($arg,)*)> {
+                    function: self,
+                    args: PhantomData,
+                })
+            }
+        }
+
+        impl<F, R, NT, T, NTV, TV, $($arg,)*> ReduceFn<NT, NTV, TV> for TypedAction<F, T, ($($arg,)*)>
+        where
+            F: Fn($($arg),*) -> R + Send + Sync,
+            R: IntoNonTermValue<NT, NTV>,
+            NT: Ord + Eq + Clone,
+            T: Ord + Eq + Clone,
+            $($arg: FromStackValue<NT, T, NTV, TV>,)*
+        {
+            #[allow(non_snake_case, unused_variables, unused_mut)]
+            fn call(&self, args: ReduceArgs<NT, NTV, TV>) -> Option<NTV> {
+                let mut args = args.into_iter();
+                $(
+                    let $arg = $arg::from_stack_value(args.next()?)?;
+                )*
+                Some((self.function)($($arg),*).into_value())
+            }
+
+            fn tree_arguments(&self) -> Vec<usize> {
+                let takes_tree: Vec<bool> = vec![$($arg::takes_tree()),*];
+                takes_tree
+                    .into_iter()
+                    .enumerate()
+                    .filter_map(|(position, tree)| tree.then_some(position))
+                    .collect()
+            }
+
+            fn value_arguments(&self) -> Vec<usize> {
+                let takes_value: Vec<bool> =
+                    vec![$(!$arg::takes_tree() && !$arg::takes_any()),*];
+                takes_value
+                    .into_iter()
+                    .enumerate()
+                    .filter_map(|(position, value)| value.then_some(position))
+                    .collect()
+            }
+        }
+    };
+}
+
+impl_typed_reduce_fn!(0);
+impl_typed_reduce_fn!(1, A);
+impl_typed_reduce_fn!(2, A, B);
+impl_typed_reduce_fn!(3, A, B, C);
+impl_typed_reduce_fn!(4, A, B, C, D);
+impl_typed_reduce_fn!(5, A, B, C, D, E);
+impl_typed_reduce_fn!(6, A, B, C, D, E, G);
+
+impl<NT, T, NTV, TV> Expr<NT, T, NTV, TV>
+where
+    T: Ord + Eq + Clone,
+    NT: Ord + Eq + Clone,
+    TV: IntoKind<T>,
+    NTV: IntoKind<NT>,
+{
+    /// 型付きの還元動作を持つ規則を作る.
+    ///
+    /// 右辺の記号と引数の型,左辺と結果の型が合わなければ `TypeMismatch` を返す.
+    /// 引数は値スタックから取り出したものがそのまま(複製せずに)渡される.
+    pub fn typed<F, Args>(
+        left: NT,
+        right: Vec<Symbol<NT, T>>,
+        reduce_action: F,
+    ) -> Result<Self, TypeMismatch<NT, T>>
+    where
+        F: TypedReduceFn<NT, T, NTV, TV, Args>,
+    {
+        F::check(&left, &right)?;
+        Ok(Expr {
+            left,
+            right,
+            reduce_action: Some(reduce_action.into_reduce_action()),
+        })
+    }
+}
+
+/// 具象構文木として受け取る引数の記号が還元動作のない規則だけで,
+/// 還元動作の値として受け取る引数の記号が還元動作のある規則だけで作られるか調べる.
+///
+/// 還元動作のある規則で還元した値は具象構文木にならず,還元動作のない規則で還元した値は
+/// 具象構文木にしかならないので,受け取れない.
+pub fn check_tree_arguments<NT, T, NTV, TV>(
+    grammer: &Grammer<NT, T, NTV, TV>,
+) -> Result<(), TypeMismatch<NT, T>>
+where
+    T: Ord + Eq + Clone,
+    NT: Ord + Eq + Clone,
+    TV: IntoKind<T>,
+    NTV: IntoKind<NT>,
+{
+    for expr in &grammer.rules {
+        let Some(reduce_action) = &expr.reduce_action else {
+            continue;
+        };
+        for position in reduce_action.tree_arguments() {
+            let Some(symbol @ Symbol::NonTerm(nt)) = expr.right.get(position) else {
+                continue;
+            };
+            let with_action = grammer
+                .rules
+                .iter()
+                .position(|rule| &rule.left == nt && rule.reduce_action.is_some());
+            if let Some(rule) = with_action {
+                return Err(TypeMismatch::Tree {
+                    left: expr.left.clone(),
+                    position,
+                    symbol: symbol.clone(),
+                    rule,
+                });
+            }
+        }
+        for position in reduce_action.value_arguments() {
+            let Some(symbol @ Symbol::NonTerm(nt)) = expr.right.get(position) else {
+                continue;
+            };
+            let without_action = grammer
+                .rules
+                .iter()
+                .position(|rule| &rule.left == nt && rule.reduce_action.is_none());
+            if let Some(rule) = without_action {
+                return Err(TypeMismatch::Value {
+                    left: expr.left.clone(),
+                    position,
+                    symbol: symbol.clone(),
+                    rule,
+                });
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::{FromStackValue, IntoNonTermValue, Token, TypeMismatch};
+    use crate::bnf::{EOFSupply, Expr, Grammer, IntoKind, Symbol};
+    use crate::cst::ConcreteSyntaxTree;
+    use crate::item_set::{generate_canonical_automaton, GrammerError};
+    use crate::parse_session::{ParseError, ParseSession};
+    use crate::parsing_table::{canonical_automaton_to_lr0_parser, ValueStackSymbol};
+    use std::sync::Arc;
+
+    #[derive(Ord, PartialOrd, PartialEq, Eq, Clone, Debug)]
+    enum NT {
+        List,
+        Item,
+    }
+
+    #[derive(Ord, PartialOrd, PartialEq, Eq, Clone, Debug)]
+    enum T {
+        Num(u8),
+        Comma,
+        Eof,
+    }
+
+    impl IntoKind<T> for u8 {
+        fn into_kind(&self) -> T {
+            match self {
+                b',' => T::Comma,
+                b'$' => T::Eof,
+                _ => T::Num(0),
+            }
+        }
+    }
+
+    impl EOFSupply<T> for T {
+        fn eof() -> T {
+            T::Eof
+        }
+    }
+
+    impl EOFSupply<u8> for u8 {
+        fn eof() -> u8 {
+            b'$'
+        }
+    }
+
+    #[derive(Debug)]
+    enum Value {
+        List(Vec<u8>),
+        Item(u8),
+    }
+
+    impl IntoKind<NT> for Value {
+        fn into_kind(&self) -> NT {
+            match self {
+                Value::List(_) => NT::List,
+                Value::Item(_) => NT::Item,
+            }
+        }
+    }
+
+    /// 複製できない値も受け取れることを確かめるために Clone を実装しない.
+    #[derive(Debug, PartialEq)]
+    struct List(Vec<u8>);
+    struct Item(u8);
+
+    impl FromStackValue<NT, T, Value, u8> for List {
+        fn accepts(symbol: &Symbol<NT, T>) -> bool {
+            symbol == &Symbol::NonTerm(NT::List)
+        }
+        fn from_stack_value(value: ValueStackSymbol<NT, Value, u8>) -> Option<Self> {
+            match value {
+                ValueStackSymbol::NonTerm(Value::List(list)) => Some(List(list)),
+                _ => None,
+            }
+        }
+    }
+    impl IntoNonTermValue<NT, Value> for List {
+        fn kind() -> NT {
+            NT::List
+        }
+        fn into_value(self) -> Value {
+            Value::List(self.0)
+        }
+    }
+    impl FromStackValue<NT, T, Value, u8> for Item {
+        fn accepts(symbol: &Symbol<NT, T>) -> bool {
+            symbol == &Symbol::NonTerm(NT::Item)
+        }
+        fn from_stack_value(value: ValueStackSymbol<NT, Value, u8>) -> Option<Self> {
+            match value {
+                ValueStackSymbol::NonTerm(Value::Item(item)) => Some(Item(item)),
+                _ => None,
+            }
+        }
+    }
+    impl IntoNonTermValue<NT, Value> for Item {
+        fn kind() -> NT {
+            NT::Item
+        }
+        fn into_value(self) -> Value {
+            Value::Item(self.0)
+        }
+    }
+
+    #[test]
+    fn test_typed_reduce_action() {
+        // List -> List , Item | Item , Item -> num
+        let grammer = Grammer {
+            rules: vec![
+                Expr::typed(
+                    NT::List,
+                    vec![
+                        Symbol::NonTerm(NT::List),
+                        Symbol::Term(T::Comma),
+                        Symbol::NonTerm(NT::Item),
+                    ],
+                    |mut list: List, _: (), item: Item| {
+                        list.0.push(item.0);
+                        list
+                    },
+                )
+                .unwrap(),
+                Expr::typed(NT::List, vec![Symbol::NonTerm(NT::Item)], |item: Item| {
+                    List(vec![item.0])
+                })
+                .unwrap(),
+                Expr::typed(
+                    NT::Item,
+                    vec![Symbol::Term(T::Num(0))],
+                    |Token(n): Token<u8>| Item(n - b'0'),
+                )
+                .unwrap(),
+            ],
+        };
+        let (automaton, reduce_action) =
+            generate_canonical_automaton(grammer, NT::List, None).unwrap();
+        let table = canonical_automaton_to_lr0_parser(&automaton, reduce_action).unwrap();
+        let mut session = ParseSession::new(Arc::new(table));
+        let result = session.parse_iter(b"1,2,3".iter().copied());
+        assert!(matches!(
+            result,
+            Ok(ValueStackSymbol::NonTerm(Value::List(list))) if list == vec![1, 2, 3]
+        ));
+    }
+
+    #[test]
+    fn test_type_mismatch() {
+        let arity = Expr::<NT, T, Value, u8>::typed(
+            NT::List,
+            vec![Symbol::NonTerm(NT::Item)],
+            |_: Item, _: ()| List(vec![]),
+        );
+        assert_eq!(
+            arity.err(),
+            Some(TypeMismatch::Arity {
+                left: NT::List,
+                expected: 1,
+                found: 2
+            })
+        );
+
+        let argument = Expr::<NT, T, Value, u8>::typed(
+            NT::List,
+            vec![Symbol::NonTerm(NT::Item)],
+            |list: List| list,
+        );
+        assert!(matches!(
+            argument.err(),
+            Some(TypeMismatch::Argument {
+                position: 0,
+                symbol: Symbol::NonTerm(NT::Item),
+                ..
+            })
+        ));
+
+        let result = Expr::<NT, T, Value, u8>::typed(
+            NT::List,
+            vec![Symbol::NonTerm(NT::Item)],
+            |item: Item| item,
+        );
+        assert!(matches!(
+            result.err(),
+            Some(TypeMismatch::Result {
+                left: NT::List,
+                found: NT::Item,
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn test_tree_argument() {
+        let item = || {
+            Expr::typed(
+                NT::Item,
+                vec![Symbol::Term(T::Num(0))],
+                |Token(n): Token<u8>| Item(n - b'0'),
+            )
+            .unwrap()
+        };
+        let list = || {
+            Expr::typed(
+                NT::List,
+                vec![Symbol::NonTerm(NT::Item)],
+                |tree: ConcreteSyntaxTree<NT, Value, u8>| List(vec![tree.rule as u8]),
+            )
+            .unwrap()
+        };
+        // Item の規則には還元動作があるので,Item の値は具象構文木にならない.
+        let grammer = Grammer {
+            rules: vec![list(), item()],
+        };
+        assert_eq!(
+            generate_canonical_automaton(grammer, NT::List, None).err(),
+            Some(GrammerError::Type(TypeMismatch::Tree {
+                left: NT::List,
+                position: 0,
+                symbol: Symbol::NonTerm(NT::Item),
+                rule: 1,
+            }))
+        );
+
+        // 調べずに還元動作を付けた表でも,構文解析中に panic せずエラーを返す.
+        let grammer = Grammer {
+            rules: vec![
+                Expr {
+                    left: NT::List,
+                    right: vec![Symbol::NonTerm(NT::Item)],
+                    reduce_action: None,
+                },
+                item(),
+            ],
+        };
+        let (automaton, mut reduce_action) =
+            generate_canonical_automaton(grammer, NT::List, None).unwrap();
+        reduce_action.insert(0, list().reduce_action.unwrap());
+        let table = canonical_automaton_to_lr0_parser(&automaton, reduce_action).unwrap();
+        let mut session = ParseSession::new(Arc::new(table));
+        assert!(matches!(
+            session.parse_iter(b"1".iter().copied()),
+            Err(ParseError::ActionMismatch { rule: 0 })
+        ));
+    }
+
+    #[test]
+    fn test_value_argument() {
+        // List -> Item , Item -> num | , . Item , の規則には還元動作がないので,その値は Item にならない.
+        let grammer = Grammer {
+            rules: vec![
+                Expr::typed(NT::List, vec![Symbol::NonTerm(NT::Item)], |item: Item| {
+                    List(vec![item.0])
+                })
+                .unwrap(),
+                Expr::typed(
+                    NT::Item,
+                    vec![Symbol::Term(T::Num(0))],
+                    |Token(n): Token<u8>| Item(n - b'0'),
+                )
+                .unwrap(),
+                Expr {
+                    left: NT::Item,
+                    right: vec![Symbol::Term(T::Comma)],
+                    reduce_action: None,
+                },
+            ],
+        };
+        assert_eq!(
+            generate_canonical_automaton(grammer, NT::List, None).err(),
+            Some(GrammerError::Type(TypeMismatch::Value {
+                left: NT::List,
+                position: 0,
+                symbol: Symbol::NonTerm(NT::Item),
+                rule: 2,
+            }))
+        );
+
+        // 値スタックの値をそのまま受け取るなら,どちらの規則でもよい.
+        let grammer = Grammer {
+            rules: vec![
+                Expr::typed(
+                    NT::List,
+                    vec![Symbol::NonTerm(NT::Item)],
+                    |item: ValueStackSymbol<NT, Value, u8>| match item {
+                        ValueStackSymbol::NonTerm(Value::Item(n)) => List(vec![n]),
+                        _ => List(vec![]),
+                    },
+                )
+                .unwrap(),
+                Expr::typed(
+                    NT::Item,
+                    vec![Symbol::Term(T::Num(0))],
+                    |Token(n): Token<u8>| Item(n - b'0'),
+                )
+                .unwrap(),
+                Expr {
+                    left: NT::Item,
+                    right: vec![Symbol::Term(T::Comma)],
+                    reduce_action: None,
+                },
+            ],
+        };
+        let (automaton, reduce_action) =
+            generate_canonical_automaton(grammer, NT::List, None).unwrap();
+        let table = Arc::new(canonical_automaton_to_lr0_parser(&automaton, reduce_action).unwrap());
+        for (input, expected) in [(b"1", vec![1]), (b",", vec![])] {
+            let mut session = ParseSession::new(table.clone());
+            assert!(matches!(
+                session.parse_iter(input.iter().copied()),
+                Ok(ValueStackSymbol::NonTerm(Value::List(list))) if list == expected
+            ));
+        }
+    }
+}
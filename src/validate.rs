@@ -0,0 +1,251 @@
+use crate::bnf::{Grammer, IntoKind, Symbol};
+use crate::nullable_set::generate_null_set;
+use std::collections::{BTreeMap, BTreeSet};
+
+/// 文法の検査で見つかった問題. 規則の番号は `Grammer::rules` の位置.
+#[derive(Debug, PartialEq, Eq)]
+pub enum GrammerDiagnostic<NT> {
+    /// 右辺 (または開始記号) で使っているが,左辺に現れない非終端記号. rules は使っている規則.
+    Undefined { nonterm: NT, rules: Vec<usize> },
+    /// 開始記号から到達できない非終端記号
+    Unreachable(NT),
+    /// 終端記号の列を1つも導出できない非終端記号
+    Unproductive(NT),
+    /// 規則 r_{duplicate} は規則 r_{first} と同じ.
+    DuplicateRule { first: usize, duplicate: usize },
+    /// A =>+ A となる非終端記号の組 (強連結成分)
+    Cycle(Vec<NT>),
+}
+
+/// 文法を検査して,見つかった問題をすべて返す. 空なら問題はない.
+///
+/// 問題は上の種類の順に並び,同じ種類の中では非終端記号・規則の順に並ぶ.
+pub fn validate_grammer<NT, T, NTV, TV>(
+    grammer: &Grammer<NT, T, NTV, TV>,
+    start: &NT,
+) -> Vec<GrammerDiagnostic<NT>>
+where
+    T: Ord + Eq + Clone,
+    NT: Ord + Eq + Clone,
+    NTV: IntoKind<NT>,
+    TV: IntoKind<T>,
+{
+    let mut diagnostics = vec![];
+    let defined: BTreeSet<&NT> = grammer.rules.iter().map(|rule| &rule.left).collect();
+
+    //未定義
+    let mut undefined: BTreeMap<&NT, Vec<usize>> = BTreeMap::new();
+    if !defined.contains(start) {
+        undefined.entry(start).or_default();
+    }
+    for (number, rule) in grammer.rules.iter().enumerate() {
+        for symbol in &rule.right {
+            if let Symbol::NonTerm(nt) = symbol {
+                if !defined.contains(nt) {
+                    let rules = undefined.entry(nt).or_default();
+                    if !rules.contains(&number) {
+                        rules.push(number);
+                    }
+                }
+            }
+        }
+    }
+    for (nonterm, rules) in undefined {
+        diagnostics.push(GrammerDiagnostic::Undefined {
+            nonterm: nonterm.clone(),
+            rules,
+        });
+    }
+
+    //到達可能性
+    let mut reachable: BTreeSet<&NT> = BTreeSet::new();
+    let mut stack = vec![start];
+    while let Some(nt) = stack.pop() {
+        if !reachable.insert(nt) {
+            continue;
+        }
+        for rule in grammer.rules.iter().filter(|rule| rule.left == *nt) {
+            for symbol in &rule.right {
+                if let Symbol::NonTerm(next) = symbol {
+                    stack.push(next);
+                }
+            }
+        }
+    }
+    for nt in &defined {
+        if !reachable.contains(nt) {
+            diagnostics.push(GrammerDiagnostic::Unreachable((*nt).clone()));
+        }
+    }
+
+    //生成可能性
+    let mut productive: BTreeSet<&NT> = BTreeSet::new();
+    let mut changed = true;
+    while changed {
+        changed = false;
+        for rule in &grammer.rules {
+            if productive.contains(&rule.left) {
+                continue;
+            }
+            if rule.right.iter().all(|symbol| match symbol {
+                Symbol::Term(_) => true,
+                Symbol::NonTerm(nt) => productive.contains(nt),
+            }) {
+                productive.insert(&rule.left);
+                changed = true;
+            }
+        }
+    }
+    for nt in &defined {
+        if !productive.contains(nt) {
+            diagnostics.push(GrammerDiagnostic::Unproductive((*nt).clone()));
+        }
+    }
+
+    //重複
+    for (duplicate, rule) in grammer.rules.iter().enumerate() {
+        if let Some(first) = grammer.rules[..duplicate]
+            .iter()
+            .position(|other| other.left == rule.left && other.right == rule.right)
+        {
+            diagnostics.push(GrammerDiagnostic::DuplicateRule { first, duplicate });
+        }
+    }
+
+    //循環. A -> α B β で α と β がヌルになりうるとき A => B.
+    let nullable_set = generate_null_set(grammer);
+    let is_nullable = |symbol: &Symbol<NT, T>| match symbol {
+        Symbol::Term(_) => false,
+        Symbol::NonTerm(nt) => nullable_set.contains(nt),
+    };
+    let mut unit: BTreeMap<&NT, BTreeSet<&NT>> = BTreeMap::new();
+    for rule in &grammer.rules {
+        for (i, symbol) in rule.right.iter().enumerate() {
+            if let Symbol::NonTerm(nt) = symbol {
+                if rule.right[..i].iter().all(is_nullable)
+                    && rule.right[i + 1..].iter().all(is_nullable)
+                {
+                    unit.entry(&rule.left).or_default().insert(nt);
+                }
+            }
+        }
+    }
+    let reach = |from: &NT| -> BTreeSet<&NT> {
+        let mut visited = BTreeSet::new();
+        let mut stack: Vec<&NT> = unit.get(from).into_iter().flatten().copied().collect();
+        while let Some(nt) = stack.pop() {
+            if visited.insert(nt) {
+                stack.extend(unit.get(nt).into_iter().flatten().copied());
+            }
+        }
+        visited
+    };
+    let reaches: BTreeMap<&NT, BTreeSet<&NT>> = defined.iter().map(|nt| (*nt, reach(nt))).collect();
+    let mut reported: BTreeSet<&NT> = BTreeSet::new();
+    for nt in &defined {
+        if reported.contains(nt) || !reaches[nt].contains(nt) {
+            continue;
+        }
+        let component: Vec<NT> = reaches[nt]
+            .iter()
+            .filter(|other| reaches.get(*other).is_some_and(|set| set.contains(nt)))
+            .map(|other| {
+                reported.insert(*other);
+                (*other).clone()
+            })
+            .collect();
+        diagnostics.push(GrammerDiagnostic::Cycle(component));
+    }
+
+    diagnostics
+}
+
+#[cfg(test)]
+mod test {
+    use super::{validate_grammer, GrammerDiagnostic};
+    use crate::bnf::{Expr, Grammer, Symbol};
+
+    #[derive(Debug, PartialOrd, Ord, Clone, PartialEq, Eq)]
+    enum NT {
+        S,
+        A,
+        B,
+        C,
+        D,
+        Typo,
+    }
+
+    fn expr(left: NT, right: Vec<Symbol<NT, char>>) -> Expr<NT, char, NT, char> {
+        Expr {
+            left,
+            right,
+            reduce_action: None,
+        }
+    }
+
+    #[test]
+    fn test_validate_grammer() {
+        let grammer = Grammer {
+            rules: vec![
+                // S -> A | B Typo
+                expr(NT::S, vec![Symbol::NonTerm(NT::A)]),
+                expr(
+                    NT::S,
+                    vec![Symbol::NonTerm(NT::B), Symbol::NonTerm(NT::Typo)],
+                ),
+                // A -> a | B , B -> A | ε  (A => B => A)
+                expr(NT::A, vec![Symbol::Term('a')]),
+                expr(NT::A, vec![Symbol::NonTerm(NT::B)]),
+                expr(NT::B, vec![Symbol::NonTerm(NT::A)]),
+                expr(NT::B, vec![]),
+                // C -> c C は終端記号の列を導出できない.
+                expr(NT::C, vec![Symbol::Term('c'), Symbol::NonTerm(NT::C)]),
+                // D -> d は到達できない.
+                expr(NT::D, vec![Symbol::Term('d')]),
+                expr(NT::A, vec![Symbol::Term('a')]),
+            ],
+        };
+        assert_eq!(
+            validate_grammer(&grammer, &NT::S),
+            vec![
+                GrammerDiagnostic::Undefined {
+                    nonterm: NT::Typo,
+                    rules: vec![1]
+                },
+                GrammerDiagnostic::Unreachable(NT::C),
+                GrammerDiagnostic::Unreachable(NT::D),
+                GrammerDiagnostic::Unproductive(NT::C),
+                GrammerDiagnostic::DuplicateRule {
+                    first: 2,
+                    duplicate: 8
+                },
+                GrammerDiagnostic::Cycle(vec![NT::A, NT::B]),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_validate_clean_grammer() {
+        // S -> ( S ) | x
+        let grammer = Grammer {
+            rules: vec![
+                expr(
+                    NT::S,
+                    vec![Symbol::Term('('), Symbol::NonTerm(NT::S), Symbol::Term(')')],
+                ),
+                expr(NT::S, vec![Symbol::Term('x')]),
+            ],
+        };
+        assert!(validate_grammer(&grammer, &NT::S).is_empty());
+        assert_eq!(
+            validate_grammer(&grammer, &NT::A),
+            vec![
+                GrammerDiagnostic::Undefined {
+                    nonterm: NT::A,
+                    rules: vec![]
+                },
+                GrammerDiagnostic::Unreachable(NT::S),
+            ]
+        );
+    }
+}